@@ -8,7 +8,7 @@ use mimic_common::{
 use mimic_frontend::{
     cameras::camera::Camera,
     main_loop::{Application, MainLoopBuilder},
-    render_commands::RenderCommands,
+    render_commands::{CullMode, DrawOptions, PipelineDescriptor, RenderCommands},
 };
 use nalgebra_glm as glm;
 use std::{io::Write, path::PathBuf, rc::Rc};
@@ -22,6 +22,7 @@ struct Material {
     model: Rc<PathBuf>,
     vertex_shader: Rc<PathBuf>,
     frag_shader: Rc<PathBuf>,
+    pipeline: PipelineDescriptor,
 }
 
 struct GameObject {
@@ -77,6 +78,11 @@ impl Scene {
                         .resolve_resource("res/shaders/spv/quad_textured.frag.spv")
                         .unwrap(),
                 ),
+                // the quad is flat so it should be visible from both sides
+                pipeline: PipelineDescriptor {
+                    cull_mode: CullMode::None,
+                    ..PipelineDescriptor::default()
+                },
             };
             let quad_go = GameObject {
                 material,
@@ -85,6 +91,29 @@ impl Scene {
             };
             self.game_objects.push(quad_go);
         }
+        {
+            let material = Material {
+                texture_file: Rc::new(config.resolve_resource("res/textures/texture.jpg").unwrap()),
+                model: Rc::new(config.resolve_resource("res/models/cube.obj").unwrap()),
+                vertex_shader: Rc::new(
+                    config
+                        .resolve_resource("res/shaders/spv/cube.vert.spv")
+                        .unwrap(),
+                ),
+                frag_shader: Rc::new(
+                    config
+                        .resolve_resource("res/shaders/spv/cube.frag.spv")
+                        .unwrap(),
+                ),
+                pipeline: PipelineDescriptor::default(),
+            };
+            let cube_go = GameObject {
+                material,
+                position: glm::vec3(1.5, -0.5, -2.),
+                rotation: glm::rotation(std::f32::consts::FRAC_PI_4, &glm::Vec3::y_axis()),
+            };
+            self.game_objects.push(cube_go);
+        }
     }
 }
 
@@ -120,8 +149,9 @@ impl Application for Demo {
                         &go.material.frag_shader,
                         Box::new(DemoUniformSpec::new(
                             self.scene_manager.scenes[new_scene].camera.clone(),
-                            go.rotation,
+                            glm::translation(&go.position) * go.rotation,
                         )),
+                        &DrawOptions::default().with_pipeline_descriptor(go.material.pipeline),
                     );
                 }
                 self.current_scene = Some(new_scene);
@@ -1,8 +1,12 @@
 pub use mimic_common::{
     apptime::AppTime,
     config::MimicConfig,
-    texture::{FilesystemTextureSource, TextureSource},
-    uniforms::{copy_uniform_to_memory, ForceAlignWrapper, UniformBufferObject, UniformSpec},
+    cubemap::CubemapFiles,
+    texture::{ColorSpace, FilesystemTextureSource, PixelFormat, TextureSource},
+    uniforms::{
+        copy_push_constants_to_slice, copy_uniform_to_memory, ForceAlignWrapper,
+        UniformBufferObject, UniformSpec, BINDLESS_TEXTURE_ID_OFFSET, MAX_PUSH_CONSTANTS_SIZE,
+    },
 };
 pub use mimic_frontend::{
     cameras::camera::Camera,
@@ -28,6 +28,11 @@ impl MimicConfig {
         })
     }
 
+    /// The folder which resources are resolved against. Files that the engine writes, like caches, are stored here too.
+    pub fn get_resource_base_folder(&self) -> &Path {
+        &self.resource_base_folder
+    }
+
     pub fn resolve_resource<P>(&self, resource_file_name: P) -> Result<PathBuf>
     where
         P: AsRef<Path>,
@@ -0,0 +1,234 @@
+use exr::prelude::f16;
+use std::{
+    f32::consts::PI,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    result::{MimicCommonError, Result},
+    texture::{ColorSpace, FilesystemTextureSource, PixelFormat, TextureSource},
+};
+//////////////////////// Enums ///////////////////////
+/// The image files that a cubemap is loaded from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CubemapFiles {
+    /// one square image per face in the order +x, -x, +y, -y, +z, -z
+    Faces([PathBuf; 6]),
+    /// a single image with a 2:1 equirectangular (latitude/longitude) projection of the whole sphere
+    Equirectangular(PathBuf),
+}
+//////////////////////// Structs ///////////////////////
+/// The six square faces of a cube texture, in the order +x, -x, +y, -y, +z, -z.
+pub struct CubemapSource {
+    size: u32,
+    pixels: Vec<u8>,
+    pixel_format: PixelFormat,
+    color_space: ColorSpace,
+}
+//////////////////////// Impls ///////////////////////
+impl CubemapSource {
+    pub fn new(files: &CubemapFiles, color_space: ColorSpace) -> Result<Self> {
+        match files {
+            CubemapFiles::Faces(faces) => Self::from_faces(faces, color_space),
+            CubemapFiles::Equirectangular(path) => Self::from_equirectangular(path, color_space),
+        }
+    }
+
+    /// All faces must be square and have the same size and pixel format.
+    pub fn from_faces(faces: &[PathBuf; 6], color_space: ColorSpace) -> Result<Self> {
+        let sources = faces
+            .iter()
+            .map(|face| {
+                FilesystemTextureSource::with_color_space(&Rc::new(face.clone()), color_space)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let first = &sources[0];
+        let size = first.get_width();
+        let pixel_format = first.get_pixel_format();
+        let mut pixels = Vec::with_capacity(first.get_image_size() as usize * 6);
+        for (face, source) in faces.iter().zip(&sources) {
+            if source.get_width() != source.get_height() {
+                return Err(invalid(face, "the face is not square"));
+            }
+            if source.get_width() != size {
+                return Err(invalid(face, "the faces have different sizes"));
+            }
+            if source.get_pixel_format() != pixel_format {
+                return Err(invalid(face, "the faces have different pixel formats"));
+            }
+            pixels.extend_from_slice(source.get_pixels());
+        }
+
+        Ok(CubemapSource {
+            size,
+            pixels,
+            pixel_format,
+            color_space: first.get_color_space(),
+        })
+    }
+
+    /// Projects the panorama onto six faces that are a quarter of its width wide.
+    pub fn from_equirectangular(path: &Path, color_space: ColorSpace) -> Result<Self> {
+        let source =
+            FilesystemTextureSource::with_color_space(&Rc::new(path.to_path_buf()), color_space)?;
+        let size = source.get_width() / 4;
+        if size == 0 || source.get_height() == 0 {
+            return Err(invalid(path, "the image is too small"));
+        }
+
+        let pixel_format = source.get_pixel_format();
+        let bytes_per_pixel = pixel_format.get_bytes_per_pixel() as usize;
+        let face_size = size as usize * size as usize * bytes_per_pixel;
+        let mut pixels = vec![0; face_size * 6];
+        for (face, face_pixels) in pixels.chunks_exact_mut(face_size).enumerate() {
+            for y in 0..size {
+                for x in 0..size {
+                    // the texel center in [-1, 1]
+                    let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                    let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+                    let direction = get_face_direction(face, s, t);
+                    let length = direction.iter().map(|c| c * c).sum::<f32>().sqrt();
+                    let [dx, dy, dz] = [
+                        direction[0] / length,
+                        direction[1] / length,
+                        direction[2] / length,
+                    ];
+                    let u = dz.atan2(dx) / (2.0 * PI) + 0.5;
+                    let v = dy.clamp(-1.0, 1.0).acos() / PI;
+                    let texel = sample_bilinear(&source, u, v);
+
+                    let offset = (y as usize * size as usize + x as usize) * bytes_per_pixel;
+                    write_texel(
+                        pixel_format,
+                        &texel,
+                        &mut face_pixels[offset..offset + bytes_per_pixel],
+                    );
+                }
+            }
+        }
+
+        Ok(CubemapSource {
+            size,
+            pixels,
+            pixel_format,
+            color_space: source.get_color_space(),
+        })
+    }
+
+    /// The width and height of each face.
+    pub fn get_size(&self) -> u32 {
+        self.size
+    }
+
+    /// The pixels of all six faces one after another.
+    pub fn get_pixels(&self) -> &Vec<u8> {
+        &self.pixels
+    }
+
+    pub fn get_pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    pub fn get_color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+}
+//////////////////////// Fns ///////////////////////
+fn invalid(path: &Path, reason: &str) -> MimicCommonError {
+    MimicCommonError::CubemapInvalid(format!("{:?}: {}", path, reason))
+}
+
+/// The direction through the point (s, t) of a face, following the Vulkan cube face layout.
+fn get_face_direction(face: usize, s: f32, t: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -t, -s],
+        1 => [-1.0, -t, s],
+        2 => [s, 1.0, t],
+        3 => [s, -1.0, -t],
+        4 => [s, -t, 1.0],
+        _ => [-s, -t, -1.0],
+    }
+}
+
+/// Samples the texture at (u, v) in [0, 1], wrapping horizontally and clamping vertically.
+fn sample_bilinear(source: &FilesystemTextureSource, u: f32, v: f32) -> [f32; 4] {
+    let width = source.get_width() as i64;
+    let height = source.get_height() as i64;
+    let x = u * width as f32 - 0.5;
+    let y = v * height as f32 - 0.5;
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+
+    let texel_at = |x: i64, y: i64| {
+        read_texel(
+            source,
+            x.rem_euclid(width) as usize,
+            y.clamp(0, height - 1) as usize,
+        )
+    };
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let top_left = texel_at(x0, y0);
+    let top_right = texel_at(x0 + 1, y0);
+    let bottom_left = texel_at(x0, y0 + 1);
+    let bottom_right = texel_at(x0 + 1, y0 + 1);
+
+    let mut texel = [0.0; 4];
+    for (channel, value) in texel.iter_mut().enumerate() {
+        let top = top_left[channel] * (1.0 - fx) + top_right[channel] * fx;
+        let bottom = bottom_left[channel] * (1.0 - fx) + bottom_right[channel] * fx;
+        *value = top * (1.0 - fy) + bottom * fy;
+    }
+    texel
+}
+
+/// Reads the channels of a texel, channels that the format doesn't have are 0.
+fn read_texel(source: &FilesystemTextureSource, x: usize, y: usize) -> [f32; 4] {
+    let pixel_format = source.get_pixel_format();
+    let bytes_per_pixel = pixel_format.get_bytes_per_pixel() as usize;
+    let offset = (y * source.get_width() as usize + x) * bytes_per_pixel;
+    let bytes = &source.get_pixels()[offset..offset + bytes_per_pixel];
+
+    let mut texel = [0.0; 4];
+    match pixel_format {
+        PixelFormat::R8 | PixelFormat::Rg8 | PixelFormat::Rgba8 => {
+            for (value, byte) in texel.iter_mut().zip(bytes) {
+                *value = *byte as f32;
+            }
+        }
+        PixelFormat::Rgba16F => {
+            for (value, half) in texel.iter_mut().zip(bytes.chunks_exact(2)) {
+                *value = f16::from_ne_bytes([half[0], half[1]]).to_f32();
+            }
+        }
+        PixelFormat::Rgba32F => {
+            for (value, float) in texel.iter_mut().zip(bytes.chunks_exact(4)) {
+                *value = f32::from_ne_bytes([float[0], float[1], float[2], float[3]]);
+            }
+        }
+    }
+    texel
+}
+
+fn write_texel(pixel_format: PixelFormat, texel: &[f32; 4], bytes: &mut [u8]) {
+    match pixel_format {
+        PixelFormat::R8 | PixelFormat::Rg8 | PixelFormat::Rgba8 => {
+            for (byte, value) in bytes.iter_mut().zip(texel) {
+                *byte = value.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        PixelFormat::Rgba16F => {
+            for (half, value) in bytes.chunks_exact_mut(2).zip(texel) {
+                half.copy_from_slice(&f16::from_f32(*value).to_ne_bytes());
+            }
+        }
+        PixelFormat::Rgba32F => {
+            for (float, value) in bytes.chunks_exact_mut(4).zip(texel) {
+                float.copy_from_slice(&value.to_ne_bytes());
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 pub mod apptime;
 pub mod config;
+pub mod cubemap;
 pub mod macros;
 pub mod result;
 pub mod texture;
@@ -8,15 +8,22 @@ pub type Result<T> = std::result::Result<T, MimicCommonError>;
 //////////////////////// Enums ///////////////////////
 #[derive(Error, Debug)]
 pub enum MimicCommonError {
+    #[error("Cubemap is invalid: {0}")]
+    CubemapInvalid(String),
     #[error("Failed to get base directory from executable")]
     ExecutableBaseDirError,
     #[error(transparent)]
+    ExrLoadError(exr::error::Error),
+    #[error(transparent)]
     ImageLoadError(ImageError),
     #[error(transparent)]
     IoError(io::Error),
     #[error("Resource {0:?} failed to resolve")]
     ResourceFailedToResolve(OsString),
+    #[error("Texture of {0}x{1} pixels is too large")]
+    TextureTooLarge(u32, u32),
 }
 //////////////////////// Impls ///////////////////////
+propagate!(MimicCommonError, ExrLoadError as exr::error::Error, using_panic_feature);
 propagate!(MimicCommonError, IoError as io::Error, using_panic_feature);
 propagate!(MimicCommonError, ImageLoadError as ImageError, using_panic_feature);
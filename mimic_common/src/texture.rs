@@ -1,13 +1,39 @@
-use image::GenericImageView;
-use std::{path::PathBuf, rc::Rc};
+use exr::prelude::{f16, SampleType};
+use image::{codecs::hdr::HdrDecoder, ColorType, GenericImageView};
+use std::{fs::File, io::BufReader, path::Path, path::PathBuf, rc::Rc};
 
-use crate::result::Result;
+use crate::result::{MimicCommonError, Result};
+//////////////////////// Enums ///////////////////////
+/// The layout of the bytes returned by `TextureSource::get_pixels`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    R8,
+    Rg8,
+    #[default]
+    Rgba8,
+    /// four half floats per pixel
+    Rgba16F,
+    /// four floats per pixel
+    Rgba32F,
+}
+
+/// How the shaders read the 8 bit pixel formats. The float formats are always linear.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// for color textures, the pixels are converted from sRGB to linear when they are sampled
+    #[default]
+    Srgb,
+    /// for textures that hold data like normals, roughness or masks
+    Linear,
+}
 //////////////////////// Traits ///////////////////////
 pub trait TextureSource {
     fn get_pixels(&self) -> &Vec<u8>;
     fn get_width(&self) -> u32;
     fn get_height(&self) -> u32;
     fn get_image_size(&self) -> u32;
+    fn get_pixel_format(&self) -> PixelFormat;
+    fn get_color_space(&self) -> ColorSpace;
 }
 //////////////////////// Structs ///////////////////////
 #[derive(Default)]
@@ -17,24 +43,88 @@ pub struct FilesystemTextureSource {
     height: u32,
     image_size: u32,
     pixels: Vec<u8>,
+    pixel_format: PixelFormat,
+    color_space: ColorSpace,
+}
+
+/// A texture whose pixels are already in memory, for example because they were decoded from a model file.
+#[derive(Default)]
+pub struct MemoryTextureSource {
+    width: u32,
+    height: u32,
+    image_size: u32,
+    pixels: Vec<u8>,
+    pixel_format: PixelFormat,
+    color_space: ColorSpace,
+}
+
+/// The pixels of an exr file while they are being read.
+struct ExrPixels {
+    width: usize,
+    is_half_float: bool,
+    bytes: Vec<u8>,
 }
 //////////////////////// Impls ///////////////////////
+impl PixelFormat {
+    pub fn get_bytes_per_pixel(&self) -> u32 {
+        match self {
+            PixelFormat::R8 => 1,
+            PixelFormat::Rg8 => 2,
+            PixelFormat::Rgba8 => 4,
+            PixelFormat::Rgba16F => 8,
+            PixelFormat::Rgba32F => 16,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, PixelFormat::Rgba16F | PixelFormat::Rgba32F)
+    }
+}
+
 impl FilesystemTextureSource {
+    /// Loads the image as a color texture.
     pub fn new(path: &Rc<PathBuf>) -> Result<Self> {
-        let image = image::open(path.as_path())?;
-
-        let (width, height) = image.dimensions();
-        let image_size = width * height * 4;
+        Self::with_color_space(path, ColorSpace::Srgb)
+    }
 
-        let rgba_image = image.into_rgba8();
-        let pixels: &Vec<u8> = rgba_image.as_raw();
+    /// Linear textures keep the single and two channel formats of the image file as R8 and RG8.
+    /// .hdr and .exr files are loaded as linear float textures whatever the `color_space`.
+    pub fn with_color_space(path: &Rc<PathBuf>, color_space: ColorSpace) -> Result<Self> {
+        let (width, height, pixels, pixel_format, color_space) = if has_extension(path, "hdr") {
+            let (width, height, pixels) = load_hdr(path)?;
+            (
+                width,
+                height,
+                pixels,
+                PixelFormat::Rgba32F,
+                ColorSpace::Linear,
+            )
+        } else if has_extension(path, "exr") {
+            let (width, height, pixels, pixel_format) = load_exr(path)?;
+            (width, height, pixels, pixel_format, ColorSpace::Linear)
+        } else {
+            let image = image::open(path.as_path())?;
+            let (width, height) = image.dimensions();
+            let (pixels, pixel_format) = match (color_space, image.color()) {
+                (ColorSpace::Linear, ColorType::L8) | (ColorSpace::Linear, ColorType::L16) => {
+                    (image.into_luma8().into_raw(), PixelFormat::R8)
+                }
+                (ColorSpace::Linear, ColorType::La8) | (ColorSpace::Linear, ColorType::La16) => {
+                    (image.into_luma_alpha8().into_raw(), PixelFormat::Rg8)
+                }
+                _ => (image.into_rgba8().into_raw(), PixelFormat::Rgba8),
+            };
+            (width, height, pixels, pixel_format, color_space)
+        };
 
         Ok(FilesystemTextureSource {
             path: Rc::clone(path),
             width,
             height,
-            image_size,
-            pixels: pixels.clone(),
+            image_size: get_image_size(width, height, pixel_format)?,
+            pixels,
+            pixel_format,
+            color_space,
         })
     }
 }
@@ -52,4 +142,133 @@ impl TextureSource for FilesystemTextureSource {
     fn get_image_size(&self) -> u32 {
         self.image_size
     }
+    fn get_pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+    fn get_color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+}
+
+impl MemoryTextureSource {
+    /// A color texture with RGBA8 `pixels`.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Result<Self> {
+        Self::with_format(width, height, pixels, PixelFormat::Rgba8, ColorSpace::Srgb)
+    }
+
+    pub fn with_format(
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+        pixel_format: PixelFormat,
+        color_space: ColorSpace,
+    ) -> Result<Self> {
+        Ok(MemoryTextureSource {
+            width,
+            height,
+            image_size: get_image_size(width, height, pixel_format)?,
+            pixels,
+            pixel_format,
+            color_space,
+        })
+    }
+}
+
+impl TextureSource for MemoryTextureSource {
+    fn get_pixels(&self) -> &Vec<u8> {
+        &self.pixels
+    }
+    fn get_width(&self) -> u32 {
+        self.width
+    }
+    fn get_height(&self) -> u32 {
+        self.height
+    }
+    fn get_image_size(&self) -> u32 {
+        self.image_size
+    }
+    fn get_pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+    fn get_color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+}
+//////////////////////// Fns ///////////////////////
+fn has_extension(path: &Path, expected: &str) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => extension.eq_ignore_ascii_case(expected),
+        None => false,
+    }
+}
+
+/// Radiance .hdr files only have rgb, so the alpha is set to 1.
+/// The number of bytes of a `width` x `height` image, which has to fit into the u32 image size of a `TextureSource`.
+fn get_image_size(width: u32, height: u32, pixel_format: PixelFormat) -> Result<u32> {
+    width
+        .checked_mul(height)
+        .and_then(|pixel_count| pixel_count.checked_mul(pixel_format.get_bytes_per_pixel()))
+        .ok_or(MimicCommonError::TextureTooLarge(width, height))
+}
+
+fn load_hdr(path: &Path) -> Result<(u32, u32, Vec<u8>)> {
+    let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder
+        .read_image_hdr()?
+        .iter()
+        .flat_map(|pixel| {
+            let [r, g, b] = pixel.0;
+            [r, g, b, 1.0]
+        })
+        .flat_map(f32::to_ne_bytes)
+        .collect();
+    Ok((metadata.width, metadata.height, pixels))
+}
+
+/// Files that only have half float channels are loaded as RGBA16F, all others as RGBA32F.
+fn load_exr(path: &Path) -> Result<(u32, u32, Vec<u8>, PixelFormat)> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |size, (r, g, b, a): &exr::image::RgbaChannels| {
+            let is_half_float = [Some(r), Some(g), Some(b), a.as_ref()]
+                .iter()
+                .flatten()
+                .all(|channel| channel.sample_type == SampleType::F16);
+            let bytes_per_channel = if is_half_float { 2 } else { 4 };
+            ExrPixels {
+                width: size.width(),
+                is_half_float,
+                bytes: vec![0; size.area() * 4 * bytes_per_channel],
+            }
+        },
+        // missing alpha channels are read as 1
+        |pixels: &mut ExrPixels, position, (r, g, b, a): (f32, f32, f32, f32)| {
+            let first_channel = (position.y() * pixels.width + position.x()) * 4;
+            for (channel, value) in [r, g, b, a].iter().enumerate() {
+                if pixels.is_half_float {
+                    let offset = (first_channel + channel) * 2;
+                    pixels.bytes[offset..offset + 2]
+                        .copy_from_slice(&f16::from_f32(*value).to_ne_bytes());
+                } else {
+                    let offset = (first_channel + channel) * 4;
+                    pixels.bytes[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+                }
+            }
+        },
+    )?;
+
+    let size = image.layer_data.size;
+    let pixels = image.layer_data.channel_data.pixels;
+    let pixel_format = if pixels.is_half_float {
+        PixelFormat::Rgba16F
+    } else {
+        PixelFormat::Rgba32F
+    };
+    Ok((
+        size.width() as u32,
+        size.height() as u32,
+        pixels.bytes,
+        pixel_format,
+    ))
 }
@@ -1,10 +1,30 @@
 use nalgebra_glm::{self as glm, Mat4, Vec2, Vec3};
 
 use crate::apptime::AppTime;
+//////////////////////// Consts ///////////////////////
+/// Vulkan guarantees that at least 128 bytes of push constants are available on every device.
+pub const MAX_PUSH_CONSTANTS_SIZE: usize = 128;
+/// With bindless textures the index of the texture of each draw is pushed as a `uint` at this offset,
+/// so the push constants of a render command can be at most this large.
+pub const BINDLESS_TEXTURE_ID_OFFSET: usize = MAX_PUSH_CONSTANTS_SIZE - 4;
+/// The uniform data of every render command is bound as a range of this many bytes, so it can be at most this large.
+pub const MAX_UNIFORM_BUFFER_SIZE: usize = 1024;
 //////////////////////// Traits ///////////////////////
 pub trait UniformSpec {
     fn get_uniform_data(&self, input: UniformUpdateInput<'_>, memory_target_ptr: *mut core::ffi::c_void);
+    /// The size of the uniform data, which can be at most `MAX_UNIFORM_BUFFER_SIZE` bytes.
     fn uniform_buffer_size(&self) -> usize;
+
+    /// The size of the per-object data that is pushed to the shaders as push constants.
+    /// It can be at most `MAX_PUSH_CONSTANTS_SIZE` bytes. By default no push constants are used.
+    fn push_constants_size(&self) -> usize {
+        0
+    }
+
+    /// Writes the push constants into `data_target` which is `push_constants_size` bytes long.
+    /// Unlike uniform data, push constants are written into the command buffer while each draw is recorded,
+    /// so they don't need a buffer that is mapped every frame.
+    fn get_push_constants_data(&self, _input: UniformUpdateInput<'_>, _data_target: &mut [u8]) {}
 }
 //////////////////////// Structs ///////////////////////
 /// This struct contains information related to a uniform that we want to use in our shaders
@@ -28,6 +48,7 @@ pub struct UniformBufferObject {
     pub proj: Mat4,
 }
 
+#[derive(Clone, Copy)]
 pub struct UniformUpdateInput<'a> {
     pub swapchain_image_width: u32,
     pub swapchain_image_height: u32,
@@ -98,6 +119,18 @@ pub fn update_uniform_buffer(input: UniformUpdateInput, data_target_ptr: *mut co
     copy_uniform_to_memory(&ubo, data_target_ptr);
 }
 //////////////////////// Fns ///////////////////////
+pub fn copy_push_constants_to_slice<T>(src: &T, data_target: &mut [u8])
+where
+    T: Sized,
+{
+    let size = std::mem::size_of::<T>().min(data_target.len());
+    unsafe {
+        data_target
+            .as_mut_ptr()
+            .copy_from_nonoverlapping(src as *const T as *const u8, size);
+    }
+}
+
 pub fn copy_uniform_to_memory<T>(src: &T, memory_data_target_ptr: *mut core::ffi::c_void)
 where
     T: Sized,
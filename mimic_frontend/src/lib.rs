@@ -5,6 +5,7 @@ extern crate nalgebra_glm as glm;
 pub mod cameras;
 pub mod main_loop;
 pub mod render_commands;
+pub mod render_graph;
 pub mod result;
 pub mod uniform_specs;
 pub mod winit_window;
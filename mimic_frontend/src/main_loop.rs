@@ -1,11 +1,13 @@
 use crate::{
-    render_commands::{RenderCommand, RenderCommands},
-    result::Result,
+    render_commands::{ObjectHandle, RenderCommand, RenderCommands},
+    render_graph::RenderGraph,
+    result::{MimicError, Result},
     winit_window,
 };
 use log::{error, info};
 use mimic_common::{apptime::AppTime, config::MimicConfig};
-use mimic_vulkan_backend::backend::mimic_backend::VulkanApp;
+use mimic_vulkan_backend::backend::mimic_backend::{RenderCommandId, VulkanApp, VulkanAppOptions};
+use std::collections::HashMap;
 use winit::{
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
@@ -21,6 +23,16 @@ pub struct MainLoopBuilder {
     event_loop: Option<EventLoop<()>>,
     window: Option<winit::window::Window>,
     vulkan_app: Option<VulkanApp>,
+    vulkan_app_options: VulkanAppOptions,
+    /// the render command that is shown until the application submits its first object
+    default_render_command: Option<RenderCommandId>,
+}
+
+/// Keeps track of which vulkan render command draws which of the application's objects.
+#[derive(Default)]
+struct DrawnObjects {
+    render_command_ids: HashMap<ObjectHandle, RenderCommandId>,
+    default_render_command: Option<RenderCommandId>,
 }
 //////////////////////// Impls ///////////////////////
 impl MainLoopBuilder {
@@ -31,9 +43,26 @@ impl MainLoopBuilder {
             event_loop: None,
             window: None,
             vulkan_app: None,
+            vulkan_app_options: VulkanAppOptions::default(),
+            default_render_command: None,
         }
     }
 
+    /// Sets how many frames the CPU can prepare while the GPU is still drawing earlier ones. The default is 2.
+    /// This has to be called before the window is created using `with_window`.
+    pub fn with_frames_in_flight(&mut self, frames_in_flight: usize) -> &mut Self {
+        self.vulkan_app_options.frames_in_flight = frames_in_flight;
+        self
+    }
+
+    /// Adds the textures of all render commands to one array that shaders can index with a per-draw texture id.
+    /// See `bindless_triangle.frag` for how a shader reads it. This needs a vulkan 1.2 device with descriptor indexing.
+    /// This has to be called before the window is created using `with_window`.
+    pub fn with_bindless_textures(&mut self, enabled: bool) -> &mut Self {
+        self.vulkan_app_options.bindless_textures = enabled;
+        self
+    }
+
     pub fn with_window(
         &mut self,
         window_title: &str,
@@ -59,13 +88,26 @@ impl MainLoopBuilder {
             &window_surface,
             &window_size,
             mimic_config,
+            &self.vulkan_app_options,
         )?;
-        vulkan_app.create_default_render_command()?;
+        self.default_render_command = Some(vulkan_app.create_default_render_command()?);
         self.vulkan_app = Some(vulkan_app);
 
         Ok(self)
     }
 
+    /// Replaces the default rendering of the window with the passes of the `render_graph`.
+    /// This has to be called after the window was created using `with_window`.
+    pub fn with_render_graph(&mut self, render_graph: RenderGraph) -> Result<&mut Self> {
+        let plan = render_graph.compile()?;
+        let vulkan_app = self
+            .vulkan_app
+            .as_mut()
+            .ok_or_else(|| MimicError::RenderGraphInvalid(String::from("no window was created")))?;
+        vulkan_app.set_render_graph(plan)?;
+        Ok(self)
+    }
+
     /// Initialize a window with the given `window_tile` and the provided `window_width` and `window_height`.
     /// The provided `event_loop` is used to detect and react to window events.
     fn init_window(
@@ -88,6 +130,10 @@ impl MainLoopBuilder {
         let event_loop = self.event_loop.take().unwrap();
         let winit_window = self.window.take().unwrap();
         let mut vulkan_app = self.vulkan_app.take().unwrap();
+        let mut drawn_objects = DrawnObjects {
+            default_render_command: self.default_render_command.take(),
+            ..Default::default()
+        };
 
         let mut apptime = AppTime::new();
         event_loop.run(move |event, _, control_flow| {
@@ -105,6 +151,7 @@ impl MainLoopBuilder {
                         &mut apptime,
                         &mut application,
                         &mut vulkan_app,
+                        &mut drawn_objects,
                         &winit_window,
                     );
                 }
@@ -136,6 +183,7 @@ impl MainLoopBuilder {
         apptime: &mut AppTime,
         application: &mut A,
         vulkan_app: &mut VulkanApp,
+        drawn_objects: &mut DrawnObjects,
         winit_window: &winit::window::Window,
     ) {
         let time_update_result = apptime.update();
@@ -148,21 +196,99 @@ impl MainLoopBuilder {
             for render_command in render_commands.command_queue.drain(..) {
                 match render_command {
                     RenderCommand::DrawObject {
+                        handle,
                         texture_file,
+                        texture_color_space,
                         model_file,
                         vertex_shader_file,
                         fragment_shader_file,
                         uniform_spec,
+                        pipeline_descriptor,
+                        vertex_layout,
+                        mesh_loading_flags,
+                        instances,
                     } => {
+                        // once the application draws its own objects the default render command is no longer needed
+                        if let Some(default_render_command) =
+                            drawn_objects.default_render_command.take()
+                        {
+                            if let Err(error) =
+                                vulkan_app.remove_render_command(default_render_command)
+                            {
+                                error!("Failed to remove default render command: {}", error);
+                            }
+                        }
                         let result = vulkan_app.create_render_command(
                             &texture_file,
+                            texture_color_space,
                             &model_file,
                             &vertex_shader_file,
                             &fragment_shader_file,
                             uniform_spec,
+                            &pipeline_descriptor,
+                            &vertex_layout,
+                            mesh_loading_flags,
+                            instances.as_deref(),
                         );
-                        if let Err(error) = result {
-                            error!("Failed draw object operation: {}", error);
+                        match result {
+                            Ok(render_command_id) => {
+                                drawn_objects
+                                    .render_command_ids
+                                    .insert(handle, render_command_id);
+                            }
+                            Err(error) => {
+                                error!("Failed draw object operation: {}", error);
+                                Self::exit(control_flow);
+                            }
+                        }
+                    }
+                    RenderCommand::RemoveObject { handle } => {
+                        if let Some(render_command_id) =
+                            drawn_objects.render_command_ids.remove(&handle)
+                        {
+                            if let Err(error) = vulkan_app.remove_render_command(render_command_id)
+                            {
+                                error!("Failed remove object operation: {}", error);
+                                Self::exit(control_flow);
+                            }
+                        } else {
+                            error!("Object {:?} cannot be removed since it isn't drawn", handle);
+                        }
+                    }
+                    RenderCommand::SetPartVisibility {
+                        handle,
+                        part_name,
+                        visible,
+                    } => {
+                        if let Some(&render_command_id) =
+                            drawn_objects.render_command_ids.get(&handle)
+                        {
+                            if let Err(error) = vulkan_app.set_part_visibility(
+                                render_command_id,
+                                &part_name,
+                                visible,
+                            ) {
+                                error!("Failed set part visibility operation: {}", error);
+                            }
+                        } else {
+                            error!(
+                                "Object {:?} cannot show or hide parts since it isn't drawn",
+                                handle
+                            );
+                        }
+                    }
+                    RenderCommand::SetSkybox {
+                        cubemap_files,
+                        uniform_spec,
+                    } => {
+                        if let Err(error) = vulkan_app.set_skybox(&cubemap_files, uniform_spec) {
+                            error!("Failed set skybox operation: {}", error);
+                            Self::exit(control_flow);
+                        }
+                    }
+                    RenderCommand::RemoveSkybox => {
+                        if let Err(error) = vulkan_app.remove_skybox() {
+                            error!("Failed remove skybox operation: {}", error);
                             Self::exit(control_flow);
                         }
                     }
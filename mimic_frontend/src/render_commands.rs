@@ -1,23 +1,119 @@
-use mimic_common::uniforms::UniformSpec;
-use std::{path::PathBuf, rc::Rc};
+pub use mimic_vulkan_backend::{
+    graphics_pipeline::pipeline_descriptor::{
+        BlendMode, CullMode, DepthTest, FrontFace, PipelineDescriptor, PolygonMode,
+        PrimitiveTopology,
+    },
+    models::{
+        instance::InstanceData,
+        textured_model::MeshLoadingFlags,
+        vertex::{VertexAttribute, VertexLayout},
+    },
+};
+
+use mimic_common::{cubemap::CubemapFiles, texture::ColorSpace, uniforms::UniformSpec};
+use std::{
+    path::PathBuf,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+//////////////////////// Statics ///////////////////////
+static NEXT_OBJECT_HANDLE: AtomicUsize = AtomicUsize::new(0);
 //////////////////////// Enums ///////////////////////
 pub enum RenderCommand {
     DrawObject {
+        handle: ObjectHandle,
         texture_file: Rc<PathBuf>,
+        texture_color_space: ColorSpace,
         model_file: Rc<PathBuf>,
         vertex_shader_file: Rc<PathBuf>,
         fragment_shader_file: Rc<PathBuf>,
         uniform_spec: Box<dyn UniformSpec>,
+        pipeline_descriptor: PipelineDescriptor,
+        vertex_layout: VertexLayout,
+        mesh_loading_flags: MeshLoadingFlags,
+        /// `None` if the object is not instanced
+        instances: Option<Vec<InstanceData>>,
+    },
+    RemoveObject {
+        handle: ObjectHandle,
+    },
+    SetPartVisibility {
+        handle: ObjectHandle,
+        part_name: String,
+        visible: bool,
+    },
+    SetSkybox {
+        cubemap_files: CubemapFiles,
+        uniform_spec: Box<dyn UniformSpec>,
     },
+    RemoveSkybox,
 }
 //////////////////////// Structs ///////////////////////
+/// A handle to an object that was submitted for drawing.
+/// The object keeps being drawn every frame until it is removed using this handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectHandle(usize);
+
+/// The settings of an object drawn by `RenderCommands::draw_textured_model`.
+/// The default draws a single copy of the model with the default pipeline, vertex layout and an sRGB texture.
+#[derive(Clone, Debug, Default)]
+pub struct DrawOptions {
+    /// controls culling, blending, depth testing and how the vertices are rasterized
+    pub pipeline_descriptor: PipelineDescriptor,
+    /// textures that hold data like normals or roughness have to be `ColorSpace::Linear`
+    pub texture_color_space: ColorSpace,
+    /// the vertex attributes which the vertex shader reads from locations 0, 1, ... followed by the instance attributes.
+    /// Normals and tangents are generated if the model file doesn't have them
+    pub vertex_layout: VertexLayout,
+    /// how the model file is converted, for example to turn a z up model into a y up model or to shade it with flat normals.
+    /// `None` uses `MeshLoadingFlags::default_for_file`
+    pub mesh_loading_flags: Option<MeshLoadingFlags>,
+    /// one copy of the model is drawn for each instance using a single draw call. `None` if the object is not instanced
+    pub instances: Option<Vec<InstanceData>>,
+}
+
 #[derive(Default)]
 pub struct RenderCommands {
     pub request_redraw: bool,
     pub command_queue: Vec<RenderCommand>,
 }
 //////////////////////// Impls ///////////////////////
+impl ObjectHandle {
+    fn next() -> Self {
+        Self(NEXT_OBJECT_HANDLE.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl DrawOptions {
+    pub fn with_pipeline_descriptor(mut self, pipeline_descriptor: PipelineDescriptor) -> Self {
+        self.pipeline_descriptor = pipeline_descriptor;
+        self
+    }
+
+    pub fn with_texture_color_space(mut self, texture_color_space: ColorSpace) -> Self {
+        self.texture_color_space = texture_color_space;
+        self
+    }
+
+    pub fn with_vertex_layout(mut self, vertex_layout: VertexLayout) -> Self {
+        self.vertex_layout = vertex_layout;
+        self
+    }
+
+    pub fn with_mesh_loading_flags(mut self, mesh_loading_flags: MeshLoadingFlags) -> Self {
+        self.mesh_loading_flags = Some(mesh_loading_flags);
+        self
+    }
+
+    pub fn with_instances(mut self, instances: &[InstanceData]) -> Self {
+        self.instances = Some(instances.to_vec());
+        self
+    }
+}
+
 impl RenderCommands {
+    /// Draws the model with the texture every frame, until it is removed using the returned handle.
+    /// The `options` control the pipeline, the vertex layout, how the files are loaded and whether the model is instanced.
     pub fn draw_textured_model(
         &mut self,
         texture_file: &Rc<PathBuf>,
@@ -25,13 +121,52 @@ impl RenderCommands {
         vertex_shader_file: &Rc<PathBuf>,
         fragment_shader_file: &Rc<PathBuf>,
         uniform_spec: Box<dyn UniformSpec>,
-    ) {
+        options: &DrawOptions,
+    ) -> ObjectHandle {
+        let handle = ObjectHandle::next();
         self.command_queue.push(RenderCommand::DrawObject {
+            handle,
             texture_file: Rc::clone(texture_file),
+            texture_color_space: options.texture_color_space,
             model_file: Rc::clone(model_file),
             vertex_shader_file: Rc::clone(vertex_shader_file),
             fragment_shader_file: Rc::clone(fragment_shader_file),
             uniform_spec,
+            pipeline_descriptor: options.pipeline_descriptor,
+            vertex_layout: options.vertex_layout.clone(),
+            mesh_loading_flags: options
+                .mesh_loading_flags
+                .unwrap_or_else(|| MeshLoadingFlags::default_for_file(model_file)),
+            instances: options.instances.clone(),
         });
+        handle
+    }
+
+    pub fn remove_object(&mut self, handle: ObjectHandle) {
+        self.command_queue
+            .push(RenderCommand::RemoveObject { handle });
+    }
+
+    /// Shows or hides a part of the model of a drawn object.
+    /// The parts are the objects and groups of an obj file or the nodes of a gltf file.
+    pub fn set_part_visible(&mut self, handle: ObjectHandle, part_name: &str, visible: bool) {
+        self.command_queue.push(RenderCommand::SetPartVisibility {
+            handle,
+            part_name: part_name.to_string(),
+            visible,
+        });
+    }
+
+    /// Draws the cubemap behind all objects, replacing the previous skybox.
+    /// Only the rotation of the view matrix written by the `uniform_spec` is applied to the sky.
+    pub fn set_skybox(&mut self, cubemap_files: &CubemapFiles, uniform_spec: Box<dyn UniformSpec>) {
+        self.command_queue.push(RenderCommand::SetSkybox {
+            cubemap_files: cubemap_files.clone(),
+            uniform_spec,
+        });
+    }
+
+    pub fn remove_skybox(&mut self) {
+        self.command_queue.push(RenderCommand::RemoveSkybox);
     }
 }
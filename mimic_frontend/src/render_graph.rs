@@ -0,0 +1,216 @@
+pub mod compiler;
+
+pub use mimic_vulkan_backend::drawing::render_graph::{
+    AttachmentLoad, BufferAccess, GraphImageFormat, ImageAccess, PassRecorder, PassRecordingContext,
+};
+
+use crate::result::Result;
+use mimic_vulkan_backend::drawing::render_graph::RenderGraphPlan;
+use std::rc::Rc;
+//////////////////////// Enums ///////////////////////
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PassKind {
+    /// draws the render commands, see `RenderGraph::add_scene_pass`
+    Scene,
+    /// renders into the color and depth attachments that were declared with `PassBuilder::write_color`/`write_depth`
+    Attachments,
+    /// has no attachments and records its commands outside of a render pass
+    NoAttachments,
+}
+//////////////////////// Structs ///////////////////////
+/// A handle to an image declared in a `RenderGraph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ImageHandle(usize);
+
+/// A handle to a buffer declared in a `RenderGraph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BufferHandle(usize);
+
+/// A description of the passes which render a frame and of the images and buffers that they read and write.
+/// The passes only declare what they access. When the graph is compiled the passes are ordered by their dependencies,
+/// passes that don't contribute to the render target are culled, the declared images and buffers are allocated
+/// (sharing memory when their lifetimes don't overlap) and the barriers between the passes are inserted.
+pub struct RenderGraph {
+    images: Vec<ImageDeclaration>,
+    buffers: Vec<BufferDeclaration>,
+    passes: Vec<PassDeclaration>,
+}
+
+/// Used to declare what a pass reads and writes and how it records its commands.
+pub struct PassBuilder {
+    pass: PassDeclaration,
+}
+
+struct ImageDeclaration {
+    name: String,
+    /// `None` for the render target which is not allocated by the graph
+    format: Option<GraphImageFormat>,
+}
+
+struct BufferDeclaration {
+    name: String,
+    size: u64,
+}
+
+struct PassDeclaration {
+    name: String,
+    kind: PassKind,
+    image_accesses: Vec<(ImageHandle, ImageAccess)>,
+    buffer_accesses: Vec<(BufferHandle, BufferAccess)>,
+    color_attachments: Vec<(ImageHandle, AttachmentLoad)>,
+    depth_attachment: Option<(ImageHandle, AttachmentLoad)>,
+    recorder: Option<Rc<dyn PassRecorder>>,
+    /// passes with side effects are never culled even if nothing reads what they write
+    has_side_effects: bool,
+}
+//////////////////////// Impls ///////////////////////
+impl RenderGraph {
+    const RENDER_TARGET: ImageHandle = ImageHandle(0);
+
+    pub fn new() -> Self {
+        Self {
+            images: vec![ImageDeclaration {
+                name: String::from("render target"),
+                format: None,
+            }],
+            buffers: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    /// The image that the frame is presented from. The graph only keeps passes that (indirectly) write to it.
+    pub fn render_target(&self) -> ImageHandle {
+        Self::RENDER_TARGET
+    }
+
+    /// Declares an image with the size of the render target which only lives for the duration of a frame.
+    pub fn create_image(&mut self, name: &str, format: GraphImageFormat) -> ImageHandle {
+        self.images.push(ImageDeclaration {
+            name: String::from(name),
+            format: Some(format),
+        });
+        ImageHandle(self.images.len() - 1)
+    }
+
+    /// Declares a buffer of `size` bytes which only lives for the duration of a frame.
+    pub fn create_buffer(&mut self, name: &str, size: u64) -> BufferHandle {
+        self.buffers.push(BufferDeclaration {
+            name: String::from(name),
+            size,
+        });
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    /// Adds the pass which draws all the render commands.
+    /// `output` has to be the render target or an image with the `GraphImageFormat::RenderTarget` format.
+    pub fn add_scene_pass(&mut self, name: &str, output: ImageHandle) {
+        let mut pass_builder = PassBuilder::new(name, PassKind::Scene);
+        pass_builder.write_color(output, AttachmentLoad::Clear);
+        self.passes.push(pass_builder.pass);
+    }
+
+    /// Adds a pass whose reads, writes and recording are declared using the `PassBuilder` passed to `setup`.
+    /// If the pass writes color or depth attachments then it is recorded inside of a render pass with those attachments.
+    pub fn add_pass<F>(&mut self, name: &str, setup: F)
+    where
+        F: FnOnce(&mut PassBuilder),
+    {
+        let mut pass_builder = PassBuilder::new(name, PassKind::NoAttachments);
+        setup(&mut pass_builder);
+        self.passes.push(pass_builder.pass);
+    }
+
+    /// Orders the passes, culls the unused ones and plans the resources and barriers needed to run the graph.
+    pub fn compile(self) -> Result<RenderGraphPlan> {
+        compiler::compile(self)
+    }
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PassBuilder {
+    fn new(name: &str, kind: PassKind) -> Self {
+        Self {
+            pass: PassDeclaration {
+                name: String::from(name),
+                kind,
+                image_accesses: Vec::new(),
+                buffer_accesses: Vec::new(),
+                color_attachments: Vec::new(),
+                depth_attachment: None,
+                recorder: None,
+                has_side_effects: false,
+            },
+        }
+    }
+
+    /// Renders into `image` as a color attachment. The attachments are bound in the order they are declared.
+    /// With `AttachmentLoad::Load` the pass draws over what earlier passes rendered into the image.
+    pub fn write_color(&mut self, image: ImageHandle, load: AttachmentLoad) -> &mut Self {
+        if self.pass.kind == PassKind::NoAttachments {
+            self.pass.kind = PassKind::Attachments;
+        }
+        self.pass.color_attachments.push((image, load));
+        self.access_image(image, ImageAccess::ColorAttachment)
+    }
+
+    /// Uses `image` as the depth attachment. A pass can only have one depth attachment.
+    /// With `AttachmentLoad::Load` the pass tests against the depth that earlier passes wrote.
+    pub fn write_depth(&mut self, image: ImageHandle, load: AttachmentLoad) -> &mut Self {
+        if self.pass.kind == PassKind::NoAttachments {
+            self.pass.kind = PassKind::Attachments;
+        }
+        self.pass.depth_attachment = Some((image, load));
+        self.access_image(image, ImageAccess::DepthAttachment)
+    }
+
+    /// Samples `image` in a shader.
+    pub fn sample_image(&mut self, image: ImageHandle) -> &mut Self {
+        self.access_image(image, ImageAccess::Sampled)
+    }
+
+    /// Copies or blits from `image`.
+    pub fn copy_from_image(&mut self, image: ImageHandle) -> &mut Self {
+        self.access_image(image, ImageAccess::TransferSrc)
+    }
+
+    /// Copies or blits into `image`.
+    pub fn copy_to_image(&mut self, image: ImageHandle) -> &mut Self {
+        self.access_image(image, ImageAccess::TransferDst)
+    }
+
+    /// Reads or writes `buffer` in the way given by `access`.
+    pub fn access_buffer(&mut self, buffer: BufferHandle, access: BufferAccess) -> &mut Self {
+        self.pass.buffer_accesses.push((buffer, access));
+        self
+    }
+
+    /// Sets what records the commands of the pass.
+    pub fn record(&mut self, recorder: Rc<dyn PassRecorder>) -> &mut Self {
+        self.pass.recorder = Some(recorder);
+        self
+    }
+
+    /// Keeps the pass even if none of what it writes is used to produce the render target.
+    pub fn with_side_effects(&mut self) -> &mut Self {
+        self.pass.has_side_effects = true;
+        self
+    }
+
+    fn access_image(&mut self, image: ImageHandle, access: ImageAccess) -> &mut Self {
+        self.pass.image_accesses.push((image, access));
+        self
+    }
+}
+
+impl PassDeclaration {
+    fn writes_image(&self, image: ImageHandle) -> bool {
+        self.image_accesses
+            .iter()
+            .any(|(accessed, access)| *accessed == image && access.is_write())
+    }
+}
@@ -0,0 +1,696 @@
+use crate::{
+    render_graph::{BufferHandle, ImageHandle, PassDeclaration, PassKind, RenderGraph},
+    result::{MimicError, Result},
+};
+use mimic_vulkan_backend::drawing::render_graph::{
+    BufferAccess, BufferBarrier, GraphAttachment, GraphBuffer, GraphBufferDescription, GraphImage,
+    GraphImageFormat, ImageAccess, ImageBarrier, PassAttachments, PlannedPass, RenderGraphPlan,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap, HashMap},
+    hash::Hash,
+};
+//////////////////////// Enums ///////////////////////
+/// Images and buffers are tracked together when working out the dependencies between passes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Resource {
+    Image(ImageHandle),
+    Buffer(BufferHandle),
+}
+//////////////////////// Structs ///////////////////////
+/// The first and last position (in the ordered passes) at which a resource is used.
+#[derive(Clone, Copy)]
+struct Lifetime {
+    first_use: usize,
+    last_use: usize,
+}
+//////////////////////// Fns ///////////////////////
+pub fn compile(render_graph: RenderGraph) -> Result<RenderGraphPlan> {
+    validate(&render_graph)?;
+
+    let dependencies = find_dependencies(&render_graph);
+    let ordered_passes = order_passes(&render_graph, &dependencies)?;
+    let kept_passes = cull_passes(&render_graph, &dependencies, ordered_passes);
+    if !kept_passes
+        .iter()
+        .any(|&pass_idx| render_graph.passes[pass_idx].writes_image(RenderGraph::RENDER_TARGET))
+    {
+        return Err(MimicError::RenderGraphInvalid(String::from(
+            "no pass writes to the render target",
+        )));
+    }
+
+    let (transient_images, image_to_graph_image) =
+        allocate_transient_images(&render_graph, &kept_passes);
+    let (transient_buffers, buffer_to_graph_buffer) =
+        allocate_transient_buffers(&render_graph, &kept_passes);
+
+    let RenderGraph { passes, .. } = render_graph;
+    let mut passes: Vec<_> = passes.into_iter().map(Some).collect();
+    let mut image_states: HashMap<GraphImage, (ImageAccess, ImageHandle)> = HashMap::new();
+    let mut buffer_states: HashMap<GraphBuffer, (BufferAccess, BufferHandle)> = HashMap::new();
+    let mut planned_passes = Vec::with_capacity(kept_passes.len());
+
+    for pass_idx in kept_passes {
+        let pass = match passes[pass_idx].take() {
+            Some(pass) => pass,
+            None => continue,
+        };
+
+        let mut image_barriers = Vec::new();
+        for &(image, access) in unique_accesses(&pass.image_accesses).iter() {
+            let graph_image = image_to_graph_image[&image];
+            let previous_state = image_states.insert(graph_image, (access, image));
+            // the memory of the image held a different resource before, so its contents are meaningless
+            let discard_contents = previous_state.is_none_or(|(_, owner)| owner != image);
+            let needs_barrier = match previous_state {
+                None => true,
+                Some((previous_access, _)) => {
+                    discard_contents
+                        || previous_access != access
+                        || previous_access.is_write()
+                        || access.is_write()
+                }
+            };
+            if needs_barrier {
+                image_barriers.push(ImageBarrier {
+                    image: graph_image,
+                    from: previous_state.map(|(previous_access, _)| previous_access),
+                    to: access,
+                    discard_contents,
+                });
+            }
+        }
+
+        let mut buffer_barriers = Vec::new();
+        for &(buffer, access) in unique_accesses(&pass.buffer_accesses).iter() {
+            let graph_buffer = buffer_to_graph_buffer[&buffer];
+            // the first access to a buffer doesn't need to wait on anything
+            if let Some((previous_access, owner)) =
+                buffer_states.insert(graph_buffer, (access, buffer))
+            {
+                if owner != buffer || previous_access.is_write() || access.is_write() {
+                    buffer_barriers.push(BufferBarrier {
+                        buffer: graph_buffer,
+                        from: previous_access,
+                        to: access,
+                    });
+                }
+            }
+        }
+
+        let attachments = match pass.kind {
+            PassKind::Scene => PassAttachments::Scene {
+                resolve_target: image_to_graph_image[&pass.color_attachments[0].0],
+            },
+            PassKind::Attachments => PassAttachments::Custom {
+                color_attachments: pass
+                    .color_attachments
+                    .iter()
+                    .map(|(image, load)| GraphAttachment {
+                        image: image_to_graph_image[image],
+                        load: *load,
+                    })
+                    .collect(),
+                depth_attachment: pass.depth_attachment.map(|(image, load)| GraphAttachment {
+                    image: image_to_graph_image[&image],
+                    load,
+                }),
+            },
+            PassKind::NoAttachments => PassAttachments::None,
+        };
+
+        planned_passes.push(PlannedPass {
+            name: pass.name,
+            image_barriers,
+            buffer_barriers,
+            attachments,
+            recorder: pass.recorder,
+        });
+    }
+
+    // at the end of the frame the render target has to be ready to be presented
+    let final_barriers = image_states
+        .get(&GraphImage::RenderTarget)
+        .map(|&(previous_access, _)| ImageBarrier {
+            image: GraphImage::RenderTarget,
+            from: Some(previous_access),
+            to: ImageAccess::Final,
+            discard_contents: false,
+        })
+        .into_iter()
+        .collect();
+
+    Ok(RenderGraphPlan {
+        transient_images,
+        transient_buffers,
+        passes: planned_passes,
+        final_barriers,
+    })
+}
+
+fn validate(render_graph: &RenderGraph) -> Result<()> {
+    for buffer_declaration in render_graph.buffers.iter() {
+        if buffer_declaration.size == 0 {
+            return Err(MimicError::RenderGraphInvalid(format!(
+                "buffer \"{}\" is empty",
+                buffer_declaration.name
+            )));
+        }
+    }
+
+    for pass in render_graph.passes.iter() {
+        let invalid = |reason: String| {
+            Err(MimicError::RenderGraphInvalid(format!(
+                "pass \"{}\" {}",
+                pass.name, reason
+            )))
+        };
+
+        for &(image, access) in pass.image_accesses.iter() {
+            let image_declaration = match render_graph.images.get(image.0) {
+                Some(image_declaration) => image_declaration,
+                None => return invalid(format!("uses an undeclared image {:?}", image)),
+            };
+            // an image can only be in one layout at a time
+            if pass
+                .image_accesses
+                .iter()
+                .any(|&(other_image, other_access)| other_image == image && other_access != access)
+            {
+                return invalid(format!(
+                    "accesses image \"{}\" in more than one way",
+                    image_declaration.name
+                ));
+            }
+            if image == RenderGraph::RENDER_TARGET && access != ImageAccess::ColorAttachment {
+                return invalid(String::from(
+                    "can only write to the render target as a color attachment",
+                ));
+            }
+            if access == ImageAccess::DepthAttachment
+                && image_declaration.format != Some(GraphImageFormat::Depth)
+            {
+                return invalid(format!(
+                    "uses image \"{}\" without a depth format as a depth attachment",
+                    image_declaration.name
+                ));
+            }
+        }
+
+        for &(buffer, _) in pass.buffer_accesses.iter() {
+            if buffer.0 >= render_graph.buffers.len() {
+                return invalid(format!("uses an undeclared buffer {:?}", buffer));
+            }
+        }
+
+        if pass.kind == PassKind::Scene {
+            let (output, _) = pass.color_attachments[0];
+            let output_format = render_graph.images[output.0].format;
+            if output != RenderGraph::RENDER_TARGET
+                && output_format != Some(GraphImageFormat::RenderTarget)
+            {
+                return invalid(String::from(
+                    "can only draw the scene into an image with the render target format",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Finds the passes that each pass has to wait for.
+/// A pass which reads a resource depends on the pass which last wrote it before it was declared.
+/// If it was declared before any pass writes the resource, then it depends on the first pass that writes it.
+/// A pass which writes a resource depends on the previous writer and on all the readers of the previous contents.
+fn find_dependencies(render_graph: &RenderGraph) -> Vec<BTreeSet<usize>> {
+    let mut resource_accesses: HashMap<Resource, Vec<(usize, bool)>> = HashMap::new();
+    for (pass_idx, pass) in render_graph.passes.iter().enumerate() {
+        let image_accesses = pass
+            .image_accesses
+            .iter()
+            .map(|&(image, access)| (Resource::Image(image), access.is_write()));
+        let buffer_accesses = pass
+            .buffer_accesses
+            .iter()
+            .map(|&(buffer, access)| (Resource::Buffer(buffer), access.is_write()));
+        for (resource, is_write) in image_accesses.chain(buffer_accesses) {
+            let accesses = resource_accesses.entry(resource).or_default();
+            match accesses.last_mut() {
+                // a pass may access the same resource more than once, a write wins over a read
+                Some((last_pass_idx, last_is_write)) if *last_pass_idx == pass_idx => {
+                    *last_is_write |= is_write;
+                }
+                _ => accesses.push((pass_idx, is_write)),
+            }
+        }
+    }
+
+    let mut dependencies = vec![BTreeSet::new(); render_graph.passes.len()];
+    for accesses in resource_accesses.values() {
+        let first_writer = accesses
+            .iter()
+            .find(|(_, is_write)| *is_write)
+            .map(|(pass_idx, _)| *pass_idx);
+        let mut last_writer = None;
+        let mut readers_since_last_writer = Vec::new();
+
+        for &(pass_idx, is_write) in accesses.iter() {
+            if is_write {
+                if let Some(last_writer) = last_writer {
+                    dependencies[pass_idx].insert(last_writer);
+                    dependencies[pass_idx].extend(readers_since_last_writer.drain(..));
+                }
+                last_writer = Some(pass_idx);
+            } else {
+                match last_writer.or(first_writer) {
+                    Some(writer) if writer != pass_idx => {
+                        dependencies[pass_idx].insert(writer);
+                    }
+                    _ => {}
+                }
+                // readers declared before the first writer read what the first writer wrote,
+                // so they stay in the list until the second writer
+                readers_since_last_writer.push(pass_idx);
+            }
+        }
+    }
+    dependencies
+}
+
+/// Orders the passes so that every pass comes after the passes it depends on.
+/// When there is a choice, passes are kept in the order they were declared in.
+fn order_passes(
+    render_graph: &RenderGraph,
+    dependencies: &[BTreeSet<usize>],
+) -> Result<Vec<usize>> {
+    let pass_count = render_graph.passes.len();
+    let mut dependents = vec![Vec::new(); pass_count];
+    let mut unresolved_dependency_counts = vec![0; pass_count];
+    for (pass_idx, pass_dependencies) in dependencies.iter().enumerate() {
+        unresolved_dependency_counts[pass_idx] = pass_dependencies.len();
+        for &dependency in pass_dependencies.iter() {
+            dependents[dependency].push(pass_idx);
+        }
+    }
+
+    let mut ready_passes: BinaryHeap<Reverse<usize>> = unresolved_dependency_counts
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count == 0)
+        .map(|(pass_idx, _)| Reverse(pass_idx))
+        .collect();
+    let mut ordered_passes = Vec::with_capacity(pass_count);
+    while let Some(Reverse(pass_idx)) = ready_passes.pop() {
+        ordered_passes.push(pass_idx);
+        for &dependent in dependents[pass_idx].iter() {
+            unresolved_dependency_counts[dependent] -= 1;
+            if unresolved_dependency_counts[dependent] == 0 {
+                ready_passes.push(Reverse(dependent));
+            }
+        }
+    }
+
+    if ordered_passes.len() != pass_count {
+        let passes_in_cycle: Vec<_> = find_cycle(dependencies, &unresolved_dependency_counts)
+            .into_iter()
+            .map(|pass_idx| render_graph.passes[pass_idx].name.as_str())
+            .collect();
+        return Err(MimicError::RenderGraphCycle(passes_in_cycle.join(", ")));
+    }
+    Ok(ordered_passes)
+}
+
+/// Finds the passes of one cycle among the passes which still have `unresolved_dependency_counts` after ordering.
+/// Every such pass depends on another unresolved pass, so following those dependencies has to run into a cycle.
+/// Passes that merely depend on the cycle are left out. The passes are returned in the order they were added.
+fn find_cycle(
+    dependencies: &[BTreeSet<usize>],
+    unresolved_dependency_counts: &[usize],
+) -> Vec<usize> {
+    let is_unresolved = |pass_idx: usize| unresolved_dependency_counts[pass_idx] > 0;
+    let mut path = Vec::new();
+    let mut pass_idx = (0..dependencies.len()).find(|&pass_idx| is_unresolved(pass_idx));
+    while let Some(current_pass_idx) = pass_idx {
+        if let Some(cycle_start) = path.iter().position(|&visited| visited == current_pass_idx) {
+            let mut cycle = path.split_off(cycle_start);
+            cycle.sort_unstable();
+            return cycle;
+        }
+        path.push(current_pass_idx);
+        pass_idx = dependencies[current_pass_idx]
+            .iter()
+            .copied()
+            .find(|&dependency| is_unresolved(dependency));
+    }
+    // unreachable as long as every unresolved pass depends on another unresolved pass
+    path
+}
+
+/// Removes the passes which neither have side effects nor contribute to what ends up in the render target.
+fn cull_passes(
+    render_graph: &RenderGraph,
+    dependencies: &[BTreeSet<usize>],
+    ordered_passes: Vec<usize>,
+) -> Vec<usize> {
+    let mut is_needed = vec![false; render_graph.passes.len()];
+    let mut to_visit: Vec<usize> = render_graph
+        .passes
+        .iter()
+        .enumerate()
+        .filter(|(_, pass)| pass.has_side_effects || pass.writes_image(RenderGraph::RENDER_TARGET))
+        .map(|(pass_idx, _)| pass_idx)
+        .collect();
+    while let Some(pass_idx) = to_visit.pop() {
+        if !is_needed[pass_idx] {
+            is_needed[pass_idx] = true;
+            to_visit.extend(dependencies[pass_idx].iter());
+        }
+    }
+
+    ordered_passes
+        .into_iter()
+        .filter(|&pass_idx| is_needed[pass_idx])
+        .collect()
+}
+
+/// Finds where in the ordered `kept_passes` each resource is first and last used.
+fn find_lifetimes<R, F>(
+    render_graph: &RenderGraph,
+    kept_passes: &[usize],
+    get_resources: F,
+) -> Vec<(R, Lifetime)>
+where
+    R: Copy + Ord + Hash,
+    F: Fn(&PassDeclaration) -> Vec<R>,
+{
+    let mut lifetimes: HashMap<R, Lifetime> = HashMap::new();
+    for (position, &pass_idx) in kept_passes.iter().enumerate() {
+        for resource in get_resources(&render_graph.passes[pass_idx]) {
+            lifetimes
+                .entry(resource)
+                .and_modify(|lifetime| lifetime.last_use = position)
+                .or_insert(Lifetime {
+                    first_use: position,
+                    last_use: position,
+                });
+        }
+    }
+    let mut lifetimes: Vec<_> = lifetimes.into_iter().collect();
+    lifetimes.sort_by_key(|(resource, lifetime)| (lifetime.first_use, *resource));
+    lifetimes
+}
+
+/// Assigns every used image to a transient image of the backend.
+/// Images with the same format whose lifetimes don't overlap share the same transient image.
+fn allocate_transient_images(
+    render_graph: &RenderGraph,
+    kept_passes: &[usize],
+) -> (Vec<GraphImageFormat>, HashMap<ImageHandle, GraphImage>) {
+    let lifetimes = find_lifetimes(render_graph, kept_passes, |pass| {
+        pass.image_accesses
+            .iter()
+            .map(|(image, _)| *image)
+            .collect()
+    });
+
+    let mut transient_images: Vec<GraphImageFormat> = Vec::new();
+    let mut transient_image_last_uses: Vec<usize> = Vec::new();
+    let mut image_to_graph_image = HashMap::new();
+    for (image, lifetime) in lifetimes {
+        let format = match render_graph.images[image.0].format {
+            Some(format) => format,
+            None => {
+                image_to_graph_image.insert(image, GraphImage::RenderTarget);
+                continue;
+            }
+        };
+        let reusable_image = (0..transient_images.len()).find(|&transient_idx| {
+            transient_images[transient_idx] == format
+                && transient_image_last_uses[transient_idx] < lifetime.first_use
+        });
+        let transient_idx = match reusable_image {
+            Some(transient_idx) => transient_idx,
+            None => {
+                transient_images.push(format);
+                transient_image_last_uses.push(0);
+                transient_images.len() - 1
+            }
+        };
+        transient_image_last_uses[transient_idx] = lifetime.last_use;
+        image_to_graph_image.insert(image, GraphImage::Transient(transient_idx));
+    }
+    (transient_images, image_to_graph_image)
+}
+
+/// Assigns every used buffer to a transient buffer of the backend.
+/// Buffers with the same size whose lifetimes don't overlap share the same transient buffer.
+fn allocate_transient_buffers(
+    render_graph: &RenderGraph,
+    kept_passes: &[usize],
+) -> (
+    Vec<GraphBufferDescription>,
+    HashMap<BufferHandle, GraphBuffer>,
+) {
+    let lifetimes = find_lifetimes(render_graph, kept_passes, |pass| {
+        pass.buffer_accesses
+            .iter()
+            .map(|(buffer, _)| *buffer)
+            .collect()
+    });
+
+    let mut transient_buffers: Vec<GraphBufferDescription> = Vec::new();
+    let mut transient_buffer_last_uses: Vec<usize> = Vec::new();
+    let mut buffer_to_graph_buffer = HashMap::new();
+    for (buffer, lifetime) in lifetimes {
+        let size = render_graph.buffers[buffer.0].size;
+        let reusable_buffer = (0..transient_buffers.len()).find(|&transient_idx| {
+            transient_buffers[transient_idx].size == size
+                && transient_buffer_last_uses[transient_idx] < lifetime.first_use
+        });
+        let transient_idx = match reusable_buffer {
+            Some(transient_idx) => transient_idx,
+            None => {
+                transient_buffers.push(GraphBufferDescription { size });
+                transient_buffer_last_uses.push(0);
+                transient_buffers.len() - 1
+            }
+        };
+        transient_buffer_last_uses[transient_idx] = lifetime.last_use;
+        buffer_to_graph_buffer.insert(buffer, GraphBuffer(transient_idx));
+    }
+    (transient_buffers, buffer_to_graph_buffer)
+}
+
+/// The accesses of a pass without duplicates, in the order they were declared.
+fn unique_accesses<R, A>(accesses: &[(R, A)]) -> Vec<(R, A)>
+where
+    R: Copy + PartialEq,
+    A: Copy + PartialEq,
+{
+    let mut unique = Vec::with_capacity(accesses.len());
+    for access in accesses.iter() {
+        if !unique.contains(access) {
+            unique.push(*access);
+        }
+    }
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_graph::AttachmentLoad;
+
+    fn compile_order(render_graph: &RenderGraph) -> Result<Vec<usize>> {
+        order_passes(render_graph, &find_dependencies(render_graph))
+    }
+
+    fn kept_passes(render_graph: &RenderGraph) -> Vec<usize> {
+        let dependencies = find_dependencies(render_graph);
+        let ordered_passes = order_passes(render_graph, &dependencies).unwrap();
+        cull_passes(render_graph, &dependencies, ordered_passes)
+    }
+
+    #[test]
+    fn independent_passes_keep_their_declaration_order() {
+        let mut render_graph = RenderGraph::new();
+        for name in ["a", "b", "c"].iter() {
+            render_graph.add_pass(name, |_| {});
+        }
+
+        assert_eq!(compile_order(&render_graph).unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn passes_come_after_their_dependencies_and_ties_keep_declaration_order() {
+        let mut render_graph = RenderGraph::new();
+        let shadow_map = render_graph.create_image("shadow map", GraphImageFormat::Depth);
+        render_graph.add_pass("lighting", |pass| {
+            pass.sample_image(shadow_map);
+        });
+        render_graph.add_pass("unrelated", |_| {});
+        render_graph.add_pass("shadows", |pass| {
+            pass.write_depth(shadow_map, AttachmentLoad::Clear);
+        });
+
+        // lighting reads what shadows writes, unrelated and shadows are ready at the same time
+        assert_eq!(compile_order(&render_graph).unwrap(), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn writers_wait_for_the_readers_of_the_previous_contents() {
+        let mut render_graph = RenderGraph::new();
+        let image = render_graph.create_image("image", GraphImageFormat::Rgba8);
+        render_graph.add_pass("first write", |pass| {
+            pass.copy_to_image(image);
+        });
+        render_graph.add_pass("second write", |pass| {
+            pass.write_color(image, AttachmentLoad::Load);
+        });
+        render_graph.add_pass("read", |pass| {
+            pass.sample_image(image);
+        });
+
+        let dependencies = find_dependencies(&render_graph);
+        let dependencies: Vec<Vec<usize>> = dependencies
+            .iter()
+            .map(|pass_dependencies| pass_dependencies.iter().copied().collect())
+            .collect();
+        assert_eq!(dependencies, vec![vec![], vec![0], vec![1]]);
+        assert_eq!(compile_order(&render_graph).unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn passes_that_dont_reach_the_render_target_are_culled() {
+        let mut render_graph = RenderGraph::new();
+        let render_target = render_graph.render_target();
+        let scene = render_graph.create_image("scene", GraphImageFormat::RenderTarget);
+        let unused = render_graph.create_image("unused", GraphImageFormat::Rgba8);
+        let readback = render_graph.create_buffer("readback", 16);
+        render_graph.add_scene_pass("scene", scene);
+        render_graph.add_pass("unused", |pass| {
+            pass.sample_image(scene)
+                .write_color(unused, AttachmentLoad::Clear);
+        });
+        render_graph.add_pass("readback", |pass| {
+            pass.copy_from_image(scene)
+                .access_buffer(readback, BufferAccess::TransferDst)
+                .with_side_effects();
+        });
+        render_graph.add_pass("post process", |pass| {
+            pass.sample_image(scene)
+                .write_color(render_target, AttachmentLoad::Clear);
+        });
+
+        assert_eq!(kept_passes(&render_graph), vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn a_graph_without_a_pass_that_writes_the_render_target_is_invalid() {
+        let mut render_graph = RenderGraph::new();
+        let scene = render_graph.create_image("scene", GraphImageFormat::RenderTarget);
+        render_graph.add_scene_pass("scene", scene);
+
+        assert!(matches!(
+            compile(render_graph),
+            Err(MimicError::RenderGraphInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn transient_images_are_only_reused_when_their_lifetimes_dont_overlap() {
+        let mut render_graph = RenderGraph::new();
+        let first = render_graph.create_image("first", GraphImageFormat::Rgba8);
+        let overlapping = render_graph.create_image("overlapping", GraphImageFormat::Rgba8);
+        let after_first = render_graph.create_image("after first", GraphImageFormat::Rgba8);
+        let other_format = render_graph.create_image("other format", GraphImageFormat::Depth);
+        render_graph.add_pass("write first", |pass| {
+            pass.write_color(first, AttachmentLoad::Clear);
+        });
+        render_graph.add_pass("read first", |pass| {
+            pass.sample_image(first)
+                .write_color(overlapping, AttachmentLoad::Clear);
+        });
+        render_graph.add_pass("read overlapping", |pass| {
+            pass.sample_image(overlapping)
+                .write_color(after_first, AttachmentLoad::Clear)
+                .write_depth(other_format, AttachmentLoad::Clear);
+        });
+
+        let (transient_images, image_to_graph_image) =
+            allocate_transient_images(&render_graph, &[0, 1, 2]);
+        assert_eq!(
+            transient_images,
+            vec![
+                GraphImageFormat::Rgba8,
+                GraphImageFormat::Rgba8,
+                GraphImageFormat::Depth
+            ]
+        );
+        assert_eq!(image_to_graph_image[&first], GraphImage::Transient(0));
+        // the overlapping image is written while the first one is still read
+        assert_eq!(image_to_graph_image[&overlapping], GraphImage::Transient(1));
+        assert_eq!(image_to_graph_image[&after_first], GraphImage::Transient(0));
+        assert_eq!(
+            image_to_graph_image[&other_format],
+            GraphImage::Transient(2)
+        );
+    }
+
+    #[test]
+    fn transient_buffers_are_only_reused_when_their_lifetimes_dont_overlap() {
+        let mut render_graph = RenderGraph::new();
+        let first = render_graph.create_buffer("first", 64);
+        let overlapping = render_graph.create_buffer("overlapping", 64);
+        let after_first = render_graph.create_buffer("after first", 64);
+        let other_size = render_graph.create_buffer("other size", 128);
+        render_graph.add_pass("write first", |pass| {
+            pass.access_buffer(first, BufferAccess::TransferDst);
+        });
+        render_graph.add_pass("read first", |pass| {
+            pass.access_buffer(first, BufferAccess::TransferSrc)
+                .access_buffer(overlapping, BufferAccess::TransferDst);
+        });
+        render_graph.add_pass("read overlapping", |pass| {
+            pass.access_buffer(overlapping, BufferAccess::TransferSrc)
+                .access_buffer(after_first, BufferAccess::TransferDst)
+                .access_buffer(other_size, BufferAccess::TransferDst);
+        });
+
+        let (transient_buffers, buffer_to_graph_buffer) =
+            allocate_transient_buffers(&render_graph, &[0, 1, 2]);
+        let sizes: Vec<_> = transient_buffers.iter().map(|buffer| buffer.size).collect();
+        assert_eq!(sizes, vec![64, 64, 128]);
+        assert_eq!(buffer_to_graph_buffer[&first], GraphBuffer(0));
+        assert_eq!(buffer_to_graph_buffer[&overlapping], GraphBuffer(1));
+        assert_eq!(buffer_to_graph_buffer[&after_first], GraphBuffer(0));
+        assert_eq!(buffer_to_graph_buffer[&other_size], GraphBuffer(2));
+    }
+
+    #[test]
+    fn passes_that_depend_on_each_other_are_a_cycle() {
+        let mut render_graph = RenderGraph::new();
+        let render_target = render_graph.render_target();
+        let first = render_graph.create_image("first", GraphImageFormat::Rgba8);
+        let second = render_graph.create_image("second", GraphImageFormat::Rgba8);
+        render_graph.add_pass("a", |pass| {
+            pass.sample_image(first)
+                .write_color(second, AttachmentLoad::Clear);
+        });
+        render_graph.add_pass("b", |pass| {
+            pass.sample_image(second)
+                .write_color(first, AttachmentLoad::Clear);
+        });
+        render_graph.add_pass("output", |pass| {
+            pass.sample_image(first)
+                .write_color(render_target, AttachmentLoad::Clear);
+        });
+
+        match compile(render_graph) {
+            Err(MimicError::RenderGraphCycle(passes)) => assert_eq!(passes, "a, b"),
+            _ => panic!("expected a cycle error"),
+        }
+    }
+}
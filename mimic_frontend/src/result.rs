@@ -9,6 +9,10 @@ pub type Result<T> = std::result::Result<T, MimicError>;
 pub enum MimicError {
     #[error(transparent)]
     MimicCommonError(MimicCommonError),
+    #[error("Render graph has a cycle between the passes (or passes depending on them): {0}")]
+    RenderGraphCycle(String),
+    #[error("Invalid render graph: {0}")]
+    RenderGraphInvalid(String),
     #[error(transparent)]
     VulkanError(VulkanError),
     #[error(transparent)]
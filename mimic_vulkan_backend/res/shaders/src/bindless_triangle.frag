@@ -0,0 +1,21 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+// needed for the runtime sized array. The texture id is the same for the whole draw, so it is not indexed with nonuniformEXT
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+// every texture of the render commands, requires the bindless textures option of the app
+layout(set = 2, binding = 0) uniform sampler2D textures[];
+
+// the texture id of the draw is pushed into the last 4 bytes of the push constants
+layout(push_constant) uniform Draw {
+    layout(offset = 124) uint textureId;
+} draw;
+
+void main() {
+    outColor = texture(textures[draw.textureId], fragTexCoord);
+}
\ No newline at end of file
@@ -6,8 +6,15 @@ layout(location = 1) in vec2 fragTexCoord;
 
 layout(location = 0) out vec4 outColor;
 
-layout(binding = 1) uniform sampler2D texSampler;
+layout(set = 1, binding = 1) uniform sampler2D texSampler;
+
+// the material of the submesh, the w of diffuse holds the dissolve value
+layout(set = 1, binding = 2) uniform Material {
+    vec4 ambient;
+    vec4 diffuse;
+    vec4 specular;
+} material;
 
 void main() {
-    outColor = texture(texSampler, fragTexCoord);
+    outColor = texture(texSampler, fragTexCoord) * material.diffuse;
 }
\ No newline at end of file
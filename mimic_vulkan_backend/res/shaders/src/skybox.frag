@@ -0,0 +1,12 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 fragDirection;
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 1, binding = 1) uniform samplerCube skySampler;
+
+void main() {
+    outColor = texture(skySampler, fragDirection);
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(location = 0) in vec3 inPosition;
+
+layout(location = 0) out vec3 fragDirection;
+
+void main() {
+    // only the rotation of the camera is applied, so the sky stays centered around the camera
+    mat4 rotation = mat4(mat3(ubo.view));
+    vec4 position = ubo.proj * rotation * vec4(inPosition, 1.0);
+    // z = w puts the sky at the far plane behind all geometry
+    gl_Position = position.xyww;
+    fragDirection = inPosition;
+}
\ No newline at end of file
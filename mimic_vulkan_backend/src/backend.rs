@@ -1 +1,3 @@
+pub mod assets;
 pub mod mimic_backend;
+pub mod pipelines;
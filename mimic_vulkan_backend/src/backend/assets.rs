@@ -0,0 +1,452 @@
+use crate::{
+    buffers::{
+        index_buffer::IndexBuffer, material_buffer::MaterialBuffer,
+        memory::allocator::MemoryAllocator, vertex_buffer::VertexBuffer,
+    },
+    drawing::upload_context::UploadContext,
+    graphics_pipeline::{
+        shader_module::{create_shader_module, Shader},
+        shader_reflection::ShaderReflection,
+    },
+    models::{
+        material::MeshMaterial,
+        textured_model::{Mesh, MeshLoadingFlags, Submesh},
+        vertex::VertexLayout,
+    },
+    textures::{
+        compressed_texture::{self, CompressedTextureSource},
+        images::TextureImage,
+    },
+    util::result::Result,
+};
+use ash::vk;
+use log::{info, warn};
+use mimic_common::{
+    cubemap::{CubemapFiles, CubemapSource},
+    texture::{ColorSpace, FilesystemTextureSource, MemoryTextureSource},
+};
+use std::{collections::HashMap, hash::Hash, path::PathBuf, rc::Rc};
+//////////////////////// Structs ///////////////////////
+/// A reference counted handle to a texture loaded by the `AssetManager`.
+#[derive(Clone)]
+pub struct TextureHandle(Rc<TextureImage>);
+
+/// A reference counted handle to the vertex and index buffers of a mesh loaded by the `AssetManager`.
+#[derive(Clone)]
+pub struct MeshHandle(Rc<MeshBuffers>);
+
+/// A reference counted handle to a shader module loaded by the `AssetManager`.
+#[derive(Clone)]
+pub struct ShaderHandle(Rc<Shader>);
+
+pub struct MeshBuffers {
+    pub vertex_buffer: VertexBuffer,
+    pub index_buffer: IndexBuffer,
+    pub submeshes: Vec<Submesh>,
+    /// the materials of the mesh followed by the default material, which is used by submeshes without a material
+    pub materials: Vec<MeshMaterial>,
+    pub material_buffer: MaterialBuffer,
+    /// the textures of the images that were decoded from the model file
+    pub images: Vec<TextureHandle>,
+}
+
+/// Makes sure that every texture, cubemap, mesh and shader file is only loaded to the gpu once.
+/// The images that are decoded from a model file are shared by all meshes loaded from it.
+/// Loading a file that is already loaded hands out another handle to the same gpu resources.
+/// The gpu resources are freed by `collect_garbage` once the asset manager holds the last handle.
+#[derive(Default)]
+pub struct AssetManager {
+    textures: HashMap<(PathBuf, ColorSpace), Rc<TextureImage>>,
+    cubemaps: HashMap<(CubemapFiles, ColorSpace), Rc<TextureImage>>,
+    meshes: HashMap<(PathBuf, MeshLoadingFlags, VertexLayout), Rc<MeshBuffers>>,
+    /// the images decoded from model files by the model file and the index of the image in the mesh
+    mesh_images: HashMap<(PathBuf, usize), Rc<TextureImage>>,
+    shaders: HashMap<PathBuf, Rc<Shader>>,
+}
+//////////////////////// Impls ///////////////////////
+impl TextureHandle {
+    pub fn get_texture(&self) -> &TextureImage {
+        &self.0
+    }
+}
+
+impl MeshHandle {
+    pub fn get_vertex_buffer(&self) -> &VertexBuffer {
+        &self.0.vertex_buffer
+    }
+
+    pub fn get_index_buffer(&self) -> &IndexBuffer {
+        &self.0.index_buffer
+    }
+
+    pub fn get_submeshes(&self) -> &[Submesh] {
+        &self.0.submeshes
+    }
+
+    pub fn get_material(&self, submesh: &Submesh) -> &MeshMaterial {
+        &self.0.materials[self.get_material_idx(submesh)]
+    }
+
+    /// The texture of a `MaterialTexture::Image` of one of the materials.
+    pub fn get_image(&self, image: usize) -> &TextureHandle {
+        &self.0.images[image]
+    }
+
+    /// The slot of the material buffer which holds the uniform data of the material of the `submesh`.
+    pub fn get_material_descriptor_info(
+        &self,
+        submesh: &Submesh,
+    ) -> Result<vk::DescriptorBufferInfo> {
+        self.0
+            .material_buffer
+            .get_descriptor_info(self.get_material_idx(submesh))
+    }
+
+    fn get_material_idx(&self, submesh: &Submesh) -> usize {
+        submesh
+            .material
+            .unwrap_or_else(|| self.0.materials.len() - 1)
+    }
+}
+
+impl ShaderHandle {
+    pub fn get_shader_module(&self) -> vk::ShaderModule {
+        self.0.module
+    }
+
+    pub fn get_reflection(&self) -> &ShaderReflection {
+        &self.0.reflection
+    }
+}
+
+impl AssetManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The `color_space` decides how the 8 bit pixels of image files are sampled.
+    /// Compressed, .hdr and .exr textures are sampled in the color space of their format,
+    /// except for legacy .dds files which don't say if they are srgb.
+    pub fn load_texture(
+        &mut self,
+        texture_file: &Rc<PathBuf>,
+        color_space: ColorSpace,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        upload_context: &mut UploadContext,
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<TextureHandle> {
+        // the same file loaded in different color spaces ends up in images with different formats
+        let key = (texture_file.to_path_buf(), color_space);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(TextureHandle(Rc::clone(texture)));
+        }
+
+        let texture = if compressed_texture::is_compressed_texture_file(texture_file) {
+            TextureImage::from_compressed(
+                CompressedTextureSource::new(texture_file, color_space)?,
+                instance,
+                physical_device,
+                allocator,
+                logical_device,
+                upload_context,
+                physical_device_properties,
+            )?
+        } else {
+            TextureImage::new(
+                Box::new(FilesystemTextureSource::with_color_space(
+                    texture_file,
+                    color_space,
+                )?),
+                instance,
+                physical_device,
+                allocator,
+                logical_device,
+                upload_context,
+                physical_device_properties,
+            )?
+        };
+        let texture = Rc::new(texture);
+        self.textures.insert(key, Rc::clone(&texture));
+
+        Ok(TextureHandle(texture))
+    }
+
+    /// Cubemaps are loaded into cube images and have to be sampled with a `samplerCube`.
+    pub fn load_cubemap(
+        &mut self,
+        cubemap_files: &CubemapFiles,
+        color_space: ColorSpace,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        upload_context: &mut UploadContext,
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<TextureHandle> {
+        let key = (cubemap_files.clone(), color_space);
+        if let Some(cubemap) = self.cubemaps.get(&key) {
+            return Ok(TextureHandle(Rc::clone(cubemap)));
+        }
+
+        let cubemap = Rc::new(TextureImage::from_cubemap(
+            &CubemapSource::new(cubemap_files, color_space)?,
+            instance,
+            physical_device,
+            allocator,
+            logical_device,
+            upload_context,
+            physical_device_properties,
+        )?);
+        self.cubemaps.insert(key, Rc::clone(&cubemap));
+
+        Ok(TextureHandle(cubemap))
+    }
+
+    pub fn load_mesh(
+        &mut self,
+        model_file: &Rc<PathBuf>,
+        loading_flags: MeshLoadingFlags,
+        vertex_layout: &VertexLayout,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        upload_context: &mut UploadContext,
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<MeshHandle> {
+        // the same file loaded with different flags or vertex layouts ends up with different vertices
+        let key = (
+            model_file.to_path_buf(),
+            loading_flags,
+            vertex_layout.clone(),
+        );
+        if let Some(mesh) = self.meshes.get(&key) {
+            return Ok(MeshHandle(Rc::clone(mesh)));
+        }
+
+        // once the vertices and indices are on the gpu we don't need to keep the cpu copy of the mesh around
+        let mut model = Mesh::new(model_file.as_path(), loading_flags)?;
+        let vertices = model.build_vertices(vertex_layout);
+
+        let vertex_buffer = VertexBuffer::new(
+            &vertices,
+            vertex_layout,
+            allocator,
+            logical_device,
+            upload_context,
+        )?;
+
+        let index_buffer =
+            match IndexBuffer::new(&model.indices, allocator, logical_device, upload_context) {
+                Ok(index_buffer) => index_buffer,
+                Err(error) => {
+                    finish_recorded_uploads(upload_context, allocator, logical_device);
+                    unsafe { vertex_buffer.cleanup(allocator, logical_device) };
+                    return Err(error);
+                }
+            };
+
+        // the images which were already uploaded are freed by the garbage collection once their handles are dropped
+        let images = match self.load_mesh_images(
+            model_file,
+            model.images,
+            instance,
+            physical_device,
+            allocator,
+            logical_device,
+            upload_context,
+            physical_device_properties,
+        ) {
+            Ok(images) => images,
+            Err(error) => {
+                finish_recorded_uploads(upload_context, allocator, logical_device);
+                unsafe {
+                    index_buffer.cleanup(allocator, logical_device);
+                    vertex_buffer.cleanup(allocator, logical_device);
+                }
+                return Err(error);
+            }
+        };
+
+        let mut materials = model.materials;
+        materials.push(MeshMaterial::default());
+        let material_buffer = match MaterialBuffer::new(
+            &materials,
+            physical_device_properties,
+            allocator,
+            logical_device,
+            upload_context,
+        ) {
+            Ok(material_buffer) => material_buffer,
+            Err(error) => {
+                finish_recorded_uploads(upload_context, allocator, logical_device);
+                unsafe {
+                    index_buffer.cleanup(allocator, logical_device);
+                    vertex_buffer.cleanup(allocator, logical_device);
+                }
+                return Err(error);
+            }
+        };
+
+        let mesh = Rc::new(MeshBuffers {
+            vertex_buffer,
+            index_buffer,
+            submeshes: model.submeshes,
+            materials,
+            material_buffer,
+            images,
+        });
+        self.meshes.insert(key, Rc::clone(&mesh));
+
+        Ok(MeshHandle(mesh))
+    }
+
+    /// The images of a mesh are keyed by their index in the model file, since they have no path of their own.
+    fn load_mesh_images(
+        &mut self,
+        model_file: &Rc<PathBuf>,
+        mesh_images: Vec<MemoryTextureSource>,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        upload_context: &mut UploadContext,
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<Vec<TextureHandle>> {
+        let mut images = Vec::new();
+        for (image_idx, image) in mesh_images.into_iter().enumerate() {
+            let key = (model_file.to_path_buf(), image_idx);
+            let texture = match self.mesh_images.get(&key) {
+                Some(texture) => Rc::clone(texture),
+                None => {
+                    let texture = Rc::new(TextureImage::new(
+                        Box::new(image),
+                        instance,
+                        physical_device,
+                        allocator,
+                        logical_device,
+                        upload_context,
+                        physical_device_properties,
+                    )?);
+                    self.mesh_images.insert(key, Rc::clone(&texture));
+                    texture
+                }
+            };
+            images.push(TextureHandle(texture));
+        }
+        Ok(images)
+    }
+
+    pub fn load_shader(
+        &mut self,
+        shader_file: &Rc<PathBuf>,
+        logical_device: &ash::Device,
+    ) -> Result<ShaderHandle> {
+        if let Some(shader) = self.shaders.get(shader_file.as_path()) {
+            return Ok(ShaderHandle(Rc::clone(shader)));
+        }
+
+        let shader = Rc::new(create_shader_module(shader_file.as_path(), logical_device)?);
+        self.shaders
+            .insert(shader_file.to_path_buf(), Rc::clone(&shader));
+
+        Ok(ShaderHandle(shader))
+    }
+
+    /// Frees the gpu resources of all the assets for which no handles exist anymore outside of the asset manager.
+    ///
+    /// # Safety
+    ///
+    /// The freed resources must not be used by any in-flight frame.
+    pub unsafe fn collect_garbage(
+        &mut self,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) {
+        for texture in take_unused(&mut self.textures) {
+            texture.cleanup(allocator, logical_device);
+        }
+        for cubemap in take_unused(&mut self.cubemaps) {
+            cubemap.cleanup(allocator, logical_device);
+        }
+        for mesh in take_unused(&mut self.meshes) {
+            mesh.cleanup(allocator, logical_device);
+        }
+        // the images are only unused once the meshes which hold them were freed
+        for image in take_unused(&mut self.mesh_images) {
+            image.cleanup(allocator, logical_device);
+        }
+        for shader in take_unused(&mut self.shaders) {
+            logical_device.destroy_shader_module(shader.module, None);
+        }
+    }
+
+    /// Frees the gpu resources of all assets. All handles have to be dropped before this is called.
+    ///
+    /// # Safety
+    ///
+    /// The freed resources must not be used by any in-flight frame.
+    pub unsafe fn destroy(
+        &mut self,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) {
+        self.collect_garbage(allocator, logical_device);
+
+        let leaked_count = self.textures.len()
+            + self.cubemaps.len()
+            + self.meshes.len()
+            + self.mesh_images.len()
+            + self.shaders.len();
+        if leaked_count > 0 {
+            info!(
+                "{} assets are still in use and won't be freed by the asset manager",
+                leaked_count
+            );
+        }
+    }
+}
+
+impl MeshBuffers {
+    unsafe fn cleanup(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
+        self.material_buffer.cleanup(allocator, logical_device);
+        self.index_buffer.cleanup(allocator, logical_device);
+        self.vertex_buffer.cleanup(allocator, logical_device);
+    }
+}
+//////////////////////// Fns ///////////////////////
+/// Removes the assets that are only referenced by the asset manager and returns them.
+fn take_unused<K, V>(assets: &mut HashMap<K, Rc<V>>) -> Vec<V>
+where
+    K: Clone + Eq + Hash,
+{
+    let unused_keys: Vec<K> = assets
+        .iter()
+        .filter(|(_, asset)| Rc::strong_count(asset) == 1)
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    unused_keys
+        .iter()
+        .filter_map(|key| assets.remove(key))
+        .filter_map(|asset| Rc::try_unwrap(asset).ok())
+        .collect()
+}
+
+/// The copies into the buffers of a mesh that failed to load may already be recorded,
+/// so they have to finish before the buffers are destroyed.
+fn finish_recorded_uploads(
+    upload_context: &mut UploadContext,
+    allocator: &mut MemoryAllocator,
+    logical_device: &ash::Device,
+) {
+    if let Err(error) = upload_context.wait_until_finished(allocator, logical_device) {
+        warn!(
+            "Failed to finish the uploads of a mesh before cleaning it up: {}",
+            error
+        );
+    }
+}
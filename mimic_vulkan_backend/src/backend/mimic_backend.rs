@@ -1,5 +1,12 @@
 use crate::{
-    buffers::{buffer::Buffer, index_buffer::IndexBuffer, memory, vertex_buffer::VertexBuffer},
+    backend::{
+        assets::{AssetManager, MeshHandle, ShaderHandle, TextureHandle},
+        pipelines::{PipelineKey, PipelineSettings, PipelineStore},
+    },
+    buffers::{
+        instance_buffer::InstanceBuffer,
+        memory::allocator::{MemoryAllocator, MemoryStatistics},
+    },
     depth::depth_resource::DepthResource,
     devices::{
         logical_device::create_logical_device,
@@ -7,16 +14,34 @@ use crate::{
         queues::{QueueFamilyIndices, QueueMap, QueueType},
         requirements::DeviceRequirements,
     },
-    drawing::{command_buffers, framebuffers, synchronization::SynchronizationContainer},
-    graphics_pipeline::GraphicsPipeline,
-    models::textured_model::{Mesh, MeshLoadingFlags},
+    drawing::{
+        command_buffers::{self, DrawCommandData, SubmeshDrawData},
+        framebuffers,
+        render_graph::{self, RenderGraphPlan, RenderGraphResources},
+        synchronization::SynchronizationContainer,
+        upload_context::UploadContext,
+    },
+    graphics_pipeline::{
+        pipeline_cache::PipelineCache,
+        pipeline_descriptor::{BlendMode, CullMode, DepthTest, PipelineDescriptor, PolygonMode},
+        render_pass::create_render_pass,
+    },
+    models::{
+        instance::InstanceData,
+        material::MaterialTexture,
+        textured_model::{MeshLoadingFlags, Submesh},
+        vertex::{VertexAttribute, VertexLayout},
+    },
     msaa::{multisampling::ColorResource, util::get_max_sample_count},
     presentation::{
+        frame_capture::{self, FrameCapture},
         image_views::ImageViews,
+        render_target::{OffscreenImage, RenderTarget},
         swap_chain::{SwapChainContainer, SwapChainSupportDetails},
     },
-    textures::images::TextureImage,
-    uniforms::{self, descriptors::DescriptorData},
+    uniforms::{
+        self, bindless::BindlessTextures, buffers::UniformRingBuffer, descriptors::DescriptorData,
+    },
     util::{
         self,
         debug::VulkanDebug,
@@ -31,13 +56,16 @@ use log::info;
 use mimic_common::{
     apptime::AppTime,
     config::MimicConfig,
-    texture::{FilesystemTextureSource, TextureSource},
+    cubemap::CubemapFiles,
+    texture::ColorSpace,
     uniforms::{
         update_uniform_buffer, StaticFnUniformSpec, UniformBufferObject, UniformSpec,
-        UniformUpdateInput,
+        UniformUpdateInput, BINDLESS_TEXTURE_ID_OFFSET, MAX_PUSH_CONSTANTS_SIZE,
+        MAX_UNIFORM_BUFFER_SIZE,
     },
 };
 use std::{
+    collections::BTreeMap,
     convert::TryFrom,
     ffi::CString,
     path::{Path, PathBuf},
@@ -50,6 +78,11 @@ const REQUIRED_QUEUES: [QueueType; 2] = [
     QueueType::PresentQueue,
 ];
 const DEVICE_EXTENSIONS: [&str; 1] = ["VK_KHR_swapchain"];
+// without a surface we never present so we neither need a present queue nor the swapchain extension
+const HEADLESS_REQUIRED_QUEUES: [QueueType; 1] =
+    [QueueType::QueueWithFlag(vk::QueueFlags::GRAPHICS)];
+const HEADLESS_DEVICE_EXTENSIONS: [&str; 0] = [];
+const PIPELINE_CACHE_FILE: &str = "pipeline_cache.bin";
 //////////////////////// Enums ///////////////////////
 /// This enum informs us during which part of the draw-frame process a window resize happened
 enum ResizeDetectedLocation {
@@ -64,15 +97,51 @@ pub struct VulkanApp {
     instance: ash::Instance,
     _validation: VulkanValidation,
     debug: VulkanDebug,
-    surface_container: SurfaceContainer,
+    /// the surface is `None` when the app is rendering headless into an offscreen image
+    surface_container: Option<SurfaceContainer>,
     physical_device: vk::PhysicalDevice,
     physical_device_properties: vk::PhysicalDeviceProperties,
     logical_device: ash::Device,
     queue_indices: QueueFamilyIndices,
     queues: QueueMap,
     dependent_fields: SwapChainDependentFields,
+    /// the render pass is shared by all render commands, since they all draw to the same attachments.
+    /// it only depends on the format of the render target, so it usually outlives swap-chain recreation
+    render_pass: vk::RenderPass,
     command_pool: vk::CommandPool,
-    current_render_command: Option<RenderCommand>,
+    /// hands out the device memory of all buffers and images
+    allocator: MemoryAllocator,
+    /// records the uploads of meshes and textures, which are submitted together before the next frame
+    upload_context: UploadContext,
+    /// used for all pipeline creation and persisted to disk when the app shuts down
+    pipeline_cache: PipelineCache,
+    /// command buffers which draw all the render commands. there is one per frame in flight and it is recorded every frame
+    command_buffers: Vec<vk::CommandBuffer>,
+    render_commands: BTreeMap<RenderCommandId, RenderCommand>,
+    next_render_command_id: usize,
+    /// the sky cube, which is drawn before the render commands and behind all of their geometry
+    skybox: Option<RenderCommand>,
+    /// render commands which were removed, with the number of frames that were submitted when they were removed.
+    /// they are destroyed once a frame that was submitted after their removal is finished
+    removed_render_commands: Vec<(u64, RenderCommand)>,
+    /// the textures, meshes and shaders used by the render commands
+    assets: AssetManager,
+    /// the graphics pipelines used by the render commands
+    pipelines: PipelineStore,
+    /// the layout of the set which binds the uniform data of a render command as a slice of the uniform ring buffer
+    uniform_descriptors: vk::DescriptorSetLayout,
+    /// holds the uniform data of all render commands for every frame in flight
+    uniform_ring_buffer: UniformRingBuffer,
+    /// the single untransformed instance that render commands without instances are drawn with
+    default_instance_buffer: InstanceBuffer,
+    /// the array that all textures of the render commands are added to, if bindless textures are enabled
+    bindless_textures: Option<BindlessTextures>,
+    /// the passes which are recorded every frame. if there is no render graph then only the render commands are drawn
+    render_graph: Option<RenderGraphPlan>,
+    /// whether the next drawn frame should be copied for `capture_frame`
+    capture_requested: bool,
+    /// the copy of the frame that was drawn after the capture was requested, until it is read
+    pending_capture: Option<FrameCapture>,
     sync_container: SynchronizationContainer,
     msaa_samples: vk::SampleCountFlags,
     /// resolve resource files
@@ -85,36 +154,72 @@ pub struct VulkanApp {
     pub window_minimized: bool,
 }
 
-struct RenderCommandSwapChainFields {
-    uniform_buffers: Vec<Buffer>,
-    descriptor_data: DescriptorData,
-    command_buffers: Vec<vk::CommandBuffer>,
-    graphics_pipeline: GraphicsPipeline,
-    framebuffers: Vec<vk::Framebuffer>,
+/// The settings which a `VulkanApp` is created with.
+#[derive(Clone, Copy, Debug)]
+pub struct VulkanAppOptions {
+    /// how many frames the CPU can record before it has to wait for the GPU to finish the oldest one.
+    /// every frame in flight has its own command buffer and uniform buffer, independent of the number of swap-chain images
+    pub frames_in_flight: usize,
+    /// whether the textures of all submeshes are also added to one large texture array, which shaders can bind as set 2
+    /// and index with the texture id at `BINDLESS_TEXTURE_ID_OFFSET` of the push constants.
+    /// This needs a vulkan 1.2 device with descriptor indexing and leaves less room for the push constants of render commands
+    pub bindless_textures: bool,
 }
 
+/// A handle to a render command that was submitted to the `VulkanApp`.
+/// The render command stays alive and is drawn every frame until it is removed using this handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RenderCommandId(usize);
+
 struct RenderCommand {
-    vertex_shader_file: Rc<PathBuf>,
-    fragment_shader_file: Rc<PathBuf>,
+    // the shaders are only held so that they stay loaded for as long as the pipeline is used
+    _vertex_shader: ShaderHandle,
+    _fragment_shader: ShaderHandle,
     uniform_spec: Box<dyn UniformSpec>,
-    _model: Mesh,
-    vertex_buffer: VertexBuffer,
-    index_buffer: IndexBuffer,
-    texture_image: TextureImage,
-    uniform_descriptors: vk::DescriptorSetLayout,
-    dependent_fields: RenderCommandSwapChainFields,
+    mesh: MeshHandle,
+    /// `None` if the render command is drawn as the default instance
+    instance_buffer: Option<InstanceBuffer>,
+    pipeline_key: PipelineKey,
+    /// one for each submesh of the mesh, the uniform data is bound through the shared uniform ring buffer
+    submesh_descriptors: Vec<SubmeshDescriptors>,
+}
+
+struct SubmeshDescriptors {
+    // the texture is only held so that it stays loaded for as long as the descriptor set refers to it
+    _texture: TextureHandle,
+    /// binds the texture and material of the submesh
+    descriptor_data: DescriptorData,
+    /// the index of the texture in the bindless texture array, if bindless textures are enabled
+    bindless_texture_id: Option<u32>,
+    /// hidden submeshes are skipped when the draw list is recorded
+    visible: bool,
 }
 
 /// This structure wraps all the objects that depend on the swap-chain in order to be able to recreate them when the swap-chain images change.
 /// Swap-chain is a series of framebuffers that can be drawn to and later presented to the graphics display.
 /// The purpose is to allow double buffering so that the framebuffer isn't being written to while it is presented.
+/// When rendering headless the swap-chain is replaced by a single offscreen image.
 struct SwapChainDependentFields {
-    swap_chain_container: SwapChainContainer,
+    render_target: RenderTarget,
     image_views_container: ImageViews,
     color_resource: ColorResource,
     depth_resource: DepthResource,
+    framebuffers: Vec<vk::Framebuffer>,
+    /// the index of the image which the most recently submitted frame was rendered into
+    last_rendered_image_index: Option<usize>,
+    /// the transient images, render passes and framebuffers used by the render graph
+    render_graph_resources: Option<RenderGraphResources>,
 }
 //////////////////////// Impls ///////////////////////
+impl Default for VulkanAppOptions {
+    fn default() -> Self {
+        Self {
+            frames_in_flight: 2,
+            bindless_textures: false,
+        }
+    }
+}
+
 impl VulkanApp {
     /// Constructs a new `VulkanApp` with the provided window title and engine name.
     // It creates a swap-chain using the `window_surface` and `window_size`.
@@ -124,22 +229,105 @@ impl VulkanApp {
         window_surface: &WindowSurface,
         window_size: &WindowSize,
         resource_resolver: MimicConfig,
+        options: &VulkanAppOptions,
+    ) -> Result<Self> {
+        Self::create(
+            window_title,
+            engine_name,
+            Some(window_surface),
+            window_size,
+            resource_resolver,
+            options,
+        )
+    }
+
+    /// Constructs a new headless `VulkanApp` which renders into an offscreen color image of size `width` x `height`.
+    /// No window, surface or present queue is needed, so this can run where there is no display (for example with a software vulkan ICD like lavapipe).
+    /// Frames are rendered using the same `draw_frame` as when rendering to a window.
+    pub fn new_headless(
+        app_name: &str,
+        engine_name: &str,
+        width: u32,
+        height: u32,
+        resource_resolver: MimicConfig,
+        options: &VulkanAppOptions,
+    ) -> Result<Self> {
+        let window_size = WindowSize {
+            monitor_resolution_width: width,
+            monitor_resolution_height: height,
+        };
+        Self::create(
+            app_name,
+            engine_name,
+            None,
+            &window_size,
+            resource_resolver,
+            options,
+        )
+    }
+
+    fn create(
+        window_title: &str,
+        engine_name: &str,
+        window_surface: Option<&WindowSurface>,
+        window_size: &WindowSize,
+        resource_resolver: MimicConfig,
+        options: &VulkanAppOptions,
     ) -> Result<Self> {
         let entry = unsafe { ash::Entry::load().unwrap() };
         let validation = VulkanValidation::enabled(util::validation::ValidationOptions::Verbose);
         // creating the instance is equivalent to initializing the vulkan library
-        let instance = Self::create_instance(window_title, engine_name, &entry, &validation)?;
+        let extension_names = if window_surface.is_some() {
+            util::platform::required_extension_names()
+        } else {
+            util::platform::headless_extension_names()
+        };
+        let instance = Self::create_instance(
+            window_title,
+            engine_name,
+            &entry,
+            &validation,
+            &extension_names,
+            // descriptor indexing is core since vulkan 1.2
+            if options.bindless_textures {
+                vk::API_VERSION_1_2
+            } else {
+                vk::make_api_version(0, 1, 0, 0)
+            },
+        )?;
         let debug = VulkanDebug::new(&entry, &instance, &validation);
-        // creating a surface to present images to
-        let surface_container = util::platform::create_surface(&entry, &instance, window_surface)?;
+        // creating a surface to present images to, unless we are headless
+        let surface_container = match window_surface {
+            Some(window_surface) => Some(util::platform::create_surface(
+                &entry,
+                &instance,
+                window_surface,
+            )?),
+            None => None,
+        };
         // pick the first graphics card that supports all the features we specified in instance
-        let requirements = DeviceRequirements::new(
-            &REQUIRED_QUEUES,
-            &DEVICE_EXTENSIONS,
-            is_swap_chain_adequate,
-            is_device_supporting_features,
-        );
-        let physical_device = pick_physical_device(&instance, &surface_container, &requirements)?;
+        let requirements = if surface_container.is_some() {
+            DeviceRequirements::new(
+                &REQUIRED_QUEUES,
+                &DEVICE_EXTENSIONS,
+                is_swap_chain_adequate,
+                is_device_supporting_features,
+            )
+        } else {
+            DeviceRequirements::new(
+                &HEADLESS_REQUIRED_QUEUES,
+                &HEADLESS_DEVICE_EXTENSIONS,
+                is_swap_chain_adequate,
+                is_device_supporting_features,
+            )
+        };
+        let requirements = if options.bindless_textures {
+            requirements.with_descriptor_indexing()
+        } else {
+            requirements
+        };
+        let physical_device =
+            pick_physical_device(&instance, surface_container.as_ref(), &requirements)?;
         let physical_device_properties =
             get_physical_device_properties(&instance, physical_device)?;
         let msaa_samples = get_max_sample_count(physical_device_properties);
@@ -147,7 +335,7 @@ impl VulkanApp {
         let queue_indices = QueueFamilyIndices::find(
             &instance,
             physical_device,
-            &surface_container,
+            surface_container.as_ref(),
             &requirements,
         )?;
         let logical_device = create_logical_device(
@@ -158,23 +346,85 @@ impl VulkanApp {
             &validation,
         )?;
 
-        let sync_container = SynchronizationContainer::create(&logical_device)?;
+        let sync_container =
+            SynchronizationContainer::create(&logical_device, options.frames_in_flight)?;
 
         let command_pool = command_buffers::create_command_pool(&logical_device, &queue_indices)?;
+        // the command buffers are freed when the pool is destroyed
+        let command_buffers = command_buffers::allocate_command_buffers(
+            &logical_device,
+            &command_pool,
+            sync_container.get_frames_in_flight(),
+        )?;
 
         let queues = QueueMap::new(&queue_indices, &logical_device)?;
 
-        let current_render_command: Option<RenderCommand> = None;
+        let pipeline_cache = PipelineCache::new(
+            resource_resolver
+                .get_resource_base_folder()
+                .join(PIPELINE_CACHE_FILE),
+            &logical_device,
+            &physical_device_properties,
+        )?;
 
-        let dependent_fields = Self::create_swapchain_dependent_fields(
+        let mut allocator =
+            MemoryAllocator::new(&instance, physical_device, &physical_device_properties);
+        let mut upload_context = UploadContext::new(&logical_device, &queue_indices, &queues)?;
+
+        let uniform_descriptors =
+            uniforms::descriptors::create_uniform_descriptor_set_layout(&logical_device)?;
+        let uniform_ring_buffer = UniformRingBuffer::new(
+            sync_container.get_frames_in_flight(),
+            &mut allocator,
+            &logical_device,
+            uniform_descriptors,
+            &physical_device_properties,
+        )?;
+
+        let bindless_textures = if options.bindless_textures {
+            Some(BindlessTextures::new(
+                &instance,
+                physical_device,
+                &logical_device,
+            )?)
+        } else {
+            None
+        };
+
+        let default_instance_buffer = InstanceBuffer::new(
+            &[InstanceData::default()],
+            &mut allocator,
+            &logical_device,
+            &mut upload_context,
+        )?;
+
+        let render_target = Self::create_render_target(
             &instance,
             physical_device,
+            &mut allocator,
             &logical_device,
             &queue_indices,
-            &surface_container,
+            surface_container.as_ref(),
+            window_size,
+        )?;
+
+        let render_pass = create_render_pass(
+            &instance,
+            &logical_device,
+            physical_device,
+            &render_target,
+            msaa_samples,
+        )?;
+
+        let dependent_fields = Self::create_swapchain_dependent_fields(
+            render_target,
+            &instance,
+            physical_device,
+            &mut allocator,
+            &logical_device,
             &command_pool,
             &queues,
-            window_size,
+            render_pass,
             msaa_samples,
         )?;
 
@@ -190,10 +440,27 @@ impl VulkanApp {
             queue_indices,
             queues,
             dependent_fields,
+            render_pass,
             command_pool,
+            allocator,
+            upload_context,
+            pipeline_cache,
+            command_buffers,
             sync_container,
             msaa_samples,
-            current_render_command,
+            render_commands: BTreeMap::new(),
+            skybox: None,
+            removed_render_commands: Vec::new(),
+            next_render_command_id: 0,
+            assets: AssetManager::new(),
+            pipelines: PipelineStore::new(),
+            uniform_descriptors,
+            uniform_ring_buffer,
+            default_instance_buffer,
+            bindless_textures,
+            render_graph: None,
+            capture_requested: false,
+            pending_capture: None,
             resource_resolver,
             window_resized: false,
             window_minimized: false,
@@ -202,80 +469,7 @@ impl VulkanApp {
         Ok(result)
     }
 
-    fn create_render_command_swap_chain_fields(
-        vertex_shader_file: &Path,
-        fragment_shader_file: &Path,
-        uniform_spec: &Box<dyn UniformSpec>,
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
-        logical_device: &ash::Device,
-        texture_image: &TextureImage,
-        vertex_buffer: &VertexBuffer,
-        index_buffer: &IndexBuffer,
-        command_pool: &vk::CommandPool,
-        uniform_descriptors: vk::DescriptorSetLayout,
-        swap_chain_dependent_fields: &SwapChainDependentFields,
-        msaa_samples: vk::SampleCountFlags,
-    ) -> Result<RenderCommandSwapChainFields> {
-        let graphics_pipeline = GraphicsPipeline::new(
-            vertex_shader_file,
-            fragment_shader_file,
-            instance,
-            logical_device,
-            physical_device,
-            &swap_chain_dependent_fields.swap_chain_container,
-            &uniform_descriptors,
-            msaa_samples,
-        )?;
-
-        let framebuffers = framebuffers::create_framebuffers(
-            logical_device,
-            &graphics_pipeline,
-            &swap_chain_dependent_fields.image_views_container,
-            swap_chain_dependent_fields.depth_resource.depth_image_view,
-            &swap_chain_dependent_fields.color_resource,
-            &swap_chain_dependent_fields.swap_chain_container,
-        )?;
-
-        let uniform_buffers = uniforms::buffers::create_uniform_buffers(
-            uniform_spec.uniform_buffer_size(),
-            instance,
-            physical_device,
-            logical_device,
-            &swap_chain_dependent_fields.swap_chain_container,
-        )?;
-
-        let descriptor_data = DescriptorData::new(
-            &uniform_spec,
-            logical_device,
-            &swap_chain_dependent_fields.swap_chain_container,
-            uniform_descriptors,
-            &uniform_buffers,
-            texture_image,
-        )?;
-
-        // command buffers are released when we destroy the pool
-        let command_buffers = command_buffers::create_command_buffers(
-            logical_device,
-            command_pool,
-            &framebuffers,
-            &graphics_pipeline,
-            &swap_chain_dependent_fields.swap_chain_container,
-            vertex_buffer,
-            index_buffer,
-            &descriptor_data,
-        )?;
-
-        Ok(RenderCommandSwapChainFields {
-            uniform_buffers,
-            descriptor_data,
-            command_buffers,
-            graphics_pipeline,
-            framebuffers,
-        })
-    }
-
-    pub fn create_default_render_command(&mut self) -> Result<()> {
+    pub fn create_default_render_command(&mut self) -> Result<RenderCommandId> {
         let texture_file = Rc::new(
             self.resource_resolver
                 .resolve_resource("res/backend/textures/viking_room.png")?,
@@ -294,190 +488,716 @@ impl VulkanApp {
         );
         self.create_render_command(
             &texture_file,
+            ColorSpace::Srgb,
             &model_file,
             &vertex_shader_file,
             &frag_shader_file,
             Box::new(StaticFnUniformSpec::new::<UniformBufferObject>(
                 update_uniform_buffer,
             )),
+            &PipelineDescriptor::default(),
+            &VertexLayout::default(),
+            MeshLoadingFlags::default_for_file(&model_file),
+            None,
         )
     }
 
+    /// The texture of the render command is sampled in the `texture_color_space`,
+    /// while the textures of the materials of the model file are sampled in the color space of their material.
     pub fn create_render_command(
         &mut self,
         texture_file: &Rc<PathBuf>,
+        texture_color_space: ColorSpace,
         model_file: &Rc<PathBuf>,
         vertex_shader_file: &Rc<PathBuf>,
         fragment_shader_file: &Rc<PathBuf>,
         uniform_spec: Box<dyn UniformSpec>,
-    ) -> Result<()> {
-        // we are removing the previous render command so we must block until we are done using it and then clean it up
-        unsafe {
-            self.logical_device.device_wait_idle()?;
-        }
-        if let Some(render_command) = &mut self.current_render_command {
-            unsafe {
-                render_command.cleanup(&self.logical_device, self.command_pool);
-            }
-        }
-
-        let texture_source = Box::new(FilesystemTextureSource::new(texture_file)?);
-
-        let texture_image = TextureImage::new(
-            texture_source,
+        pipeline_descriptor: &PipelineDescriptor,
+        vertex_layout: &VertexLayout,
+        mesh_loading_flags: MeshLoadingFlags,
+        instances: Option<&[InstanceData]>,
+    ) -> Result<RenderCommandId> {
+        self.validate_render_command(uniform_spec.as_ref(), pipeline_descriptor, instances)?;
+
+        // files which are already loaded by another render command are shared instead of loaded again
+        let texture = self.assets.load_texture(
+            texture_file,
+            texture_color_space,
             &self.instance,
             self.physical_device,
+            &mut self.allocator,
             &self.logical_device,
-            self.command_pool,
-            &self.queues,
+            &mut self.upload_context,
             &self.physical_device_properties,
         )?;
+        let render_command = self.build_render_command(
+            texture,
+            model_file,
+            vertex_shader_file,
+            fragment_shader_file,
+            uniform_spec,
+            pipeline_descriptor,
+            vertex_layout,
+            mesh_loading_flags,
+            instances,
+        )?;
 
-        let model = Mesh::new(model_file.as_path(), MeshLoadingFlags::INVERTED_UP)?;
+        let render_command_id = RenderCommandId(self.next_render_command_id);
+        self.next_render_command_id += 1;
+        self.render_commands
+            .insert(render_command_id, render_command);
 
-        let vertex_buffer = VertexBuffer::new(
-            &model.vertices,
+        Ok(render_command_id)
+    }
+
+    /// Draws the cubemap loaded from the `cubemap_files` behind all geometry, replacing the previous skybox.
+    /// Only the rotation of the view matrix written by the `uniform_spec` is used, so the sky never comes closer.
+    pub fn set_skybox(
+        &mut self,
+        cubemap_files: &CubemapFiles,
+        uniform_spec: Box<dyn UniformSpec>,
+    ) -> Result<()> {
+        // the cube is drawn from the inside at the far plane, where it passes the depth test against the cleared depth
+        let pipeline_descriptor = PipelineDescriptor {
+            cull_mode: CullMode::None,
+            blend_mode: BlendMode::Opaque,
+            depth_test: DepthTest::LessOrEqual,
+            depth_write: false,
+            ..PipelineDescriptor::default()
+        };
+        self.validate_render_command(uniform_spec.as_ref(), &pipeline_descriptor, None)?;
+
+        let model_file = Rc::new(
+            self.resource_resolver
+                .resolve_resource("res/backend/models/skybox.obj")?,
+        );
+        let vertex_shader_file = Rc::new(
+            self.resource_resolver
+                .resolve_resource("res/backend/shaders/spv/skybox.vert.spv")?,
+        );
+        let frag_shader_file = Rc::new(
+            self.resource_resolver
+                .resolve_resource("res/backend/shaders/spv/skybox.frag.spv")?,
+        );
+        let cubemap = self.assets.load_cubemap(
+            cubemap_files,
+            ColorSpace::Srgb,
             &self.instance,
             self.physical_device,
+            &mut self.allocator,
             &self.logical_device,
-            self.command_pool,
-            &self.queues,
+            &mut self.upload_context,
+            &self.physical_device_properties,
+        )?;
+        let skybox = self.build_render_command(
+            cubemap,
+            &model_file,
+            &vertex_shader_file,
+            &frag_shader_file,
+            uniform_spec,
+            &pipeline_descriptor,
+            &VertexLayout::new(vec![VertexAttribute::Position])?,
+            MeshLoadingFlags::default_for_file(&model_file),
+            None,
         )?;
 
-        let index_buffer = IndexBuffer::new(
-            &model.indices,
+        self.remove_skybox()?;
+        self.skybox = Some(skybox);
+        Ok(())
+    }
+
+    /// Stops drawing the skybox and frees its resources. Does nothing if there is no skybox.
+    pub fn remove_skybox(&mut self) -> Result<()> {
+        if let Some(skybox) = self.skybox.take() {
+            self.queue_render_command_destruction(skybox);
+        }
+        Ok(())
+    }
+
+    fn validate_render_command(
+        &self,
+        uniform_spec: &dyn UniformSpec,
+        pipeline_descriptor: &PipelineDescriptor,
+        instances: Option<&[InstanceData]>,
+    ) -> Result<()> {
+        if instances.map_or(false, |instances| instances.is_empty()) {
+            return Err(VulkanError::InstancesEmpty);
+        }
+        if uniform_spec.uniform_buffer_size() > MAX_UNIFORM_BUFFER_SIZE {
+            return Err(VulkanError::UniformBufferTooLarge(
+                uniform_spec.uniform_buffer_size(),
+            ));
+        }
+        // with bindless textures the last push constants hold the texture id of the draw
+        let max_push_constants_size = if self.bindless_textures.is_some() {
+            BINDLESS_TEXTURE_ID_OFFSET
+        } else {
+            MAX_PUSH_CONSTANTS_SIZE
+        };
+        if uniform_spec.push_constants_size() > max_push_constants_size {
+            return Err(VulkanError::PushConstantsTooLarge(
+                uniform_spec.push_constants_size(),
+            ));
+        }
+        if pipeline_descriptor.polygon_mode != PolygonMode::Fill {
+            let features = unsafe {
+                self.instance
+                    .get_physical_device_features(self.physical_device)
+            };
+            if features.fill_mode_non_solid != vk::TRUE {
+                return Err(VulkanError::PipelineDescriptorUnsupported(format!(
+                    "polygon mode {:?} is not supported by the device",
+                    pipeline_descriptor.polygon_mode
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the mesh and shaders and creates the pipeline and descriptor sets of a render command which samples the `texture`.
+    fn build_render_command(
+        &mut self,
+        texture: TextureHandle,
+        model_file: &Rc<PathBuf>,
+        vertex_shader_file: &Rc<PathBuf>,
+        fragment_shader_file: &Rc<PathBuf>,
+        uniform_spec: Box<dyn UniformSpec>,
+        pipeline_descriptor: &PipelineDescriptor,
+        vertex_layout: &VertexLayout,
+        mesh_loading_flags: MeshLoadingFlags,
+        instances: Option<&[InstanceData]>,
+    ) -> Result<RenderCommand> {
+        let mesh = self.assets.load_mesh(
+            model_file,
+            mesh_loading_flags,
+            vertex_layout,
             &self.instance,
             self.physical_device,
+            &mut self.allocator,
             &self.logical_device,
-            self.command_pool,
-            &self.queues,
+            &mut self.upload_context,
+            &self.physical_device_properties,
         )?;
 
-        let uniform_descriptors =
-            uniforms::descriptors::create_descriptor_set_layout(&self.logical_device)?;
-
-        let dependent_fields = Self::create_render_command_swap_chain_fields(
-            vertex_shader_file.as_path(),
-            fragment_shader_file.as_path(),
-            &uniform_spec,
-            &self.instance,
-            self.physical_device,
+        let vertex_shader = self
+            .assets
+            .load_shader(vertex_shader_file, &self.logical_device)?;
+        let fragment_shader = self
+            .assets
+            .load_shader(fragment_shader_file, &self.logical_device)?;
+
+        // render commands with the same shaders, descriptor and vertex layout share a pipeline
+        let pipeline_settings = self.get_pipeline_settings();
+        let pipeline_key = self.pipelines.acquire(
+            &vertex_shader,
+            &fragment_shader,
+            pipeline_descriptor,
+            vertex_layout,
             &self.logical_device,
-            &texture_image,
-            &vertex_buffer,
-            &index_buffer,
-            &self.command_pool,
-            uniform_descriptors,
-            &self.dependent_fields,
-            self.msaa_samples,
+            &pipeline_settings,
         )?;
+        let submesh_descriptors = self.create_submesh_descriptors(&pipeline_key, &mesh, &texture);
+        let submesh_descriptors = match submesh_descriptors {
+            Ok(submesh_descriptors) => submesh_descriptors,
+            Err(error) => {
+                unsafe {
+                    self.pipelines.release(&pipeline_key, &self.logical_device);
+                }
+                return Err(error);
+            }
+        };
+
+        // the instance buffer is created last, so that the steps before it have no instance buffer to free when they fail
+        let instance_buffer = match instances {
+            Some(instances) => match InstanceBuffer::new(
+                instances,
+                &mut self.allocator,
+                &self.logical_device,
+                &mut self.upload_context,
+            ) {
+                Ok(instance_buffer) => Some(instance_buffer),
+                Err(error) => {
+                    unsafe {
+                        for descriptors in submesh_descriptors {
+                            descriptors
+                                .cleanup(&self.logical_device, self.bindless_textures.as_mut());
+                        }
+                        self.pipelines.release(&pipeline_key, &self.logical_device);
+                    }
+                    return Err(error);
+                }
+            },
+            None => None,
+        };
 
-        self.current_render_command = Some(RenderCommand {
-            vertex_shader_file: Rc::clone(vertex_shader_file),
-            fragment_shader_file: Rc::clone(fragment_shader_file),
+        Ok(RenderCommand {
+            _vertex_shader: vertex_shader,
+            _fragment_shader: fragment_shader,
             uniform_spec,
-            _model: model,
-            vertex_buffer,
-            index_buffer,
-            texture_image,
-            uniform_descriptors,
-            dependent_fields,
-        });
+            mesh,
+            instance_buffer,
+            pipeline_key,
+            submesh_descriptors,
+        })
+    }
 
+    /// Creates the set of each submesh of the `mesh`, which samples the diffuse texture of its material or else the `texture` of the render command.
+    fn create_submesh_descriptors(
+        &mut self,
+        pipeline_key: &PipelineKey,
+        mesh: &MeshHandle,
+        texture: &TextureHandle,
+    ) -> Result<Vec<SubmeshDescriptors>> {
+        let mut submesh_descriptors = Vec::new();
+        for submesh in mesh.get_submeshes() {
+            match self.create_submesh_descriptor(pipeline_key, mesh, submesh, texture) {
+                Ok(descriptors) => submesh_descriptors.push(descriptors),
+                Err(error) => {
+                    for descriptors in submesh_descriptors {
+                        unsafe {
+                            descriptors
+                                .cleanup(&self.logical_device, self.bindless_textures.as_mut())
+                        };
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(submesh_descriptors)
+    }
+
+    fn create_submesh_descriptor(
+        &mut self,
+        pipeline_key: &PipelineKey,
+        mesh: &MeshHandle,
+        submesh: &Submesh,
+        texture: &TextureHandle,
+    ) -> Result<SubmeshDescriptors> {
+        let texture = match &mesh.get_material(submesh).diffuse_texture {
+            Some(MaterialTexture::File { path, color_space }) => self.assets.load_texture(
+                &Rc::new(path.clone()),
+                *color_space,
+                &self.instance,
+                self.physical_device,
+                &mut self.allocator,
+                &self.logical_device,
+                &mut self.upload_context,
+                &self.physical_device_properties,
+            )?,
+            Some(MaterialTexture::Image(image)) => mesh.get_image(*image).clone(),
+            None => texture.clone(),
+        };
+        // cubemaps are left out of the array, since shaders sample its textures as 2D textures
+        let bindless_texture_id = match self.bindless_textures.as_mut() {
+            Some(bindless_textures)
+                if texture.get_texture().image.view_type == vk::ImageViewType::TYPE_2D =>
+            {
+                Some(bindless_textures.add(&texture, &self.logical_device)?)
+            }
+            _ => None,
+        };
+        // the set is allocated with the layout that was reflected from the shaders of the pipeline
+        let descriptor_data = self
+            .pipelines
+            .get_texture_descriptors(pipeline_key)
+            .and_then(|texture_descriptors| {
+                DescriptorData::new(
+                    &self.logical_device,
+                    texture_descriptors,
+                    texture.get_texture(),
+                    mesh.get_material_descriptor_info(submesh)?,
+                )
+            });
+        let descriptor_data = match descriptor_data {
+            Ok(descriptor_data) => descriptor_data,
+            Err(error) => {
+                if let (Some(bindless_textures), Some(texture_id)) =
+                    (self.bindless_textures.as_mut(), bindless_texture_id)
+                {
+                    bindless_textures.remove(texture_id);
+                }
+                return Err(error);
+            }
+        };
+        Ok(SubmeshDescriptors {
+            _texture: texture,
+            descriptor_data,
+            bindless_texture_id,
+            visible: true,
+        })
+    }
+
+    /// Removes the render command with the provided `render_command_id` from the draw list and frees all its resources.
+    /// The resources are freed once the frames in flight which draw the render command are finished.
+    pub fn remove_render_command(&mut self, render_command_id: RenderCommandId) -> Result<()> {
+        let render_command = self
+            .render_commands
+            .remove(&render_command_id)
+            .ok_or(VulkanError::RenderCommandNotAvailable)?;
+        self.queue_render_command_destruction(render_command);
+        Ok(())
+    }
+
+    // the frames which were already submitted may still draw the render command,
+    // and its uploads are only submitted with the next frame
+    fn queue_render_command_destruction(&mut self, render_command: RenderCommand) {
+        let submitted_frame_count = self.sync_container.get_submitted_frame_count();
+        self.removed_render_commands
+            .push((submitted_frame_count, render_command));
+    }
+
+    /// Destroys the removed render commands which are no longer used by any frame in flight or upload,
+    /// together with the assets and pipelines that only they used.
+    fn destroy_finished_render_commands(&mut self) -> Result<()> {
+        if self.removed_render_commands.is_empty() {
+            return Ok(());
+        }
+        let finished_frame_count = self
+            .sync_container
+            .get_finished_frame_count(&self.logical_device)?;
+        let (finished, still_used): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.removed_render_commands)
+                .into_iter()
+                .partition(|(removed_at, _)| *removed_at < finished_frame_count);
+        self.removed_render_commands = still_used;
+        if finished.is_empty() {
+            return Ok(());
+        }
+
+        unsafe {
+            for (_, render_command) in finished {
+                self.pipelines
+                    .release(&render_command.pipeline_key, &self.logical_device);
+                render_command.cleanup(
+                    &mut self.allocator,
+                    &self.logical_device,
+                    self.bindless_textures.as_mut(),
+                );
+            }
+            // free the assets that were only used by the removed render commands
+            // the pipelines go first since they are keyed by the shader modules
+            self.assets
+                .collect_garbage(&mut self.allocator, &self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Shows or hides the submeshes named `part_name` of the mesh of the render command with the provided `render_command_id`.
+    /// For obj files the parts are the objects and groups, for gltf files they are the nodes.
+    pub fn set_part_visibility(
+        &mut self,
+        render_command_id: RenderCommandId,
+        part_name: &str,
+        visible: bool,
+    ) -> Result<()> {
+        let render_command = self
+            .render_commands
+            .get_mut(&render_command_id)
+            .ok_or(VulkanError::RenderCommandNotAvailable)?;
+        let mut found = false;
+        for (submesh, descriptors) in render_command
+            .mesh
+            .get_submeshes()
+            .iter()
+            .zip(render_command.submesh_descriptors.iter_mut())
+        {
+            if submesh.name == part_name {
+                descriptors.visible = visible;
+                found = true;
+            }
+        }
+        if !found {
+            return Err(VulkanError::MeshPartNotFound(part_name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Replaces the passes that are recorded every frame with the passes of the render graph `plan`.
+    /// The render commands are drawn by the scene pass of the render graph.
+    pub fn set_render_graph(&mut self, plan: RenderGraphPlan) -> Result<()> {
+        unsafe {
+            self.logical_device.device_wait_idle()?;
+            if let Some(render_graph_resources) =
+                self.dependent_fields.render_graph_resources.take()
+            {
+                render_graph_resources.destroy(&mut self.allocator, &self.logical_device);
+            }
+        }
+        self.render_graph = Some(plan);
+        self.create_render_graph_resources()
+    }
+
+    fn create_render_graph_resources(&mut self) -> Result<()> {
+        if let Some(plan) = &self.render_graph {
+            let render_graph_resources = RenderGraphResources::new(
+                plan,
+                &self.instance,
+                self.physical_device,
+                &mut self.allocator,
+                &self.logical_device,
+                &self.dependent_fields.render_target,
+                &self.dependent_fields.image_views_container,
+                &self.dependent_fields.color_resource,
+                &self.dependent_fields.depth_resource,
+                self.msaa_samples,
+            )?;
+            self.dependent_fields.render_graph_resources = Some(render_graph_resources);
+        }
         Ok(())
     }
 
+    /// Records the command buffer of the current frame, which draws all the render commands in the draw list.
+    /// Since this happens every frame, changes to the draw list show up in the next frame without any extra work.
+    /// The `frame_capture` copies the rendered image before it is presented.
+    fn record_command_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        uniform_update_input: UniformUpdateInput,
+        uniform_offsets: &[u32],
+        frame_capture: Option<&FrameCapture>,
+    ) -> Result<()> {
+        let uniform_descriptor_set = self
+            .uniform_ring_buffer
+            .get_descriptor_set(self.sync_container.get_current_frame_idx())?;
+        command_buffers::begin_frame_command_buffer(&self.logical_device, command_buffer)?;
+
+        // the skybox is drawn first, so that the depth test can discard the sky behind the geometry
+        let draw_list = self
+            .skybox
+            .iter()
+            .chain(self.render_commands.values())
+            .zip(uniform_offsets.iter())
+            .map(|(render_command, &uniform_offset)| {
+                let mut push_constants =
+                    vec![0u8; render_command.uniform_spec.push_constants_size()];
+                render_command
+                    .uniform_spec
+                    .get_push_constants_data(uniform_update_input, &mut push_constants);
+                Ok(DrawCommandData {
+                    graphics_pipeline: self.pipelines.get(&render_command.pipeline_key)?,
+                    vertex_buffer: render_command.mesh.get_vertex_buffer(),
+                    index_buffer: render_command.mesh.get_index_buffer(),
+                    instance_buffer: render_command
+                        .instance_buffer
+                        .as_ref()
+                        .unwrap_or(&self.default_instance_buffer),
+                    uniform_descriptor_set,
+                    uniform_offset,
+                    submeshes: render_command
+                        .mesh
+                        .get_submeshes()
+                        .iter()
+                        .zip(render_command.submesh_descriptors.iter())
+                        .filter(|(_, descriptors)| descriptors.visible)
+                        .map(|(submesh, descriptors)| SubmeshDrawData {
+                            first_index: submesh.first_index,
+                            index_count: submesh.index_count,
+                            texture_descriptor_set: descriptors.descriptor_data.descriptor_set,
+                            bindless_texture_id: descriptors.bindless_texture_id,
+                        })
+                        .collect(),
+                    push_constants,
+                    bindless_descriptor_set: self
+                        .bindless_textures
+                        .as_ref()
+                        .map_or(vk::DescriptorSet::null(), |bindless_textures| {
+                            bindless_textures.descriptor_set
+                        }),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        match (
+            &self.render_graph,
+            &self.dependent_fields.render_graph_resources,
+        ) {
+            (Some(plan), Some(render_graph_resources)) => {
+                render_graph::record_render_graph_command_buffer(
+                    &self.logical_device,
+                    command_buffer,
+                    image_index,
+                    plan,
+                    render_graph_resources,
+                    &self.dependent_fields.render_target,
+                    &self.dependent_fields.image_views_container,
+                    &draw_list,
+                )?;
+            }
+            _ => command_buffers::record_command_buffer(
+                &self.logical_device,
+                command_buffer,
+                image_index,
+                &self.dependent_fields.framebuffers,
+                self.render_pass,
+                &self.dependent_fields.render_target,
+                &draw_list,
+            )?,
+        }
+
+        if let Some(frame_capture) = frame_capture {
+            frame_capture.record_copy(
+                &self.dependent_fields.render_target,
+                image_index,
+                command_buffer,
+                &self.logical_device,
+            )?;
+        }
+        unsafe {
+            self.logical_device.end_command_buffer(command_buffer)?;
+        }
+        Ok(())
+    }
+
+    /// Creates the swap-chain or, when rendering headless, the offscreen image that frames are rendered into
+    fn create_render_target(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        queue_indices: &QueueFamilyIndices,
+        surface_container: Option<&SurfaceContainer>,
+        window_size: &WindowSize,
+    ) -> Result<RenderTarget> {
+        let render_target = match surface_container {
+            Some(surface_container) => RenderTarget::SwapChain(SwapChainContainer::new(
+                instance,
+                physical_device,
+                logical_device,
+                surface_container,
+                window_size,
+                queue_indices,
+            )?),
+            None => RenderTarget::Offscreen(OffscreenImage::new(
+                allocator,
+                logical_device,
+                window_size,
+            )?),
+        };
+        Ok(render_target)
+    }
+
     /// Swapchain dependent fields are the ones that we need to recreate for example anytime that the window size changes
     fn create_swapchain_dependent_fields(
+        render_target: RenderTarget,
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
         logical_device: &ash::Device,
-        queue_indices: &QueueFamilyIndices,
-        surface_container: &SurfaceContainer,
         command_pool: &vk::CommandPool,
         queues: &QueueMap,
-        window_size: &WindowSize,
+        render_pass: vk::RenderPass,
         msaa_samples: vk::SampleCountFlags,
     ) -> Result<SwapChainDependentFields> {
-        let swap_chain_container = SwapChainContainer::new(
-            instance,
-            physical_device,
-            logical_device,
-            surface_container,
-            window_size,
-            queue_indices,
-        )?;
-
-        let image_views_container = ImageViews::new(logical_device, &swap_chain_container)?;
+        let image_views_container = ImageViews::new(logical_device, &render_target)?;
 
-        let color_resource = ColorResource::new(
-            msaa_samples,
-            instance,
-            logical_device,
-            physical_device,
-            &swap_chain_container,
-        )?;
+        let color_resource =
+            ColorResource::new(msaa_samples, allocator, logical_device, &render_target)?;
 
         let depth_resource = DepthResource::new(
             msaa_samples,
             instance,
-            logical_device,
             physical_device,
-            &swap_chain_container,
+            allocator,
+            logical_device,
+            &render_target,
             *command_pool,
             queues,
         )?;
 
+        let framebuffers = framebuffers::create_framebuffers(
+            logical_device,
+            render_pass,
+            &image_views_container,
+            depth_resource.depth_image_view,
+            &color_resource,
+            &render_target,
+        )?;
+
         Ok(SwapChainDependentFields {
-            swap_chain_container,
+            render_target,
             image_views_container,
             color_resource,
             depth_resource,
+            framebuffers,
+            last_rendered_image_index: None,
+            render_graph_resources: None,
         })
     }
 
     /// The swap-chain is a series of framebuffers that are to be presented to the graphics display.
     /// If the display changes size, then we need to recreate it.
+    /// Since the viewport and scissor are dynamic state, only the objects that depend on the size of the render target are recreated.
+    /// The render pass and the pipelines are only recreated if the format of the render target changed.
     pub fn recreate_swap_chain(&mut self, window_size: &WindowSize) -> Result<()> {
+        let previous_format = self.dependent_fields.render_target.get_format();
         unsafe {
             self.logical_device.device_wait_idle()?;
-            if let Some(render_command) = &mut self.current_render_command {
-                render_command.cleanup_swap_chain(&self.logical_device, self.command_pool);
-            }
             self.cleanup_swap_chain();
         }
 
-        self.dependent_fields = Self::create_swapchain_dependent_fields(
+        let render_target = Self::create_render_target(
             &self.instance,
             self.physical_device,
+            &mut self.allocator,
             &self.logical_device,
             &self.queue_indices,
-            &self.surface_container,
+            self.surface_container.as_ref(),
+            window_size,
+        )?;
+
+        if render_target.get_format() != previous_format {
+            self.recreate_render_pass(&render_target)?;
+        }
+
+        self.dependent_fields = Self::create_swapchain_dependent_fields(
+            render_target,
+            &self.instance,
+            self.physical_device,
+            &mut self.allocator,
+            &self.logical_device,
             &self.command_pool,
             &self.queues,
-            window_size,
+            self.render_pass,
             self.msaa_samples,
         )?;
 
-        if let Some(render_command) = &mut self.current_render_command {
-            render_command.dependent_fields = Self::create_render_command_swap_chain_fields(
-                render_command.vertex_shader_file.as_path(),
-                render_command.fragment_shader_file.as_path(),
-                &render_command.uniform_spec,
-                &self.instance,
-                self.physical_device,
-                &self.logical_device,
-                &render_command.texture_image,
-                &render_command.vertex_buffer,
-                &render_command.index_buffer,
-                &self.command_pool,
-                render_command.uniform_descriptors,
-                &self.dependent_fields,
-                self.msaa_samples,
-            )?;
+        self.create_render_graph_resources()
+    }
+
+    /// Recreates the render pass for the format of the `render_target` together with all pipelines that use it.
+    fn recreate_render_pass(&mut self, render_target: &RenderTarget) -> Result<()> {
+        unsafe {
+            self.logical_device
+                .destroy_render_pass(self.render_pass, None);
         }
 
-        Ok(())
+        self.render_pass = create_render_pass(
+            &self.instance,
+            &self.logical_device,
+            self.physical_device,
+            render_target,
+            self.msaa_samples,
+        )?;
+
+        let pipeline_settings = self.get_pipeline_settings();
+        unsafe {
+            self.pipelines
+                .recreate_all(&self.logical_device, &pipeline_settings)
+        }
+    }
+
+    fn get_pipeline_settings(&self) -> PipelineSettings {
+        PipelineSettings {
+            render_pass: self.render_pass,
+            uniform_descriptors: self.uniform_descriptors,
+            msaa_samples: self.msaa_samples,
+            pipeline_cache: self.pipeline_cache.cache,
+            bindless_textures: self
+                .bindless_textures
+                .as_ref()
+                .map(|bindless_textures| bindless_textures.layout),
+        }
     }
 
     /// Check to see if we need to handle a resize of the display window.
@@ -518,39 +1238,55 @@ impl VulkanApp {
             return Ok(());
         }
 
-        if self.current_render_command.is_none() {
+        if self.render_commands.is_empty() && self.skybox.is_none() && self.render_graph.is_none() {
             info!("No render command was submitted");
-            return Ok(());
+            return self.destroy_finished_render_commands();
         };
 
+        // the uploads that were recorded since the last frame have to be submitted before the frame that uses them
+        self.upload_context.submit(&self.logical_device)?;
+        unsafe {
+            self.upload_context
+                .collect_finished(&mut self.allocator, &self.logical_device)?;
+        }
+
         let cpu_gpu_to_wait_for = [self.sync_container.get_in_flight_fence()];
         unsafe {
             self.logical_device
                 .wait_for_fences(&cpu_gpu_to_wait_for, true, u64::MAX)?;
         }
+        self.destroy_finished_render_commands()?;
 
         // get an available image from the swapchain
-        let timeout = u64::MAX;
-        let acquire_result = unsafe {
-            self.dependent_fields
-                .swap_chain_container
-                .swap_chain_loader
-                .acquire_next_image(
-                    self.dependent_fields.swap_chain_container.swap_chain,
-                    timeout,
-                    self.sync_container.get_image_available_semaphore(),
-                    vk::Fence::null(),
-                )
+        // when rendering offscreen there is only the one image, so there is nothing to acquire
+        let available_image_index_u32 = match &self.dependent_fields.render_target {
+            RenderTarget::SwapChain(swap_chain_container) => {
+                let timeout = u64::MAX;
+                let acquire_result = unsafe {
+                    swap_chain_container.swap_chain_loader.acquire_next_image(
+                        swap_chain_container.swap_chain,
+                        timeout,
+                        self.sync_container.get_image_available_semaphore(),
+                        vk::Fence::null(),
+                    )
+                };
+                if self.handle_resize(
+                    ResizeDetectedLocation::InAcquire,
+                    &acquire_result,
+                    window_size,
+                )? {
+                    return Ok(());
+                }
+                let (available_image_index_u32, _) = acquire_result?;
+                available_image_index_u32
+            }
+            RenderTarget::Offscreen(_) => 0,
         };
-        if self.handle_resize(
-            ResizeDetectedLocation::InAcquire,
-            &acquire_result,
-            window_size,
-        )? {
-            return Ok(());
-        }
-        let (available_image_index_u32, _) = acquire_result?;
         let available_image_index = usize::try_from(available_image_index_u32)?;
+        let is_presenting = matches!(
+            self.dependent_fields.render_target,
+            RenderTarget::SwapChain(_)
+        );
 
         // wait on fence to see if image isn't being used already by an in-flight frame
         if self
@@ -572,39 +1308,65 @@ impl VulkanApp {
             self.sync_container.get_in_flight_fence(),
         );
 
-        // after image is acquired from swap chain we can update the uniform buffer for that swap chain
+        // we waited on the fence of this frame so its uniform ring buffer is no longer in use and can be written again
+        let render_target_extent = self.dependent_fields.render_target.get_extent();
         let uniform_update_input = UniformUpdateInput {
-            swapchain_image_width: self
-                .dependent_fields
-                .swap_chain_container
-                .swap_chain_extent
-                .width,
-            swapchain_image_height: self
-                .dependent_fields
-                .swap_chain_container
-                .swap_chain_extent
-                .height,
+            swapchain_image_width: render_target_extent.width,
+            swapchain_image_height: render_target_extent.height,
             apptime,
         };
-        self.update_uniform(uniform_update_input, available_image_index)?;
+        let uniform_offsets = if self.render_commands.is_empty() && self.skybox.is_none() {
+            Vec::new()
+        } else {
+            self.update_uniform(uniform_update_input)?
+        };
 
         // specify that we want to delay the execution of the submit of the command buffer
         // specificially, we want to wait until the wiriting to the color attachment is done on the available image
-        let wait_semaphores = [self.sync_container.get_image_available_semaphore()];
+        // an offscreen image is never acquired or presented, so there are no semaphores to wait on or signal
+        let wait_semaphores = if is_presenting {
+            vec![self.sync_container.get_image_available_semaphore()]
+        } else {
+            Vec::new()
+        };
         let wait_semaphores_count = u32::try_from(wait_semaphores.len())?;
         let wait_stages: Vec<_> = wait_semaphores
             .iter()
             .map(|_x| vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
             .collect();
 
-        let command_buffer_ptr = RenderCommand::get_command_buffer_at(
-            &self.current_render_command,
-            available_image_index,
-        )?;
+        // we waited on the fence of this frame so its command buffer is no longer in use and can be recorded again
+        let frame_idx = self.sync_container.get_current_frame_idx();
+        if frame_idx >= self.command_buffers.len() {
+            return Err(VulkanError::CommandBufferNotAvailable(frame_idx));
+        }
+        let command_buffer_ptr = &self.command_buffers[frame_idx];
 
-        let signal_semaphores = [self.sync_container.get_render_finished_semaphore()];
+        let signal_semaphores = if is_presenting {
+            vec![self.sync_container.get_render_finished_semaphore()]
+        } else {
+            Vec::new()
+        };
         let signal_semaphores_count = u32::try_from(signal_semaphores.len())?;
 
+        // the requested capture is copied by this frame, since a presented image can't be read anymore
+        let frame_capture = if self.capture_requested {
+            Some(FrameCapture::new(
+                &self.dependent_fields.render_target,
+                &mut self.allocator,
+                &self.logical_device,
+            )?)
+        } else {
+            None
+        };
+        let record_result = self.record_command_buffer(
+            *command_buffer_ptr,
+            available_image_index,
+            uniform_update_input,
+            &uniform_offsets,
+            frame_capture.as_ref(),
+        );
+
         let command_buffer_submit_infos = [vk::SubmitInfo {
             wait_semaphore_count: wait_semaphores_count,
             p_wait_semaphores: wait_semaphores.as_ptr(),
@@ -616,19 +1378,41 @@ impl VulkanApp {
             ..Default::default()
         }];
 
-        let graphics_queue = self.queues.get_graphics_queue()?;
         let cpu_gpu_fence = self.sync_container.get_in_flight_fence();
-        unsafe {
-            self.logical_device.reset_fences(&[cpu_gpu_fence])?;
-            self.logical_device.queue_submit(
-                graphics_queue,
-                &command_buffer_submit_infos,
-                cpu_gpu_fence,
-            )?
+        let submit_result = record_result
+            .and_then(|_| self.queues.get_graphics_queue())
+            .and_then(|graphics_queue| unsafe {
+                self.logical_device.reset_fences(&[cpu_gpu_fence])?;
+                self.logical_device.queue_submit(
+                    graphics_queue,
+                    &command_buffer_submit_infos,
+                    cpu_gpu_fence,
+                )?;
+                Ok(())
+            });
+        if let Err(error) = submit_result {
+            if let Some(frame_capture) = frame_capture {
+                unsafe { frame_capture.destroy(&mut self.allocator, &self.logical_device) };
+            }
+            return Err(error);
+        }
+        self.sync_container.mark_frame_submitted();
+        self.dependent_fields.last_rendered_image_index = Some(available_image_index);
+        if frame_capture.is_some() {
+            self.pending_capture = frame_capture;
+            self.capture_requested = false;
         }
 
+        let swap_chain_container = match &self.dependent_fields.render_target {
+            RenderTarget::SwapChain(swap_chain_container) => swap_chain_container,
+            RenderTarget::Offscreen(_) => {
+                self.sync_container.update_frame_counter();
+                return Ok(());
+            }
+        };
+
         // present the image to swap chain
-        let swap_chains = [self.dependent_fields.swap_chain_container.swap_chain];
+        let swap_chains = [swap_chain_container.swap_chain];
         let swap_chain_count = u32::try_from(swap_chains.len())?;
         let present_info = vk::PresentInfoKHR {
             wait_semaphore_count: signal_semaphores_count,
@@ -641,8 +1425,7 @@ impl VulkanApp {
 
         let present_queue = self.queues.get_present_queue()?;
         let present_result = unsafe {
-            self.dependent_fields
-                .swap_chain_container
+            swap_chain_container
                 .swap_chain_loader
                 .queue_present(present_queue, &present_info)
         };
@@ -658,6 +1441,65 @@ impl VulkanApp {
         Ok(())
     }
 
+    /// Copies the next frame that is drawn, before it is presented, so that `capture_frame` can read it afterwards.
+    /// This is needed to capture swap chain images, which belong to the presentation engine once they were presented.
+    pub fn request_frame_capture(&mut self) -> Result<()> {
+        // a capture which was never read is replaced by the new one
+        if let Some(frame_capture) = self.pending_capture.take() {
+            self.wait_until_device_idle()?;
+            unsafe { frame_capture.destroy(&mut self.allocator, &self.logical_device) };
+        }
+        self.capture_requested = true;
+        Ok(())
+    }
+
+    /// Reads the frame that was copied after `request_frame_capture` back from the gpu as RGBA8 pixels.
+    /// When rendering offscreen the most recently rendered frame can also be read without requesting it first.
+    /// This blocks until the device is idle, so it should only be used for things like bug reports and image comparisons.
+    pub fn capture_frame(&mut self) -> Result<image::RgbaImage> {
+        if self.pending_capture.is_some() {
+            self.wait_until_device_idle()?;
+            if let Some(frame_capture) = self.pending_capture.take() {
+                return unsafe { frame_capture.read(&mut self.allocator, &self.logical_device) };
+            }
+        }
+        if let RenderTarget::SwapChain(_) = self.dependent_fields.render_target {
+            return Err(VulkanError::CaptureUnsupported(String::from(
+                "swap chain images can only be captured by the frame that draws them, call request_frame_capture before drawing it",
+            )));
+        }
+        let image_index = self
+            .dependent_fields
+            .last_rendered_image_index
+            .ok_or(VulkanError::CaptureNoFrameRendered)?;
+        self.wait_until_device_idle()?;
+
+        frame_capture::capture_render_target_image(
+            &self.dependent_fields.render_target,
+            image_index,
+            &mut self.allocator,
+            &self.logical_device,
+            self.command_pool,
+            &self.queues,
+        )
+    }
+
+    /// Captures the most recently rendered frame and saves it to `file`. The image format is picked from the file extension (e.g. png).
+    pub fn capture_frame_to_file(&mut self, file: &Path) -> Result<()> {
+        let captured_frame = self.capture_frame()?;
+        captured_frame
+            .save(file)
+            .map_err(|source| VulkanError::CaptureSaveFailure {
+                source,
+                file: file.to_path_buf(),
+            })
+    }
+
+    /// Returns how many memory blocks the allocator holds in each memory heap and how much of them is used.
+    pub fn get_memory_statistics(&self) -> MemoryStatistics {
+        self.allocator.get_statistics()
+    }
+
     /// Block until all operations on queues are done.
     pub fn wait_until_device_idle(&self) -> Result<()> {
         unsafe {
@@ -666,31 +1508,29 @@ impl VulkanApp {
         Ok(())
     }
 
-    /// This function fills the device memory with the the uniform data.
+    /// This function fills the uniform ring buffer of the current frame with the the uniform data of every render command.
     /// Uniforms objects are something that only the user of mimic knows about.
     /// As far as mimic is concerned everything around a uniform (its type, its size) is encapsulated inside the uniform_metadata on the rendercommand
-    /// However, we still need to update the uniforms and ensure that this happens with the correct synchronization barriers
-    pub fn update_uniform(
-        &self,
-        frame_data_input: UniformUpdateInput,
-        swapchain_image_index: usize,
-    ) -> Result<()> {
-        if let Some(render_command) = &self.current_render_command {
-            if swapchain_image_index >= render_command.dependent_fields.uniform_buffers.len() {
-                return Err(VulkanError::UniformBufferNotAvailable(
-                    swapchain_image_index,
-                ));
-            }
-            memory::fill_uniform_buffer(
-                frame_data_input,
-                &render_command.uniform_spec,
-                &self.logical_device,
-                render_command.dependent_fields.uniform_buffers[swapchain_image_index].memory,
-            )?;
-            Ok(())
-        } else {
-            Err(VulkanError::RenderCommandNotAvailable)
+    /// Returns the dynamic offsets of the uniform data of the render commands in the order in which they are drawn.
+    /// The frame must not be in use by the GPU, so the in-flight fence of the frame has to be waited on before.
+    pub fn update_uniform(&mut self, frame_data_input: UniformUpdateInput) -> Result<Vec<u32>> {
+        if self.render_commands.is_empty() && self.skybox.is_none() {
+            return Err(VulkanError::RenderCommandNotAvailable);
         }
+        // the same order as the draw list in `record_command_buffer`
+        let uniform_specs: Vec<_> = self
+            .skybox
+            .iter()
+            .chain(self.render_commands.values())
+            .map(|render_command| render_command.uniform_spec.as_ref())
+            .collect();
+        self.uniform_ring_buffer.write_frame(
+            self.sync_container.get_current_frame_idx(),
+            frame_data_input,
+            &uniform_specs,
+            &mut self.allocator,
+            &self.logical_device,
+        )
     }
 
     /// Create an Ash instance.
@@ -700,6 +1540,8 @@ impl VulkanApp {
         engine_name: &str,
         entry: &ash::Entry,
         validation: &VulkanValidation,
+        extension_names: &[*const i8],
+        api_version: u32,
     ) -> Result<ash::Instance> {
         if !(validation.check_validation_layer_support(entry)?) {
             return Err(VulkanError::RequiredValidationLayersUnsupported);
@@ -714,11 +1556,9 @@ impl VulkanApp {
             application_version: vk::make_version(1, 0, 0),
             p_engine_name: engine_name.as_ptr(),
             engine_version: vk::make_version(1, 0, 0),
-            api_version: vk::make_api_version(0, 1, 0, 0),
+            api_version,
         };
 
-        let extension_names = util::platform::required_extension_names();
-
         let debug_create_info = VulkanDebug::get_creation_destruction_debug_create_info(validation);
         let debug_create_info_ptr = if let Some(debug_create_info) = debug_create_info {
             (&debug_create_info as *const vk::DebugUtilsMessengerCreateInfoEXT)
@@ -745,75 +1585,69 @@ impl VulkanApp {
 
     /// Cleanup all objects that depend on the swap chain
     unsafe fn cleanup_swap_chain(&mut self) {
-        std::mem::take(&mut self.dependent_fields.color_resource).drop(&self.logical_device);
-        std::mem::take(&mut self.dependent_fields.depth_resource).drop(&self.logical_device);
+        if let Some(render_graph_resources) = self.dependent_fields.render_graph_resources.take() {
+            render_graph_resources.destroy(&mut self.allocator, &self.logical_device);
+        }
+
+        for framebuffer in self.dependent_fields.framebuffers.iter() {
+            self.logical_device.destroy_framebuffer(*framebuffer, None);
+        }
+
+        std::mem::take(&mut self.dependent_fields.color_resource)
+            .drop(&mut self.allocator, &self.logical_device);
+        std::mem::take(&mut self.dependent_fields.depth_resource)
+            .drop(&mut self.allocator, &self.logical_device);
 
         for &image_view in &self.dependent_fields.image_views_container.image_views {
             self.logical_device.destroy_image_view(image_view, None);
         }
-        self.dependent_fields
-            .swap_chain_container
-            .swap_chain_loader
-            .destroy_swapchain(self.dependent_fields.swap_chain_container.swap_chain, None);
-    }
-}
-
-impl RenderCommand {
-    fn get_command_buffer_at(
-        optional_self: &Option<Self>,
-        available_image_index: usize,
-    ) -> Result<&vk::CommandBuffer> {
-        if let Some(render_command) = optional_self {
-            if !render_command.dependent_fields.command_buffers.is_empty() {
-                Ok(&render_command.dependent_fields.command_buffers[available_image_index])
-            } else {
-                Err(VulkanError::CommandBufferNotAvailable(
-                    available_image_index,
-                ))
+        match std::mem::replace(
+            &mut self.dependent_fields.render_target,
+            RenderTarget::Offscreen(OffscreenImage::default()),
+        ) {
+            RenderTarget::SwapChain(swap_chain_container) => swap_chain_container
+                .swap_chain_loader
+                .destroy_swapchain(swap_chain_container.swap_chain, None),
+            RenderTarget::Offscreen(offscreen_image) => {
+                offscreen_image.drop(&mut self.allocator, &self.logical_device)
             }
-        } else {
-            Err(VulkanError::RenderCommandNotAvailable)
         }
     }
+}
 
-    unsafe fn cleanup_swap_chain(
-        &mut self,
+impl RenderCommand {
+    /// The assets of the render command are not freed here since other render commands may still use them.
+    /// They are freed by the asset manager once this render command is dropped.
+    /// The same goes for the pipeline which is released to the pipeline store.
+    unsafe fn cleanup(
+        mut self,
+        allocator: &mut MemoryAllocator,
         logical_device: &ash::Device,
-        command_pool: vk::CommandPool,
+        mut bindless_textures: Option<&mut BindlessTextures>,
     ) {
-        for uniform_buffer in self.dependent_fields.uniform_buffers.iter() {
-            logical_device.destroy_buffer(uniform_buffer.buffer, None);
-            logical_device.free_memory(uniform_buffer.memory, None);
+        for submesh_descriptors in self.submesh_descriptors.drain(..) {
+            submesh_descriptors.cleanup(logical_device, bindless_textures.as_deref_mut());
         }
 
-        for framebuffer in self.dependent_fields.framebuffers.iter() {
-            logical_device.destroy_framebuffer(*framebuffer, None);
+        if let Some(instance_buffer) = self.instance_buffer.take() {
+            instance_buffer.cleanup(allocator, logical_device);
         }
-
-        logical_device.destroy_pipeline(self.dependent_fields.graphics_pipeline.pipeline, None);
-        logical_device.destroy_pipeline_layout(
-            self.dependent_fields.graphics_pipeline.pipeline_layout,
-            None,
-        );
-
-        logical_device
-            .destroy_render_pass(self.dependent_fields.graphics_pipeline.render_pass, None);
-
-        // the descriptor sets are cleared automatically when the pool is cleared
-        logical_device
-            .destroy_descriptor_pool(self.dependent_fields.descriptor_data.descriptor_pool, None);
-
-        logical_device.free_command_buffers(command_pool, &self.dependent_fields.command_buffers);
     }
+}
 
-    unsafe fn cleanup(&mut self, logical_device: &ash::Device, command_pool: vk::CommandPool) {
-        self.cleanup_swap_chain(logical_device, command_pool);
-
-        std::mem::take(&mut self.texture_image).cleanup(logical_device);
-        std::mem::take(&mut self.index_buffer).cleanup(logical_device);
-        std::mem::take(&mut self.vertex_buffer).cleanup(logical_device);
-
-        logical_device.destroy_descriptor_set_layout(self.uniform_descriptors, None);
+impl SubmeshDescriptors {
+    unsafe fn cleanup(
+        self,
+        logical_device: &ash::Device,
+        bindless_textures: Option<&mut BindlessTextures>,
+    ) {
+        // the descriptor set is cleared automatically when the pool is cleared
+        logical_device.destroy_descriptor_pool(self.descriptor_data.descriptor_pool, None);
+        if let (Some(bindless_textures), Some(texture_id)) =
+            (bindless_textures, self.bindless_texture_id)
+        {
+            bindless_textures.remove(texture_id);
+        }
     }
 }
 
@@ -821,19 +1655,70 @@ impl Drop for VulkanApp {
     fn drop(&mut self) {
         info!("VulkanApp exiting");
         unsafe {
-            if let Some(mut render_command) = std::mem::take(&mut self.current_render_command) {
-                render_command.cleanup(&self.logical_device, self.command_pool);
+            self.upload_context
+                .destroy(&mut self.allocator, &self.logical_device);
+            if let Some(frame_capture) = self.pending_capture.take() {
+                frame_capture.destroy(&mut self.allocator, &self.logical_device);
+            }
+            for (_, render_command) in std::mem::take(&mut self.render_commands) {
+                render_command.cleanup(
+                    &mut self.allocator,
+                    &self.logical_device,
+                    self.bindless_textures.as_mut(),
+                );
+            }
+            if let Some(skybox) = self.skybox.take() {
+                skybox.cleanup(
+                    &mut self.allocator,
+                    &self.logical_device,
+                    self.bindless_textures.as_mut(),
+                );
+            }
+            for (_, render_command) in std::mem::take(&mut self.removed_render_commands) {
+                render_command.cleanup(
+                    &mut self.allocator,
+                    &self.logical_device,
+                    self.bindless_textures.as_mut(),
+                );
             }
+            self.pipelines.destroy(&self.logical_device);
+            // the array holds handles to textures, so it goes before the asset manager
+            if let Some(mut bindless_textures) = self.bindless_textures.take() {
+                bindless_textures.destroy(&self.logical_device);
+            }
+            self.assets
+                .destroy(&mut self.allocator, &self.logical_device);
+            self.uniform_ring_buffer
+                .destroy(&mut self.allocator, &self.logical_device);
+            self.logical_device
+                .destroy_descriptor_set_layout(self.uniform_descriptors, None);
+            std::mem::take(&mut self.default_instance_buffer)
+                .cleanup(&mut self.allocator, &self.logical_device);
             self.cleanup_swap_chain();
+            self.logical_device
+                .destroy_render_pass(self.render_pass, None);
+
+            // drop can't return errors so a cache that fails to save is only logged
+            if let Err(error) = self.pipeline_cache.save(&self.logical_device) {
+                info!("{}", error);
+            }
+            self.pipeline_cache.destroy(&self.logical_device);
 
             self.sync_container.destroy(&self.logical_device);
+            info!(
+                "Device memory at exit:\n{}",
+                self.allocator.get_statistics()
+            );
+            self.allocator.destroy(&self.logical_device);
             self.logical_device
                 .destroy_command_pool(self.command_pool, None);
 
             self.logical_device.destroy_device(None);
-            self.surface_container
-                .surface_loader
-                .destroy_surface(self.surface_container.surface, None);
+            if let Some(surface_container) = &self.surface_container {
+                surface_container
+                    .surface_loader
+                    .destroy_surface(surface_container.surface, None);
+            }
             self.debug.destroy_debug_messenger();
             self.instance.destroy_instance(None);
         }
@@ -0,0 +1,199 @@
+use crate::{
+    backend::assets::ShaderHandle,
+    graphics_pipeline::{
+        pipeline_descriptor::PipelineDescriptor, shader_reflection, GraphicsPipeline,
+    },
+    models::vertex::VertexLayout,
+    uniforms::descriptors::TextureDescriptorLayout,
+    util::result::{Result, VulkanError},
+};
+use ash::vk;
+use std::collections::HashMap;
+//////////////////////// Structs ///////////////////////
+/// Identifies a pipeline in the `PipelineStore` by the shaders, fixed function state and vertex layout it was created with.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    vertex_shader: vk::ShaderModule,
+    fragment_shader: vk::ShaderModule,
+    descriptor: PipelineDescriptor,
+    vertex_layout: VertexLayout,
+}
+
+/// The settings that are the same for every pipeline in the `PipelineStore`.
+pub struct PipelineSettings {
+    pub render_pass: vk::RenderPass,
+    /// the layout of set 0, which binds the uniform data of every render command
+    pub uniform_descriptors: vk::DescriptorSetLayout,
+    pub msaa_samples: vk::SampleCountFlags,
+    pub pipeline_cache: vk::PipelineCache,
+    /// the layout of set 2, which binds the bindless texture array if bindless textures are enabled
+    pub bindless_textures: Option<vk::DescriptorSetLayout>,
+}
+
+/// Shares graphics pipelines between all render commands that use the same shaders and `PipelineDescriptor`.
+/// A pipeline is destroyed once the last render command that acquired it releases it.
+#[derive(Default)]
+pub struct PipelineStore {
+    pipelines: HashMap<PipelineKey, StoredPipeline>,
+}
+
+struct StoredPipeline {
+    graphics_pipeline: GraphicsPipeline,
+    /// the layout of set 1, which is reflected from the shaders of the pipeline
+    texture_descriptors: TextureDescriptorLayout,
+    user_count: usize,
+}
+//////////////////////// Impls ///////////////////////
+impl PipelineStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the key of the pipeline for the shaders, `descriptor` and `vertex_layout`, creating the pipeline if it doesn't exist yet.
+    /// The descriptor set layouts and vertex inputs of a new pipeline are checked against the reflected interface of its shaders.
+    /// Every acquired key has to be released again using `release`.
+    pub fn acquire(
+        &mut self,
+        vertex_shader: &ShaderHandle,
+        fragment_shader: &ShaderHandle,
+        descriptor: &PipelineDescriptor,
+        vertex_layout: &VertexLayout,
+        logical_device: &ash::Device,
+        settings: &PipelineSettings,
+    ) -> Result<PipelineKey> {
+        let key = PipelineKey {
+            vertex_shader: vertex_shader.get_shader_module(),
+            fragment_shader: fragment_shader.get_shader_module(),
+            descriptor: *descriptor,
+            vertex_layout: vertex_layout.clone(),
+        };
+
+        if let Some(stored_pipeline) = self.pipelines.get_mut(&key) {
+            stored_pipeline.user_count += 1;
+            return Ok(key);
+        }
+
+        vertex_shader.get_reflection().check_vertex_inputs(
+            &GraphicsPipeline::get_vertex_input_attributes(vertex_layout)?,
+        )?;
+        let texture_bindings = shader_reflection::reflect_texture_bindings(
+            vertex_shader.get_reflection(),
+            fragment_shader.get_reflection(),
+            settings.bindless_textures.is_some(),
+        )?;
+        let texture_descriptors = TextureDescriptorLayout::new(texture_bindings, logical_device)?;
+
+        let graphics_pipeline =
+            match Self::create_pipeline(&key, &texture_descriptors, logical_device, settings) {
+                Ok(graphics_pipeline) => graphics_pipeline,
+                Err(error) => {
+                    unsafe { texture_descriptors.destroy(logical_device) };
+                    return Err(error);
+                }
+            };
+        self.pipelines.insert(
+            key.clone(),
+            StoredPipeline {
+                graphics_pipeline,
+                texture_descriptors,
+                user_count: 1,
+            },
+        );
+        Ok(key)
+    }
+
+    pub fn get(&self, key: &PipelineKey) -> Result<&GraphicsPipeline> {
+        self.pipelines
+            .get(key)
+            .map(|stored_pipeline| &stored_pipeline.graphics_pipeline)
+            .ok_or(VulkanError::PipelineNotAvailable)
+    }
+
+    /// Returns the layout of set 1 of the pipeline, which the texture descriptor sets of its render commands are allocated with.
+    pub fn get_texture_descriptors(&self, key: &PipelineKey) -> Result<&TextureDescriptorLayout> {
+        self.pipelines
+            .get(key)
+            .map(|stored_pipeline| &stored_pipeline.texture_descriptors)
+            .ok_or(VulkanError::PipelineNotAvailable)
+    }
+
+    /// # Safety
+    ///
+    /// If this was the last user of the pipeline, then the pipeline is destroyed, so it must not be used by any in-flight frame.
+    pub unsafe fn release(&mut self, key: &PipelineKey, logical_device: &ash::Device) {
+        let is_unused = match self.pipelines.get_mut(key) {
+            Some(stored_pipeline) => {
+                stored_pipeline.user_count -= 1;
+                stored_pipeline.user_count == 0
+            }
+            None => false,
+        };
+        if is_unused {
+            if let Some(stored_pipeline) = self.pipelines.remove(key) {
+                stored_pipeline.destroy(logical_device);
+            }
+        }
+    }
+
+    /// Recreates all pipelines with new `settings`, for example after the render pass changed.
+    ///
+    /// # Safety
+    ///
+    /// The old pipelines must not be used by any in-flight frame.
+    pub unsafe fn recreate_all(
+        &mut self,
+        logical_device: &ash::Device,
+        settings: &PipelineSettings,
+    ) -> Result<()> {
+        for (key, stored_pipeline) in self.pipelines.iter_mut() {
+            let graphics_pipeline = Self::create_pipeline(
+                key,
+                &stored_pipeline.texture_descriptors,
+                logical_device,
+                settings,
+            )?;
+            std::mem::replace(&mut stored_pipeline.graphics_pipeline, graphics_pipeline)
+                .destroy(logical_device);
+        }
+        Ok(())
+    }
+
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy all pipelines.
+    /// It must be called with valid vulkan state.
+    pub unsafe fn destroy(&mut self, logical_device: &ash::Device) {
+        for (_, stored_pipeline) in self.pipelines.drain() {
+            stored_pipeline.destroy(logical_device);
+        }
+    }
+
+    fn create_pipeline(
+        key: &PipelineKey,
+        texture_descriptors: &TextureDescriptorLayout,
+        logical_device: &ash::Device,
+        settings: &PipelineSettings,
+    ) -> Result<GraphicsPipeline> {
+        let mut descriptor_set_layouts =
+            vec![settings.uniform_descriptors, texture_descriptors.layout];
+        descriptor_set_layouts.extend(settings.bindless_textures);
+        GraphicsPipeline::new(
+            key.vertex_shader,
+            key.fragment_shader,
+            &key.descriptor,
+            &key.vertex_layout,
+            logical_device,
+            settings.render_pass,
+            &descriptor_set_layouts,
+            settings.msaa_samples,
+            settings.pipeline_cache,
+        )
+    }
+}
+
+impl StoredPipeline {
+    unsafe fn destroy(self, logical_device: &ash::Device) {
+        self.graphics_pipeline.destroy(logical_device);
+        self.texture_descriptors.destroy(logical_device);
+    }
+}
@@ -1,4 +1,6 @@
 pub mod buffer;
 pub mod index_buffer;
+pub mod instance_buffer;
+pub mod material_buffer;
 pub mod memory;
 pub mod vertex_buffer;
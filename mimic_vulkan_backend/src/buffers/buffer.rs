@@ -1,6 +1,9 @@
-use crate::buffers::memory;
+use crate::buffers::memory::{
+    self,
+    allocator::{Allocation, MemoryAllocator},
+};
 
-use crate::devices::queues::QueueMap;
+use crate::drawing::upload_context::UploadContext;
 use crate::util::result::Result;
 
 use ash::vk;
@@ -8,13 +11,12 @@ use ash::vk;
 #[derive(Default)]
 pub struct Buffer {
     pub buffer: vk::Buffer,
-    pub memory: vk::DeviceMemory,
+    pub allocation: Allocation,
 }
 //////////////////////// Impls ///////////////////////
 impl Buffer {
     pub fn new(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
         logical_device: &ash::Device,
         size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
@@ -29,26 +31,30 @@ impl Buffer {
 
         let vertex_buffer = unsafe { logical_device.create_buffer(&buffer_create_info, None)? };
 
-        let vertex_buffer_memory = memory::create_device_memory(
-            instance,
-            physical_device,
+        let allocation = match allocator.allocate_for_buffer(
             logical_device,
             vertex_buffer,
             memory_property_requirements,
-        )?;
+        ) {
+            Ok(allocation) => allocation,
+            Err(error) => {
+                unsafe { logical_device.destroy_buffer(vertex_buffer, None) };
+                return Err(error);
+            }
+        };
 
         Ok(Self {
             buffer: vertex_buffer,
-            memory: vertex_buffer_memory,
+            allocation,
         })
     }
 
+    /// Creates a device local buffer and records the upload of `data` into the `upload_context`.
+    /// The buffer can be used by graphics work that is submitted after the upload context.
     pub fn create_and_fill<T>(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
         logical_device: &ash::Device,
-        command_pool: vk::CommandPool,
-        queues: &QueueMap,
+        upload_context: &mut UploadContext,
         buffer_size: vk::DeviceSize,
         data: &[T],
         usage: vk::BufferUsageFlags,
@@ -57,43 +63,55 @@ impl Buffer {
         [T]: memory::MemoryCopyable,
     {
         let staging_buffer = Buffer::new(
-            instance,
-            physical_device,
+            allocator,
             logical_device,
             buffer_size,
             vk::BufferUsageFlags::TRANSFER_SRC,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
 
-        unsafe {
-            // IMPORTANT: this only works with array slices due to the copy_non_overlapping
-            // it does not work with data being a Vec
-            memory::fill_buffer(logical_device, staging_buffer.memory, data)?;
+        // IMPORTANT: this only works with array slices due to the copy_non_overlapping
+        // it does not work with data being a Vec
+        if let Err(error) = unsafe { memory::fill_buffer(&staging_buffer.allocation, data) } {
+            unsafe { staging_buffer.destroy(allocator, logical_device) };
+            return Err(error);
         }
 
-        let result_buffer = Buffer::new(
-            instance,
-            physical_device,
+        let result_buffer = match Buffer::new(
+            allocator,
             logical_device,
             buffer_size,
             vk::BufferUsageFlags::TRANSFER_DST | usage,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        )?;
+        ) {
+            Ok(result_buffer) => result_buffer,
+            Err(error) => {
+                unsafe { staging_buffer.destroy(allocator, logical_device) };
+                return Err(error);
+            }
+        };
 
-        memory::copy_buffer(
-            staging_buffer.buffer,
+        // the upload context frees the staging buffer once the copy is done
+        if let Err(error) = upload_context.copy_to_buffer(
+            staging_buffer,
             result_buffer.buffer,
             buffer_size,
+            usage,
             logical_device,
-            command_pool,
-            queues,
-        )?;
-
-        unsafe {
-            logical_device.destroy_buffer(staging_buffer.buffer, None);
-            logical_device.free_memory(staging_buffer.memory, None);
+        ) {
+            unsafe { result_buffer.destroy(allocator, logical_device) };
+            return Err(error);
         }
 
         Ok(result_buffer)
     }
+
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy the buffer and free its memory.
+    /// It must be called with valid vulkan state.
+    pub unsafe fn destroy(&self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
+        logical_device.destroy_buffer(self.buffer, None);
+        allocator.free(&self.allocation, logical_device);
+    }
 }
@@ -1,5 +1,7 @@
 use crate::{
-    buffers::buffer::Buffer, devices::queues::QueueMap, models::index::IndexType,
+    buffers::{buffer::Buffer, memory::allocator::MemoryAllocator},
+    drawing::upload_context::UploadContext,
+    models::index::IndexType,
     util::result::Result,
 };
 use ash::vk;
@@ -14,20 +16,16 @@ pub struct IndexBuffer {
 impl IndexBuffer {
     pub fn new(
         indices: &[IndexType],
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
         logical_device: &ash::Device,
-        command_pool: vk::CommandPool,
-        queues: &QueueMap,
+        upload_context: &mut UploadContext,
     ) -> Result<Self> {
         let size = vk::DeviceSize::try_from(std::mem::size_of::<IndexType>() * indices.len())?;
 
         let index_buffer = Buffer::create_and_fill(
-            instance,
-            physical_device,
+            allocator,
             logical_device,
-            command_pool,
-            queues,
+            upload_context,
             size,
             &indices,
             vk::BufferUsageFlags::INDEX_BUFFER,
@@ -39,9 +37,8 @@ impl IndexBuffer {
         })
     }
 
-    pub unsafe fn cleanup(self, logical_device: &ash::Device) {
-        logical_device.destroy_buffer(self.data.buffer, None);
-        logical_device.free_memory(self.data.memory, None);
+    pub unsafe fn cleanup(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
+        self.data.destroy(allocator, logical_device);
     }
 
     pub fn get_rectangle_indices() -> [IndexType; 12] {
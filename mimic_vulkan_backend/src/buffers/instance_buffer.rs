@@ -0,0 +1,47 @@
+use crate::{
+    buffers::{buffer::Buffer, memory::allocator::MemoryAllocator},
+    drawing::upload_context::UploadContext,
+    models::instance::InstanceData,
+    util::result::Result,
+};
+use ash::vk;
+use std::convert::TryFrom;
+//////////////////////// Structs ///////////////////////
+#[derive(Default)]
+pub struct InstanceBuffer {
+    pub data: Buffer,
+    pub instance_count: usize,
+}
+//////////////////////// Impls ///////////////////////
+impl InstanceBuffer {
+    pub fn new(
+        instances: &[InstanceData],
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        upload_context: &mut UploadContext,
+    ) -> Result<Self> {
+        let size = vk::DeviceSize::try_from(std::mem::size_of_val(instances))?;
+
+        let instance_buffer = Buffer::create_and_fill(
+            allocator,
+            logical_device,
+            upload_context,
+            size,
+            instances,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        )?;
+
+        Ok(Self {
+            data: instance_buffer,
+            instance_count: instances.len(),
+        })
+    }
+
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy buffers and free memory.
+    /// It must be called with valid vulkan state.
+    pub unsafe fn cleanup(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
+        self.data.destroy(allocator, logical_device);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{
+    buffers::{
+        buffer::Buffer,
+        memory::allocator::{self, MemoryAllocator},
+    },
+    drawing::upload_context::UploadContext,
+    models::material::{MeshMaterial, MATERIAL_UNIFORM_SIZE},
+    util::result::Result,
+};
+use ash::vk;
+use std::convert::TryFrom;
+//////////////////////// Structs ///////////////////////
+/// The uniform data of the materials of a mesh, one slot per material.
+/// The slots are aligned so that each submesh can bind the slot of its material.
+#[derive(Default)]
+pub struct MaterialBuffer {
+    pub data: Buffer,
+    stride: vk::DeviceSize,
+}
+//////////////////////// Impls ///////////////////////
+impl MaterialBuffer {
+    pub fn new(
+        materials: &[MeshMaterial],
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        upload_context: &mut UploadContext,
+    ) -> Result<Self> {
+        let stride = allocator::align_up(
+            vk::DeviceSize::try_from(MATERIAL_UNIFORM_SIZE)?,
+            physical_device_properties
+                .limits
+                .min_uniform_buffer_offset_alignment,
+        );
+        let slot_size = usize::try_from(stride)?;
+
+        let mut bytes = vec![0u8; slot_size * materials.len()];
+        for (material, slot) in materials.iter().zip(bytes.chunks_exact_mut(slot_size)) {
+            for (value, target) in material
+                .get_uniform_data()
+                .iter()
+                .zip(slot.chunks_exact_mut(std::mem::size_of::<f32>()))
+            {
+                target.copy_from_slice(&value.to_ne_bytes());
+            }
+        }
+
+        let material_buffer = Buffer::create_and_fill(
+            allocator,
+            logical_device,
+            upload_context,
+            vk::DeviceSize::try_from(bytes.len())?,
+            &bytes,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+        )?;
+
+        Ok(Self {
+            data: material_buffer,
+            stride,
+        })
+    }
+
+    /// The range of the slot of the material with the index `material_idx`.
+    pub fn get_descriptor_info(&self, material_idx: usize) -> Result<vk::DescriptorBufferInfo> {
+        Ok(vk::DescriptorBufferInfo {
+            buffer: self.data.buffer,
+            offset: self.stride * vk::DeviceSize::try_from(material_idx)?,
+            range: vk::DeviceSize::try_from(MATERIAL_UNIFORM_SIZE)?,
+        })
+    }
+
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy buffers and free memory.
+    /// It must be called with valid vulkan state.
+    pub unsafe fn cleanup(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
+        self.data.destroy(allocator, logical_device);
+    }
+}
@@ -0,0 +1,535 @@
+use crate::{buffers::memory::find_memory_type_index, util::result::Result};
+use ash::vk;
+use log::{error, info};
+use std::{fmt, ptr};
+//////////////////////// Enums ///////////////////////
+/// Buffers and linearly tiled images are linear resources, optimally tiled images are non-linear.
+/// Linear and non-linear resources may not share a page of `bufferImageGranularity` bytes in one memory block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    Linear,
+    NonLinear,
+}
+//////////////////////// Structs ///////////////////////
+/// A range of a memory block which was handed out by the `MemoryAllocator`.
+/// Resources have to be bound to `memory` at `offset`.
+#[derive(Debug)]
+pub struct Allocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    memory_type_index: u32,
+    // null if the memory isn't host visible
+    mapped_ptr: *mut std::ffi::c_void,
+}
+
+/// Carves allocations out of large `vk::DeviceMemory` blocks so that we stay far below `maxMemoryAllocationCount`.
+/// Every memory type has its own list of blocks. Host visible blocks stay mapped for their whole lifetime.
+pub struct MemoryAllocator {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    buffer_image_granularity: vk::DeviceSize,
+    // indexed by memory type index
+    blocks: Vec<Vec<MemoryBlock>>,
+}
+
+/// The memory used by the allocator in one memory heap.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeapStatistics {
+    pub block_count: usize,
+    pub allocation_count: usize,
+    pub used_bytes: vk::DeviceSize,
+    pub free_bytes: vk::DeviceSize,
+}
+
+/// The memory used by the allocator, indexed by memory heap.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStatistics {
+    pub heaps: Vec<HeapStatistics>,
+}
+
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    mapped_ptr: *mut std::ffi::c_void,
+    // sorted by offset
+    ranges: Vec<UsedRange>,
+}
+
+#[derive(Clone, Copy)]
+struct UsedRange {
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    kind: ResourceKind,
+}
+//////////////////////// Impls ///////////////////////
+impl Default for Allocation {
+    fn default() -> Self {
+        Self {
+            memory: vk::DeviceMemory::null(),
+            offset: 0,
+            size: 0,
+            memory_type_index: 0,
+            mapped_ptr: ptr::null_mut(),
+        }
+    }
+}
+
+impl Allocation {
+    /// Returns the pointer to the start of the allocation if it was allocated from host visible memory.
+    pub fn get_mapped_ptr(&self) -> Option<*mut std::ffi::c_void> {
+        if self.mapped_ptr.is_null() {
+            None
+        } else {
+            Some(self.mapped_ptr)
+        }
+    }
+}
+
+impl MemoryAllocator {
+    const DEFAULT_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+    // small heaps, like the 256MB device local and host visible heap, should not be used up by just a few blocks
+    const MAX_HEAP_FRACTION: vk::DeviceSize = 8;
+
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> Self {
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let mut blocks = Vec::new();
+        blocks.resize_with(memory_properties.memory_type_count as usize, Vec::new);
+
+        Self {
+            memory_properties,
+            buffer_image_granularity: physical_device_properties.limits.buffer_image_granularity,
+            blocks,
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        logical_device: &ash::Device,
+        requirements: vk::MemoryRequirements,
+        memory_property_requirements: vk::MemoryPropertyFlags,
+        kind: ResourceKind,
+    ) -> Result<Allocation> {
+        let memory_type_index = find_memory_type_index(
+            &self.memory_properties,
+            requirements.memory_type_bits,
+            memory_property_requirements,
+        )?;
+        let buffer_image_granularity = self.buffer_image_granularity;
+        let preferred_block_size = self.get_preferred_block_size(memory_type_index);
+        let property_flags =
+            self.memory_properties.memory_types[memory_type_index as usize].property_flags;
+        let type_blocks = &mut self.blocks[memory_type_index as usize];
+
+        for block in type_blocks.iter_mut() {
+            if let Some(offset) = find_free_offset(
+                &block.ranges,
+                block.size,
+                requirements.size,
+                requirements.alignment,
+                kind,
+                buffer_image_granularity,
+            ) {
+                return Ok(block.insert(offset, requirements.size, kind, memory_type_index));
+            }
+        }
+
+        // allocations that don't fit into a default block get a block of their own
+        let block_size = requirements.size.max(preferred_block_size);
+        let mut block = MemoryBlock::new(
+            logical_device,
+            block_size,
+            memory_type_index,
+            property_flags,
+        )?;
+        let allocation = block.insert(0, requirements.size, kind, memory_type_index);
+        type_blocks.push(block);
+
+        Ok(allocation)
+    }
+
+    /// Allocates memory for `buffer` and binds it.
+    pub fn allocate_for_buffer(
+        &mut self,
+        logical_device: &ash::Device,
+        buffer: vk::Buffer,
+        memory_property_requirements: vk::MemoryPropertyFlags,
+    ) -> Result<Allocation> {
+        let requirements = unsafe { logical_device.get_buffer_memory_requirements(buffer) };
+        let allocation = self.allocate(
+            logical_device,
+            requirements,
+            memory_property_requirements,
+            ResourceKind::Linear,
+        )?;
+        unsafe { logical_device.bind_buffer_memory(buffer, allocation.memory, allocation.offset)? };
+        Ok(allocation)
+    }
+
+    /// Allocates memory for `image` and binds it.
+    pub fn allocate_for_image(
+        &mut self,
+        logical_device: &ash::Device,
+        image: vk::Image,
+        tiling: vk::ImageTiling,
+        memory_property_requirements: vk::MemoryPropertyFlags,
+    ) -> Result<Allocation> {
+        let requirements = unsafe { logical_device.get_image_memory_requirements(image) };
+        let kind = if tiling == vk::ImageTiling::LINEAR {
+            ResourceKind::Linear
+        } else {
+            ResourceKind::NonLinear
+        };
+        let allocation = self.allocate(
+            logical_device,
+            requirements,
+            memory_property_requirements,
+            kind,
+        )?;
+        unsafe { logical_device.bind_image_memory(image, allocation.memory, allocation.offset)? };
+        Ok(allocation)
+    }
+
+    /// Returns the range of `allocation` to its block. Blocks without any allocations are freed.
+    ///
+    /// # Safety
+    ///
+    /// The resource bound to the allocation must be destroyed and not be used by any in-flight frame.
+    pub unsafe fn free(&mut self, allocation: &Allocation, logical_device: &ash::Device) {
+        if allocation.memory == vk::DeviceMemory::null() {
+            return;
+        }
+        let type_blocks = &mut self.blocks[allocation.memory_type_index as usize];
+        let block_idx = match type_blocks
+            .iter()
+            .position(|block| block.memory == allocation.memory)
+        {
+            Some(block_idx) => block_idx,
+            None => {
+                error!("Tried to free an allocation which doesn't belong to any memory block");
+                return;
+            }
+        };
+
+        let block = &mut type_blocks[block_idx];
+        remove_range(&mut block.ranges, allocation.offset);
+        if block.ranges.is_empty() {
+            // freeing the memory also unmaps it
+            logical_device.free_memory(type_blocks.remove(block_idx).memory, None);
+        }
+    }
+
+    pub fn get_statistics(&self) -> MemoryStatistics {
+        let mut heaps =
+            vec![HeapStatistics::default(); self.memory_properties.memory_heap_count as usize];
+        for (memory_type_index, type_blocks) in self.blocks.iter().enumerate() {
+            let heap_index = self.memory_properties.memory_types[memory_type_index].heap_index;
+            let heap = &mut heaps[heap_index as usize];
+            for block in type_blocks {
+                let used_bytes: vk::DeviceSize = block.ranges.iter().map(|range| range.size).sum();
+                heap.block_count += 1;
+                heap.allocation_count += block.ranges.len();
+                heap.used_bytes += used_bytes;
+                heap.free_bytes += block.size - used_bytes;
+            }
+        }
+        MemoryStatistics { heaps }
+    }
+
+    /// Frees all memory blocks, even if they still contain allocations.
+    ///
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to free memory.
+    /// None of the memory may be used anymore.
+    pub unsafe fn destroy(&mut self, logical_device: &ash::Device) {
+        let leaked_count: usize = self
+            .blocks
+            .iter()
+            .flatten()
+            .map(|block| block.ranges.len())
+            .sum();
+        if leaked_count > 0 {
+            info!(
+                "{} memory allocations were not freed before the allocator was destroyed",
+                leaked_count
+            );
+        }
+        for block in self
+            .blocks
+            .iter_mut()
+            .flat_map(|type_blocks| type_blocks.drain(..))
+        {
+            logical_device.free_memory(block.memory, None);
+        }
+    }
+
+    fn get_preferred_block_size(&self, memory_type_index: u32) -> vk::DeviceSize {
+        let heap_index = self.memory_properties.memory_types[memory_type_index as usize].heap_index;
+        let heap_size = self.memory_properties.memory_heaps[heap_index as usize].size;
+        Self::DEFAULT_BLOCK_SIZE.min(heap_size / Self::MAX_HEAP_FRACTION)
+    }
+}
+
+impl MemoryBlock {
+    fn new(
+        logical_device: &ash::Device,
+        size: vk::DeviceSize,
+        memory_type_index: u32,
+        property_flags: vk::MemoryPropertyFlags,
+    ) -> Result<Self> {
+        let memory_allocate_info = vk::MemoryAllocateInfo {
+            allocation_size: size,
+            memory_type_index,
+            ..Default::default()
+        };
+        let memory = unsafe { logical_device.allocate_memory(&memory_allocate_info, None)? };
+
+        // a memory object can only be mapped once, so we map the whole block and hand out pointers into it
+        let mapped_ptr = if property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            match unsafe { logical_device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty()) }
+            {
+                Ok(mapped_ptr) => mapped_ptr,
+                Err(error) => {
+                    unsafe { logical_device.free_memory(memory, None) };
+                    return Err(error.into());
+                }
+            }
+        } else {
+            ptr::null_mut()
+        };
+
+        Ok(Self {
+            memory,
+            size,
+            mapped_ptr,
+            ranges: Vec::new(),
+        })
+    }
+
+    fn insert(
+        &mut self,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        kind: ResourceKind,
+        memory_type_index: u32,
+    ) -> Allocation {
+        insert_range(&mut self.ranges, UsedRange { offset, size, kind });
+
+        let mapped_ptr = if self.mapped_ptr.is_null() {
+            ptr::null_mut()
+        } else {
+            // the offset is always within the block which is mapped in full
+            unsafe { (self.mapped_ptr as *mut u8).add(offset as usize) as *mut std::ffi::c_void }
+        };
+
+        Allocation {
+            memory: self.memory,
+            offset,
+            size,
+            memory_type_index,
+            mapped_ptr,
+        }
+    }
+}
+
+impl fmt::Display for MemoryStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (heap_index, heap) in self.heaps.iter().enumerate() {
+            writeln!(
+                f,
+                "heap {}: {} blocks, {} allocations, {} bytes used, {} bytes free",
+                heap_index,
+                heap.block_count,
+                heap.allocation_count,
+                heap.used_bytes,
+                heap.free_bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+//////////////////////// Fns ///////////////////////
+/// Rounds `value` up to the next multiple of `alignment`.
+pub fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    if alignment <= 1 {
+        value
+    } else {
+        value.div_ceil(alignment) * alignment
+    }
+}
+
+/// Finds the first gap between the sorted `ranges` of a block that fits `size` bytes at the required alignment.
+/// Works on the ranges instead of a `MemoryBlock`, so that it doesn't need any device memory.
+fn find_free_offset(
+    ranges: &[UsedRange],
+    block_size: vk::DeviceSize,
+    size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    kind: ResourceKind,
+    granularity: vk::DeviceSize,
+) -> Option<vk::DeviceSize> {
+    let mut gap_start = 0;
+    let mut previous: Option<&UsedRange> = None;
+
+    for next in ranges.iter().map(Some).chain(std::iter::once(None)) {
+        let mut offset = align_up(gap_start, alignment);
+        if let Some(previous) = previous {
+            if previous.kind != kind
+                && on_same_page(previous.offset + previous.size - 1, offset, granularity)
+            {
+                offset = align_up(offset, granularity);
+            }
+        }
+
+        let gap_end = next.map_or(block_size, |next| next.offset);
+        let fits = offset + size <= gap_end
+            && next.is_none_or(|next| {
+                next.kind == kind || !on_same_page(offset + size - 1, next.offset, granularity)
+            });
+        if fits {
+            return Some(offset);
+        }
+
+        if let Some(next) = next {
+            gap_start = next.offset + next.size;
+        }
+        previous = next;
+    }
+    None
+}
+
+fn insert_range(ranges: &mut Vec<UsedRange>, range: UsedRange) {
+    let idx = ranges.partition_point(|used| used.offset < range.offset);
+    ranges.insert(idx, range);
+}
+
+// the free memory is only the gaps between the used ranges, so the freed range merges with the gaps next to it
+fn remove_range(ranges: &mut Vec<UsedRange>, offset: vk::DeviceSize) {
+    ranges.retain(|range| range.offset != offset);
+}
+
+// whether the two byte offsets are on the same page of `granularity` bytes
+fn on_same_page(
+    first: vk::DeviceSize,
+    second: vk::DeviceSize,
+    granularity: vk::DeviceSize,
+) -> bool {
+    granularity > 1 && first / granularity == second / granularity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn used(offset: vk::DeviceSize, size: vk::DeviceSize, kind: ResourceKind) -> UsedRange {
+        UsedRange { offset, size, kind }
+    }
+
+    #[test]
+    fn offsets_are_aligned() {
+        let ranges = [used(0, 10, ResourceKind::Linear)];
+
+        assert_eq!(
+            find_free_offset(&[], 1024, 16, 256, ResourceKind::Linear, 1),
+            Some(0)
+        );
+        assert_eq!(
+            find_free_offset(&ranges, 1024, 16, 256, ResourceKind::Linear, 1),
+            Some(256)
+        );
+        assert_eq!(
+            find_free_offset(&ranges, 1024, 16, 1, ResourceKind::Linear, 1),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn allocations_that_dont_fit_into_any_gap_are_rejected() {
+        let ranges = [
+            used(0, 100, ResourceKind::Linear),
+            used(200, 100, ResourceKind::Linear),
+        ];
+
+        assert_eq!(
+            find_free_offset(&ranges, 400, 100, 1, ResourceKind::Linear, 1),
+            Some(100)
+        );
+        assert_eq!(
+            find_free_offset(&ranges, 400, 101, 1, ResourceKind::Linear, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn different_kinds_dont_share_a_page_with_the_previous_range() {
+        let ranges = [used(0, 100, ResourceKind::Linear)];
+
+        assert_eq!(
+            find_free_offset(&ranges, 4096, 100, 16, ResourceKind::Linear, 1024),
+            Some(112)
+        );
+        assert_eq!(
+            find_free_offset(&ranges, 4096, 100, 16, ResourceKind::NonLinear, 1024),
+            Some(1024)
+        );
+    }
+
+    #[test]
+    fn different_kinds_dont_share_a_page_with_the_next_range() {
+        let ranges = [used(512, 512, ResourceKind::NonLinear)];
+
+        assert_eq!(
+            find_free_offset(&ranges, 4096, 100, 16, ResourceKind::NonLinear, 1024),
+            Some(0)
+        );
+        // the gap in front of the optimal range ends on its page, so the linear resource goes behind it
+        assert_eq!(
+            find_free_offset(&ranges, 4096, 100, 16, ResourceKind::Linear, 1024),
+            Some(1024)
+        );
+    }
+
+    #[test]
+    fn freed_ranges_merge_with_the_gaps_next_to_them() {
+        let mut ranges = Vec::new();
+        for &offset in [0, 256, 512].iter() {
+            insert_range(&mut ranges, used(offset, 256, ResourceKind::Linear));
+        }
+        assert_eq!(
+            find_free_offset(&ranges, 1024, 512, 1, ResourceKind::Linear, 1),
+            None
+        );
+
+        remove_range(&mut ranges, 256);
+        assert_eq!(
+            find_free_offset(&ranges, 1024, 512, 1, ResourceKind::Linear, 1),
+            None
+        );
+
+        remove_range(&mut ranges, 0);
+        assert_eq!(
+            find_free_offset(&ranges, 1024, 512, 1, ResourceKind::Linear, 1),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn inserted_ranges_stay_sorted_by_offset() {
+        let mut ranges = Vec::new();
+        for &offset in [512, 0, 256].iter() {
+            insert_range(&mut ranges, used(offset, 128, ResourceKind::Linear));
+        }
+
+        let offsets: Vec<_> = ranges.iter().map(|range| range.offset).collect();
+        assert_eq!(offsets, vec![0, 256, 512]);
+        assert_eq!(
+            find_free_offset(&ranges, 1024, 128, 1, ResourceKind::Linear, 1),
+            Some(128)
+        );
+    }
+}
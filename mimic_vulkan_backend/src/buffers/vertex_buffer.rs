@@ -1,5 +1,7 @@
 use crate::{
-    buffers::buffer::Buffer, devices::queues::QueueMap, models::vertex::Vertex,
+    buffers::{buffer::Buffer, memory::allocator::MemoryAllocator},
+    drawing::upload_context::UploadContext,
+    models::vertex::VertexLayout,
     util::result::Result,
 };
 use ash::vk;
@@ -12,22 +14,20 @@ pub struct VertexBuffer {
 }
 //////////////////////// Impls ///////////////////////
 impl VertexBuffer {
+    /// The `data` holds the interleaved attributes of the `vertex_layout` for every vertex.
     pub fn new(
-        data: &[Vertex],
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
+        data: &[f32],
+        vertex_layout: &VertexLayout,
+        allocator: &mut MemoryAllocator,
         logical_device: &ash::Device,
-        command_pool: vk::CommandPool,
-        queues: &QueueMap,
+        upload_context: &mut UploadContext,
     ) -> Result<Self> {
-        let size = vk::DeviceSize::try_from(std::mem::size_of::<Vertex>() * data.len())?;
+        let size = vk::DeviceSize::try_from(std::mem::size_of::<f32>() * data.len())?;
 
         let vertex_buffer = Buffer::create_and_fill(
-            instance,
-            physical_device,
+            allocator,
             logical_device,
-            command_pool,
-            queues,
+            upload_context,
             size,
             &data,
             vk::BufferUsageFlags::VERTEX_BUFFER,
@@ -35,7 +35,7 @@ impl VertexBuffer {
 
         Ok(Self {
             data: vertex_buffer,
-            vertex_count: data.len(),
+            vertex_count: data.len() / vertex_layout.get_float_count().max(1),
         })
     }
 
@@ -43,54 +43,7 @@ impl VertexBuffer {
     ///
     /// This method calls unsafe, low-level vulkan api functions to destroy buffers and free memory.
     /// It must be called with valid vulkan state.
-    pub unsafe fn cleanup(self, logical_device: &ash::Device) {
-        logical_device.destroy_buffer(self.data.buffer, None);
-        logical_device.free_memory(self.data.memory, None);
-    }
-
-    pub fn get_rectangle() -> [Vertex; 8] {
-        [
-            Vertex {
-                pos: glm::vec3(-0.5, 0.0, -0.5),
-                color: glm::vec3(1.0, 0.0, 0.0),
-                tex_coord: glm::vec2(0.0, 0.0),
-            },
-            Vertex {
-                pos: glm::vec3(0.5, 0.0, -0.5),
-                color: glm::vec3(0.0, 1.0, 0.0),
-                tex_coord: glm::vec2(1.0, 0.0),
-            },
-            Vertex {
-                pos: glm::vec3(0.5, 0.0, 0.5),
-                color: glm::vec3(0.0, 0.0, 1.0),
-                tex_coord: glm::vec2(1.0, 1.0),
-            },
-            Vertex {
-                pos: glm::vec3(-0.5, 0.0, 0.5),
-                color: glm::vec3(1.0, 1.0, 1.0),
-                tex_coord: glm::vec2(0.0, 1.0),
-            },
-            // second rectangle
-            Vertex {
-                pos: glm::vec3(-0.5, -0.5, -0.5),
-                color: glm::vec3(1.0, 0.0, 0.0),
-                tex_coord: glm::vec2(0.0, 0.0),
-            },
-            Vertex {
-                pos: glm::vec3(0.5, -0.5, -0.5),
-                color: glm::vec3(0.0, 1.0, 0.0),
-                tex_coord: glm::vec2(1.0, 0.0),
-            },
-            Vertex {
-                pos: glm::vec3(0.5, -0.5, 0.5),
-                color: glm::vec3(0.0, 0.0, 1.0),
-                tex_coord: glm::vec2(1.0, 1.0),
-            },
-            Vertex {
-                pos: glm::vec3(-0.5, -0.5, 0.5),
-                color: glm::vec3(1.0, 1.0, 1.0),
-                tex_coord: glm::vec2(0.0, 1.0),
-            },
-        ]
+    pub unsafe fn cleanup(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
+        self.data.destroy(allocator, logical_device);
     }
 }
@@ -1,7 +1,8 @@
 use crate::{
+    buffers::memory::allocator::MemoryAllocator,
     depth::helpers::find_depth_format,
     devices::queues::QueueMap,
-    presentation::swap_chain::SwapChainContainer,
+    presentation::render_target::RenderTarget,
     textures::images::{Image, MipmapParam},
     util::result::Result,
 };
@@ -17,24 +18,24 @@ impl DepthResource {
     pub fn new(
         msaa_samples: vk::SampleCountFlags,
         instance: &ash::Instance,
-        logical_device: &ash::Device,
         physical_device: vk::PhysicalDevice,
-        swap_chain_container: &SwapChainContainer,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        render_target: &RenderTarget,
         command_pool: vk::CommandPool,
         queues: &QueueMap,
     ) -> Result<Self> {
         let depth_format = find_depth_format(instance, physical_device)?;
         let mut depth_image = Image::new(
-            swap_chain_container.swap_chain_extent.width,
-            swap_chain_container.swap_chain_extent.height,
+            render_target.get_extent().width,
+            render_target.get_extent().height,
             MipmapParam::NoMipmap,
             msaa_samples,
             depth_format,
             vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            instance,
-            physical_device,
+            allocator,
             logical_device,
         )?;
         let depth_image_view = depth_image.create_image_view(
@@ -63,9 +64,8 @@ impl DepthResource {
     ///
     /// This method calls unsafe, low level vulkan api functions to destroy images and free memory.
     /// So it must be called with valid vulkan state.
-    pub unsafe fn drop(self, logical_device: &ash::Device) {
+    pub unsafe fn drop(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
         logical_device.destroy_image_view(self.depth_image_view, None);
-        logical_device.destroy_image(self.depth_image.image, None);
-        logical_device.free_memory(self.depth_image.memory, None);
+        self.depth_image.destroy(allocator, logical_device);
     }
 }
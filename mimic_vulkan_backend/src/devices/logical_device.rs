@@ -1,3 +1,4 @@
+use crate::devices::physical_device::get_descriptor_indexing_features;
 use crate::devices::queues::{QueueFamilyCreateData, QueueFamilyIndices};
 use crate::devices::requirements::DeviceRequirements;
 use crate::util::result::{Result, VulkanError};
@@ -35,16 +36,26 @@ pub fn create_logical_device(
         queue_create_infos.push(queue_create_info);
     }
 
+    // wireframe and point polygon modes are optional so they are only enabled where supported
+    let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
     let device_features = vk::PhysicalDeviceFeatures {
         sampler_anisotropy: vk::TRUE,
+        fill_mode_non_solid: supported_features.fill_mode_non_solid,
+        // bindless textures index their texture array with the texture id of the draw
+        shader_sampled_image_array_dynamic_indexing: if requirements.descriptor_indexing {
+            vk::TRUE
+        } else {
+            vk::FALSE
+        },
         ..vk::PhysicalDeviceFeatures::default()
     };
+    let mut descriptor_indexing_features = get_descriptor_indexing_features();
 
     let enabled_extensions = requirements.get_enabled_extension_names();
     let enabled_extension_cstrs =
         DeviceRequirements::convert_enabled_extension_names(&enabled_extensions);
 
-    let device_create_info = vk::DeviceCreateInfo {
+    let mut device_create_info = vk::DeviceCreateInfo {
         queue_create_info_count: match u32::try_from(queue_create_infos.len()) {
             Ok(count) => count,
             Err(_) => return Err(VulkanError::LogicalDeviceCreateError),
@@ -57,6 +68,12 @@ pub fn create_logical_device(
         pp_enabled_extension_names: enabled_extension_cstrs.as_ptr(),
         ..vk::DeviceCreateInfo::default()
     };
+    // descriptor indexing is core in vulkan 1.2, so its features are enabled without the extension
+    if requirements.descriptor_indexing {
+        device_create_info.p_next = &mut descriptor_indexing_features
+            as *mut vk::PhysicalDeviceDescriptorIndexingFeatures
+            as *const std::ffi::c_void;
+    }
 
     let logical_device =
         unsafe { instance.create_device(physical_device, &device_create_info, None)? };
@@ -44,9 +44,10 @@ pub fn get_physical_device_properties(
 }
 
 // the device is implicitly destroyed when instance is destroyed
+// when no surface is provided we are rendering headless and no device needs to be able to present
 pub fn pick_physical_device(
     instance: &ash::Instance,
-    surface_container: &SurfaceContainer,
+    surface_container: Option<&SurfaceContainer>,
     requirements: &DeviceRequirements,
 ) -> Result<vk::PhysicalDevice> {
     let physical_devices = unsafe { instance.enumerate_physical_devices()? };
@@ -96,7 +97,7 @@ pub fn pick_physical_device(
 fn rate_physical_device(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
-    surface_container: &SurfaceContainer,
+    surface_container: Option<&SurfaceContainer>,
     requirements: &DeviceRequirements,
 ) -> Result<(u32, String, String)> {
     let physical_device_properties =
@@ -162,6 +163,15 @@ fn rate_physical_device(
         return Ok((0, short_description, long_description));
     }
 
+    if requirements.descriptor_indexing
+        && !is_descriptor_indexing_supported(instance, physical_device)
+    {
+        long_description.push_str(
+            "Physical device doesn't support the descriptor indexing needed for bindless textures",
+        );
+        return Ok((0, short_description, long_description));
+    }
+
     // if we have any special requirements as to what needs to be supported we should put it here
     let mut found_queue_families = HashSet::new();
     for (queue_family_idx, queue_family) in device_queue_families.iter().enumerate() {
@@ -194,11 +204,13 @@ fn rate_physical_device(
             long_description.push_str("| Sparse binding Queue: unsupported ");
         };
 
-        if has_present_function(surface_container, physical_device, queue_family_idx as u32)? {
-            long_description.push_str("| Present: supported ");
-            found_queue_families.insert(QueueType::PresentQueue);
-        } else {
-            long_description.push_str("| Present: unsupported ");
+        if let Some(surface_container) = surface_container {
+            if has_present_function(surface_container, physical_device, queue_family_idx as u32)? {
+                long_description.push_str("| Present: supported ");
+                found_queue_families.insert(QueueType::PresentQueue);
+            } else {
+                long_description.push_str("| Present: unsupported ");
+            }
         }
 
         long_description.push('\n');
@@ -221,16 +233,58 @@ fn rate_physical_device(
         long_description.push_str("Geometry Shader unsupported\n");
     };
 
-    let swap_query_support_details =
-        SwapChainSupportDetails::query_support(physical_device, &surface_container)?;
-    if !((requirements.is_swap_chain_adequate_check)(&swap_query_support_details)) {
-        long_description.push_str("Swap chain doesn't pass adequate check");
-        return Ok((0, short_description, long_description));
+    if let Some(surface_container) = surface_container {
+        let swap_query_support_details =
+            SwapChainSupportDetails::query_support(physical_device, surface_container)?;
+        if !((requirements.is_swap_chain_adequate_check)(&swap_query_support_details)) {
+            long_description.push_str("Swap chain doesn't pass adequate check");
+            return Ok((0, short_description, long_description));
+        }
     }
 
     Ok((rating, short_description, long_description))
 }
 
+/// Checks that the device is a vulkan 1.2 device which supports the features that `get_descriptor_indexing_features` enables.
+/// The instance has to be created with vulkan 1.2 as well.
+pub fn is_descriptor_indexing_supported(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> bool {
+    let physical_device_properties =
+        unsafe { instance.get_physical_device_properties(physical_device) };
+    if physical_device_properties.api_version < vk::API_VERSION_1_2 {
+        return false;
+    }
+
+    let mut supported_indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+    let mut supported_features = vk::PhysicalDeviceFeatures2::builder()
+        .push_next(&mut supported_indexing_features)
+        .build();
+    unsafe { instance.get_physical_device_features2(physical_device, &mut supported_features) };
+
+    let features = supported_features.features;
+    features.shader_sampled_image_array_dynamic_indexing == vk::TRUE
+        && supported_indexing_features.runtime_descriptor_array == vk::TRUE
+        && supported_indexing_features.descriptor_binding_partially_bound == vk::TRUE
+        && supported_indexing_features.descriptor_binding_sampled_image_update_after_bind
+            == vk::TRUE
+        && supported_indexing_features.descriptor_binding_update_unused_while_pending == vk::TRUE
+}
+
+/// The descriptor indexing features that the bindless texture array uses.
+/// Textures are added to the array while earlier frames that use other textures of the array are still in flight.
+/// The index into the array is the same for a whole draw, so non uniform indexing is not needed.
+pub fn get_descriptor_indexing_features() -> vk::PhysicalDeviceDescriptorIndexingFeatures {
+    vk::PhysicalDeviceDescriptorIndexingFeatures {
+        runtime_descriptor_array: vk::TRUE,
+        descriptor_binding_partially_bound: vk::TRUE,
+        descriptor_binding_sampled_image_update_after_bind: vk::TRUE,
+        descriptor_binding_update_unused_while_pending: vk::TRUE,
+        ..Default::default()
+    }
+}
+
 fn check_device_extensions(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
@@ -36,6 +36,13 @@ impl QueueMap {
             .ok_or(VulkanError::QueueGraphicsNotFound)
     }
 
+    /// Only returns a queue if the device has a queue family for transfers which doesn't support graphics.
+    pub fn get_transfer_queue(&self) -> Option<ash::vk::Queue> {
+        self.queues
+            .get(&QueueType::QueueWithFlag(vk::QueueFlags::TRANSFER))
+            .copied()
+    }
+
     pub fn get_present_queue(&self) -> Result<ash::vk::Queue> {
         self.queues
             .get(&QueueType::PresentQueue)
@@ -58,11 +65,12 @@ impl QueueFamilyIndices {
     pub fn find(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
-        surface: &SurfaceContainer,
+        surface: Option<&SurfaceContainer>,
         requirements: &DeviceRequirements,
     ) -> Result<Self> {
         let device_queue_families =
             unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+        let transfer_family = Self::find_dedicated_transfer_family(&device_queue_families);
 
         let mut queue_data_points = Vec::new();
         for (queue_family_index, queue_family) in device_queue_families.into_iter().enumerate() {
@@ -76,8 +84,15 @@ impl QueueFamilyIndices {
                             }
                         }
                         QueueType::PresentQueue => {
-                            if has_present_function(surface, physical_device, queue_family_index as u32)? {
-                                queue_data.1.insert(required_family);
+                            // without a surface (headless) there is nothing to present to
+                            if let Some(surface) = surface {
+                                if has_present_function(
+                                    surface,
+                                    physical_device,
+                                    queue_family_index as u32,
+                                )? {
+                                    queue_data.1.insert(required_family);
+                                }
                             }
                         }
                     }
@@ -123,6 +138,13 @@ impl QueueFamilyIndices {
             }
         }
 
+        // the transfer queue is optional, so it is only added if it doesn't share a family with the required queues
+        if let Some(transfer_family) = transfer_family {
+            indices
+                .entry(transfer_family)
+                .or_insert_with(|| vec![QueueType::QueueWithFlag(vk::QueueFlags::TRANSFER)]);
+        }
+
         let mut queue_index_map = HashMap::new();
         for (&queue_family_index, queue_types_set) in indices.iter() {
             for &queue_type in queue_types_set {
@@ -138,8 +160,26 @@ impl QueueFamilyIndices {
         })
     }
 
+    /// The family of the dedicated transfer queue, if the device has one.
+    pub fn get_transfer_family(&self) -> Option<u32> {
+        self.queue_index_map
+            .get(&QueueType::QueueWithFlag(vk::QueueFlags::TRANSFER))
+            .copied()
+    }
+
     pub fn get_image_sharing_details(&self) -> (vk::SharingMode, usize, Vec<u32>) {
-        let indices_needed: Vec<u32> = self.indices.keys().copied().collect();
+        // the transfer queue hands over resources explicitly, so it doesn't share the swap chain images
+        let transfer_queue = QueueType::QueueWithFlag(vk::QueueFlags::TRANSFER);
+        let indices_needed: Vec<u32> = self
+            .indices
+            .iter()
+            .filter(|(_, queue_types)| {
+                queue_types
+                    .iter()
+                    .any(|&queue_type| queue_type != transfer_queue)
+            })
+            .map(|(&queue_family_index, _)| queue_family_index)
+            .collect();
         if indices_needed.len() > 1 {
             (
                 vk::SharingMode::CONCURRENT,
@@ -151,6 +191,24 @@ impl QueueFamilyIndices {
         }
     }
 
+    // prefer a family that can only transfer, since those usually map to the dma engines of the gpu
+    fn find_dedicated_transfer_family(queue_families: &[vk::QueueFamilyProperties]) -> Option<u32> {
+        let is_transfer_only = |flags: vk::QueueFlags| {
+            !flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        };
+        let transfer_families = || {
+            queue_families.iter().enumerate().filter(|(_, family)| {
+                family.queue_count > 0
+                    && family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                    && !family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+            })
+        };
+        transfer_families()
+            .find(|(_, family)| is_transfer_only(family.queue_flags))
+            .or_else(|| transfer_families().next())
+            .map(|(queue_family_index, _)| queue_family_index as u32)
+    }
+
     pub fn get_best_queue_family_data(queue_family_index: u32) -> QueueFamilyCreateData {
         QueueFamilyCreateData(queue_family_index, 1, vec![1.0_f32])
     }
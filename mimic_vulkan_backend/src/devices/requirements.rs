@@ -12,6 +12,8 @@ pub struct DeviceRequirements {
     pub required_device_extensions: Vec<&'static str>,
     pub is_swap_chain_adequate_check: fn(&SwapChainSupportDetails) -> bool,
     pub supported_features_check: fn(&vk::PhysicalDeviceFeatures) -> bool,
+    /// whether the device needs the descriptor indexing features of vulkan 1.2, which bindless textures are built on
+    pub descriptor_indexing: bool,
 }
 
 impl DeviceRequirements {
@@ -26,9 +28,15 @@ impl DeviceRequirements {
             required_device_extensions: required_device_extensions.iter().copied().collect(),
             is_swap_chain_adequate_check,
             supported_features_check,
+            descriptor_indexing: false,
         }
     }
 
+    pub fn with_descriptor_indexing(mut self) -> Self {
+        self.descriptor_indexing = true;
+        self
+    }
+
     pub fn get_enabled_extension_count(&self) -> u32 {
         u32::try_from(self.required_device_extensions.len()).unwrap_or(0)
     }
@@ -1,3 +1,5 @@
 pub mod command_buffers;
 pub mod framebuffers;
+pub mod render_graph;
 pub mod synchronization;
+pub mod upload_context;
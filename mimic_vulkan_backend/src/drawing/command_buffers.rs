@@ -1,20 +1,51 @@
 use crate::{
-    buffers::{index_buffer::IndexBuffer, vertex_buffer::VertexBuffer},
+    buffers::{
+        index_buffer::IndexBuffer, instance_buffer::InstanceBuffer, vertex_buffer::VertexBuffer,
+    },
     devices::queues::{QueueFamilyIndices, QueueMap, QueueType},
     graphics_pipeline::GraphicsPipeline,
     models::index,
-    presentation::swap_chain::SwapChainContainer,
-    uniforms::descriptors::DescriptorData,
+    presentation::render_target::RenderTarget,
     util::result::{Result, VulkanError},
 };
-use ash::{vk};
+use ash::vk;
+use mimic_common::uniforms::BINDLESS_TEXTURE_ID_OFFSET;
 use std::{convert::TryFrom, ptr};
+//////////////////////// Structs ///////////////////////
+/// The data needed to record the draw of one object into a command buffer.
+pub struct DrawCommandData<'a> {
+    pub graphics_pipeline: &'a GraphicsPipeline,
+    pub vertex_buffer: &'a VertexBuffer,
+    pub index_buffer: &'a IndexBuffer,
+    pub instance_buffer: &'a InstanceBuffer,
+    /// the descriptor set of the uniform ring buffer of the current frame
+    pub uniform_descriptor_set: vk::DescriptorSet,
+    /// the offset of the uniform data of the object in the uniform ring buffer
+    pub uniform_offset: u32,
+    /// the parts of the mesh which are drawn with their own texture and material
+    pub submeshes: Vec<SubmeshDrawData>,
+    /// the push constants of the object, which are empty if it doesn't use any
+    pub push_constants: Vec<u8>,
+    /// the set of the bindless texture array, null if bindless textures are disabled
+    pub bindless_descriptor_set: vk::DescriptorSet,
+}
+
+pub struct SubmeshDrawData {
+    pub first_index: u32,
+    pub index_count: u32,
+    /// binds the texture and material of the submesh, null if the shaders don't declare any binding in set 1
+    pub texture_descriptor_set: vk::DescriptorSet,
+    /// the index of the texture of the submesh in the bindless texture array, if bindless textures are enabled
+    pub bindless_texture_id: Option<u32>,
+}
 //////////////////////// Fns ///////////////////////
 pub fn create_command_pool(
     logical_device: &ash::Device,
     queue_family_indices: &QueueFamilyIndices,
 ) -> Result<vk::CommandPool> {
     let pool_info = vk::CommandPoolCreateInfo {
+        // the command buffers of a frame are reset and recorded again every time the frame is drawn
+        flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
         queue_family_index: queue_family_indices.queue_index_map
             [&QueueType::QueueWithFlag(vk::QueueFlags::GRAPHICS)],
         ..Default::default()
@@ -84,122 +115,221 @@ pub fn end_single_time_commands(
     Ok(())
 }
 
-pub fn create_command_buffers(
+pub fn allocate_command_buffers(
     logical_device: &ash::Device,
     command_pool: &vk::CommandPool,
-    framebuffers: &[vk::Framebuffer],
-    graphics_pipeline: &GraphicsPipeline,
-    swap_chain_container: &SwapChainContainer,
-    vertex_buffer: &VertexBuffer,
-    index_buffer: &IndexBuffer,
-    descriptor_data: &DescriptorData,
+    command_buffer_count: usize,
 ) -> Result<Vec<vk::CommandBuffer>> {
-    let num_framebuffers = framebuffers.len();
-
     let allocate_info = vk::CommandBufferAllocateInfo {
         command_pool: *command_pool,
-        command_buffer_count: u32::try_from(num_framebuffers)?,
+        command_buffer_count: u32::try_from(command_buffer_count)?,
         level: vk::CommandBufferLevel::PRIMARY,
         ..Default::default()
     };
 
     let command_buffers = unsafe { logical_device.allocate_command_buffers(&allocate_info)? };
 
-    for i in 0..num_framebuffers {
-        let command_buffer_begin_info = vk::CommandBufferBeginInfo {
-            p_inheritance_info: ptr::null(),
-            ..Default::default()
-        };
+    Ok(command_buffers)
+}
 
-        unsafe {
-            logical_device.begin_command_buffer(command_buffers[i], &command_buffer_begin_info)?
-        }
+/// Records the drawing of all the objects in the `draw_list` into the framebuffer of the render target image with `image_index`.
+/// The command buffer has to be in the recording state, see `begin_frame_command_buffer`.
+pub fn record_command_buffer(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    image_index: usize,
+    framebuffers: &[vk::Framebuffer],
+    render_pass: vk::RenderPass,
+    render_target: &RenderTarget,
+    draw_list: &[DrawCommandData],
+) -> Result<()> {
+    if image_index >= framebuffers.len() {
+        return Err(VulkanError::CommandBufferNotAvailable(image_index));
+    }
 
-        let render_pass_begin_info = vk::RenderPassBeginInfo {
-            render_pass: graphics_pipeline.render_pass,
-            framebuffer: framebuffers[i],
-            render_area: vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: swap_chain_container.swap_chain_extent,
-            },
-            clear_value_count: u32::try_from(GraphicsPipeline::CLEAR_COLORS.len())?,
-            p_clear_values: GraphicsPipeline::CLEAR_COLORS.as_ptr(),
-            ..Default::default()
-        };
-
-        unsafe {
-            logical_device.cmd_begin_render_pass(
-                command_buffers[i],
-                &render_pass_begin_info,
-                vk::SubpassContents::INLINE,
-            );
+    let render_pass_begin_info = vk::RenderPassBeginInfo {
+        render_pass,
+        framebuffer: framebuffers[image_index],
+        render_area: vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: render_target.get_extent(),
+        },
+        clear_value_count: u32::try_from(GraphicsPipeline::CLEAR_COLORS.len())?,
+        p_clear_values: GraphicsPipeline::CLEAR_COLORS.as_ptr(),
+        ..Default::default()
+    };
 
-            logical_device.cmd_bind_pipeline(
-                command_buffers[i],
-                vk::PipelineBindPoint::GRAPHICS,
-                graphics_pipeline.pipeline,
-            );
+    unsafe {
+        logical_device.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+    }
 
-            let vertex_buffers = [vertex_buffer.data.buffer];
-            let offsets: [vk::DeviceSize; 1] = [0];
-            logical_device.cmd_bind_vertex_buffers(
-                command_buffers[i],
-                0,
-                &vertex_buffers,
-                &offsets,
-            );
+    set_viewport_and_scissor(logical_device, command_buffer, render_target.get_extent());
+
+    // all the objects in the draw list are drawn within the same render pass
+    for draw_command in draw_list.iter() {
+        record_draw_command(logical_device, command_buffer, draw_command)?;
+    }
+
+    unsafe {
+        logical_device.cmd_end_render_pass(command_buffer);
+    }
+
+    Ok(())
+}
+
+/// Resets the command buffer of a frame and begins recording it again.
+pub fn begin_frame_command_buffer(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+) -> Result<()> {
+    let command_buffer_begin_info = vk::CommandBufferBeginInfo {
+        // the command buffer is recorded again for the next frame that uses it
+        flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+        p_inheritance_info: ptr::null(),
+        ..Default::default()
+    };
+
+    unsafe {
+        logical_device
+            .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+        logical_device.begin_command_buffer(command_buffer, &command_buffer_begin_info)?;
+    }
+    Ok(())
+}
+
+/// Sets the dynamic viewport and scissor state so that the whole image with the size `extent` is drawn to.
+pub fn set_viewport_and_scissor(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+) {
+    // viewport defines the transformation from image to framebuffer
+    let viewport = vk::Viewport {
+        x: 0.0f32,
+        y: 0.0f32,
+        width: extent.width as f32,
+        height: extent.height as f32,
+        min_depth: 0.0f32,
+        max_depth: 1.0f32,
+    };
+
+    // scissor defines a filter of which part of the image to show
+    let scissor = vk::Rect2D {
+        offset: vk::Offset2D { x: 0, y: 0 },
+        extent,
+    };
+
+    unsafe {
+        logical_device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+        logical_device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+    }
+}
+
+pub fn record_draw_command(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    draw_command: &DrawCommandData,
+) -> Result<()> {
+    let DrawCommandData {
+        graphics_pipeline,
+        vertex_buffer,
+        index_buffer,
+        instance_buffer,
+        uniform_descriptor_set,
+        uniform_offset,
+        submeshes,
+        push_constants,
+        bindless_descriptor_set,
+    } = draw_command;
+
+    unsafe {
+        logical_device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            graphics_pipeline.pipeline,
+        );
+
+        let vertex_buffers = [vertex_buffer.data.buffer, instance_buffer.data.buffer];
+        let offsets: [vk::DeviceSize; 2] = [0, 0];
+        logical_device.cmd_bind_vertex_buffers(command_buffer, 0, &vertex_buffers, &offsets);
 
-            logical_device.cmd_bind_index_buffer(
-                command_buffers[i],
-                index_buffer.data.buffer,
+        logical_device.cmd_bind_index_buffer(
+            command_buffer,
+            index_buffer.data.buffer,
+            0,
+            index::INDEX_TYPE_VK_TYPE,
+        );
+
+        // set 0 is shared by all objects of the frame, the dynamic offset selects the uniform data of this object
+        logical_device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            graphics_pipeline.pipeline_layout,
+            0,
+            &[*uniform_descriptor_set],
+            &[*uniform_offset],
+        );
+
+        if !push_constants.is_empty() {
+            logical_device.cmd_push_constants(
+                command_buffer,
+                graphics_pipeline.pipeline_layout,
+                GraphicsPipeline::PUSH_CONSTANTS_STAGES,
                 0,
-                index::INDEX_TYPE_VK_TYPE,
+                push_constants,
             );
+        }
 
-            if i >= descriptor_data.descriptor_sets.len() {
-                return Err(VulkanError::DescriptorSetNotAvailable(i));
-            }
-            let descriptors_sets_to_bind = [descriptor_data.descriptor_sets[i]];
+        // set 2 has to be bound again for every pipeline, since the layouts of set 1 differ between them
+        if *bindless_descriptor_set != vk::DescriptorSet::null() {
             logical_device.cmd_bind_descriptor_sets(
-                command_buffers[i],
+                command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
                 graphics_pipeline.pipeline_layout,
-                0,
-                &descriptors_sets_to_bind,
+                2,
+                &[*bindless_descriptor_set],
                 &[],
             );
+        }
 
-            // let vertex_count = u32::try_from(vertex_buffer.vertex_count)?;
-            // let instance_count = 1; // no instancing
-            // let first_vertex = 0;
-            // let first_instance = 0;
-            // logical_device.cmd_draw(
-            //     command_buffers[i],
-            //     vertex_count,
-            //     instance_count,
-            //     first_vertex,
-            //     first_instance,
-            // );
-
-            let index_count = u32::try_from(index_buffer.index_count)?;
-            let instance_count = 1; // no instancing
-            let first_index = 0;
-            let vertex_offset = 0;
-            let first_instance = 0;
+        let instance_count = u32::try_from(instance_buffer.instance_count)?;
+        let vertex_offset = 0;
+        let first_instance = 0;
+        for submesh in submeshes.iter() {
+            // set 1 is switched between the submeshes while set 0 stays bound
+            if submesh.texture_descriptor_set != vk::DescriptorSet::null() {
+                logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    graphics_pipeline.pipeline_layout,
+                    1,
+                    &[submesh.texture_descriptor_set],
+                    &[],
+                );
+            }
+            if let Some(texture_id) = submesh.bindless_texture_id {
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    graphics_pipeline.pipeline_layout,
+                    GraphicsPipeline::PUSH_CONSTANTS_STAGES,
+                    u32::try_from(BINDLESS_TEXTURE_ID_OFFSET)?,
+                    &texture_id.to_ne_bytes(),
+                );
+            }
             logical_device.cmd_draw_indexed(
-                command_buffers[i],
-                index_count,
+                command_buffer,
+                submesh.index_count,
                 instance_count,
-                first_index,
+                submesh.first_index,
                 vertex_offset,
                 first_instance,
             );
-
-            logical_device.cmd_end_render_pass(command_buffers[i]);
-
-            logical_device.end_command_buffer(command_buffers[i])?;
         }
     }
 
-    Ok(command_buffers)
+    Ok(())
 }
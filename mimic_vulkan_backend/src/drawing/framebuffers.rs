@@ -1,7 +1,6 @@
 use crate::{
-    graphics_pipeline::GraphicsPipeline,
     msaa::multisampling::ColorResource,
-    presentation::{image_views::ImageViews, swap_chain::SwapChainContainer},
+    presentation::{image_views::ImageViews, render_target::RenderTarget},
     util::result::Result,
 };
 use ash::{vk};
@@ -9,11 +8,11 @@ use std::convert::TryFrom;
 
 pub fn create_framebuffers(
     logical_device: &ash::Device,
-    graphics_pipeline: &GraphicsPipeline,
+    render_pass: vk::RenderPass,
     image_views: &ImageViews,
     depth_image_view: vk::ImageView,
     color_resource: &ColorResource,
-    swap_chain_container: &SwapChainContainer,
+    render_target: &RenderTarget,
 ) -> Result<Vec<vk::Framebuffer>> {
     let mut framebuffers = Vec::with_capacity(image_views.image_views.len());
     for image_view in image_views.image_views.iter() {
@@ -24,11 +23,11 @@ pub fn create_framebuffers(
         let attachment_count = u32::try_from(attachments.len())?;
 
         let framebuffer_create_info = vk::FramebufferCreateInfo {
-            render_pass: graphics_pipeline.render_pass,
+            render_pass,
             attachment_count,
             p_attachments: attachments.as_ptr(),
-            width: swap_chain_container.swap_chain_extent.width,
-            height: swap_chain_container.swap_chain_extent.height,
+            width: render_target.get_extent().width,
+            height: render_target.get_extent().height,
             layers: 1,
             ..Default::default()
         };
@@ -0,0 +1,964 @@
+use crate::{
+    buffers::{buffer::Buffer, memory::allocator::MemoryAllocator},
+    depth::{depth_resource::DepthResource, helpers},
+    drawing::command_buffers::{self, DrawCommandData},
+    graphics_pipeline::{render_pass::create_scene_render_pass, GraphicsPipeline},
+    msaa::multisampling::ColorResource,
+    presentation::{image_views::ImageViews, render_target::RenderTarget},
+    textures::images::{Image, MipmapParam},
+    util::result::{Result, VulkanError},
+};
+use ash::vk;
+use std::{convert::TryFrom, ptr, rc::Rc};
+//////////////////////// Enums ///////////////////////
+/// The format of an image that is created for a render graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GraphImageFormat {
+    /// The same format as the render target images. The scene pass can only resolve into images of this format.
+    RenderTarget,
+    /// The depth format that the physical device supports.
+    Depth,
+    Rgba8,
+    Rgba16Float,
+}
+
+/// An image that is read or written by the passes of a render graph.
+/// All images have the extent of the render target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GraphImage {
+    /// The swap-chain image (or offscreen image) that the current frame is rendered into.
+    RenderTarget,
+    /// An image which only lives for the duration of a frame. The index points into `RenderGraphPlan::transient_images`.
+    Transient(usize),
+}
+
+/// How a pass accesses an image. This determines the layout that the image has to be in and what the pass has to wait for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ImageAccess {
+    ColorAttachment,
+    DepthAttachment,
+    Sampled,
+    TransferSrc,
+    TransferDst,
+    /// The layout that the render target has to be in at the end of the frame (presentable or ready to be copied from).
+    Final,
+}
+
+/// What a custom pass starts with in one of its attachments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AttachmentLoad {
+    /// The attachment is cleared to the clear color or the far depth.
+    Clear,
+    /// The attachment keeps what earlier passes rendered into it, for example to draw an overlay over the scene.
+    Load,
+}
+
+/// How a pass accesses a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BufferAccess {
+    VertexInput,
+    Uniform,
+    StorageRead,
+    StorageWrite,
+    TransferSrc,
+    TransferDst,
+}
+
+/// The attachments that a pass renders into.
+pub enum PassAttachments {
+    /// The pass is recorded outside of a render pass, for example to copy or blit images.
+    None,
+    /// The built-in pass which draws all the render commands and resolves them into `resolve_target`.
+    Scene { resolve_target: GraphImage },
+    /// A render pass with attachments of the images' formats. The recorder of the pass does the drawing.
+    Custom {
+        color_attachments: Vec<GraphAttachment>,
+        depth_attachment: Option<GraphAttachment>,
+    },
+}
+//////////////////////// Traits ///////////////////////
+/// Records the commands of one render graph pass.
+/// When the pass has attachments then this is called inside of the render pass, so only draw commands should be recorded.
+pub trait PassRecorder {
+    fn record(&self, context: &PassRecordingContext) -> Result<()>;
+}
+//////////////////////// Structs ///////////////////////
+/// A buffer which only lives for the duration of a frame. The index points into `RenderGraphPlan::transient_buffers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GraphBuffer(pub usize);
+
+/// An image that a custom pass renders into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GraphAttachment {
+    pub image: GraphImage,
+    pub load: AttachmentLoad,
+}
+
+pub struct GraphBufferDescription {
+    pub size: vk::DeviceSize,
+}
+
+/// A barrier that has to be recorded before a pass can access an image.
+pub struct ImageBarrier {
+    pub image: GraphImage,
+    /// the previous access to the image. `None` if nothing accessed it yet this frame
+    pub from: Option<ImageAccess>,
+    pub to: ImageAccess,
+    /// if true then the previous contents of the image are not needed (e.g. the image memory now holds a different resource)
+    pub discard_contents: bool,
+}
+
+/// A barrier that has to be recorded before a pass can access a buffer.
+pub struct BufferBarrier {
+    pub buffer: GraphBuffer,
+    pub from: BufferAccess,
+    pub to: BufferAccess,
+}
+
+pub struct PlannedPass {
+    pub name: String,
+    pub image_barriers: Vec<ImageBarrier>,
+    pub buffer_barriers: Vec<BufferBarrier>,
+    pub attachments: PassAttachments,
+    pub recorder: Option<Rc<dyn PassRecorder>>,
+}
+
+/// A render graph which has already been ordered, culled and had its resources and barriers planned.
+/// The backend only allocates the transient resources and records the passes in the given order.
+pub struct RenderGraphPlan {
+    pub transient_images: Vec<GraphImageFormat>,
+    pub transient_buffers: Vec<GraphBufferDescription>,
+    pub passes: Vec<PlannedPass>,
+    /// barriers recorded after the last pass, which move the render target into its final layout
+    pub final_barriers: Vec<ImageBarrier>,
+}
+
+/// The vulkan objects which back a `RenderGraphPlan`.
+/// They depend on the size and format of the render target, so they have to be recreated together with the swap-chain.
+pub struct RenderGraphResources {
+    transient_images: Vec<TransientImage>,
+    transient_buffers: Vec<Buffer>,
+    pass_objects: Vec<PassObjects>,
+}
+
+/// Everything that the recorder of a pass may need to record its commands.
+pub struct PassRecordingContext<'a> {
+    pub logical_device: &'a ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+    /// the index of the render target image that this command buffer renders into
+    pub image_index: usize,
+    pub extent: vk::Extent2D,
+    /// the render pass that the pass is recorded in, or null if the pass has no attachments
+    pub render_pass: vk::RenderPass,
+    resources: &'a RenderGraphResources,
+    render_target_images: &'a [vk::Image],
+    render_target_image_views: &'a ImageViews,
+}
+
+struct TransientImage {
+    image: Image,
+    view: vk::ImageView,
+    aspect_mask: vk::ImageAspectFlags,
+}
+
+struct PassObjects {
+    render_pass: vk::RenderPass,
+    /// one framebuffer per render target image
+    framebuffers: Vec<vk::Framebuffer>,
+    clear_values: Vec<vk::ClearValue>,
+}
+//////////////////////// Impls ///////////////////////
+impl<F> PassRecorder for F
+where
+    F: Fn(&PassRecordingContext) -> Result<()>,
+{
+    fn record(&self, context: &PassRecordingContext) -> Result<()> {
+        self(context)
+    }
+}
+
+impl ImageAccess {
+    /// Whether the access modifies the image, which means later accesses have to wait for it.
+    pub fn is_write(self) -> bool {
+        matches!(
+            self,
+            ImageAccess::ColorAttachment | ImageAccess::DepthAttachment | ImageAccess::TransferDst
+        )
+    }
+
+    fn get_layout(self, render_target: &RenderTarget) -> vk::ImageLayout {
+        match self {
+            ImageAccess::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageAccess::DepthAttachment => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ImageAccess::Sampled => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ImageAccess::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageAccess::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageAccess::Final => render_target.get_final_layout(),
+        }
+    }
+
+    fn get_stage_mask(self) -> vk::PipelineStageFlags {
+        match self {
+            ImageAccess::ColorAttachment => vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            ImageAccess::DepthAttachment => {
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            }
+            ImageAccess::Sampled => vk::PipelineStageFlags::FRAGMENT_SHADER,
+            ImageAccess::TransferSrc | ImageAccess::TransferDst => vk::PipelineStageFlags::TRANSFER,
+            ImageAccess::Final => vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        }
+    }
+
+    fn get_access_mask(self) -> vk::AccessFlags {
+        match self {
+            ImageAccess::ColorAttachment => {
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            }
+            ImageAccess::DepthAttachment => {
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            }
+            ImageAccess::Sampled => vk::AccessFlags::SHADER_READ,
+            ImageAccess::TransferSrc => vk::AccessFlags::TRANSFER_READ,
+            ImageAccess::TransferDst => vk::AccessFlags::TRANSFER_WRITE,
+            ImageAccess::Final => vk::AccessFlags::empty(),
+        }
+    }
+
+    fn get_usage(self) -> vk::ImageUsageFlags {
+        match self {
+            ImageAccess::ColorAttachment => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            ImageAccess::DepthAttachment => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            ImageAccess::Sampled => vk::ImageUsageFlags::SAMPLED,
+            ImageAccess::TransferSrc => vk::ImageUsageFlags::TRANSFER_SRC,
+            ImageAccess::TransferDst => vk::ImageUsageFlags::TRANSFER_DST,
+            ImageAccess::Final => vk::ImageUsageFlags::empty(),
+        }
+    }
+}
+
+impl AttachmentLoad {
+    fn get_load_op(self) -> vk::AttachmentLoadOp {
+        match self {
+            AttachmentLoad::Clear => vk::AttachmentLoadOp::CLEAR,
+            AttachmentLoad::Load => vk::AttachmentLoadOp::LOAD,
+        }
+    }
+}
+
+impl BufferAccess {
+    /// Whether the access modifies the buffer, which means later accesses have to wait for it.
+    pub fn is_write(self) -> bool {
+        matches!(self, BufferAccess::StorageWrite | BufferAccess::TransferDst)
+    }
+
+    fn get_stage_mask(self) -> vk::PipelineStageFlags {
+        match self {
+            BufferAccess::VertexInput => vk::PipelineStageFlags::VERTEX_INPUT,
+            BufferAccess::Uniform | BufferAccess::StorageRead | BufferAccess::StorageWrite => {
+                vk::PipelineStageFlags::VERTEX_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COMPUTE_SHADER
+            }
+            BufferAccess::TransferSrc | BufferAccess::TransferDst => {
+                vk::PipelineStageFlags::TRANSFER
+            }
+        }
+    }
+
+    fn get_access_mask(self) -> vk::AccessFlags {
+        match self {
+            BufferAccess::VertexInput => {
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDEX_READ
+            }
+            BufferAccess::Uniform => vk::AccessFlags::UNIFORM_READ,
+            BufferAccess::StorageRead => vk::AccessFlags::SHADER_READ,
+            BufferAccess::StorageWrite => vk::AccessFlags::SHADER_WRITE,
+            BufferAccess::TransferSrc => vk::AccessFlags::TRANSFER_READ,
+            BufferAccess::TransferDst => vk::AccessFlags::TRANSFER_WRITE,
+        }
+    }
+}
+
+impl GraphImageFormat {
+    fn get_vk_format(
+        self,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        render_target: &RenderTarget,
+    ) -> Result<vk::Format> {
+        let format = match self {
+            GraphImageFormat::RenderTarget => render_target.get_format(),
+            GraphImageFormat::Depth => helpers::find_depth_format(instance, physical_device)?,
+            GraphImageFormat::Rgba8 => vk::Format::R8G8B8A8_UNORM,
+            GraphImageFormat::Rgba16Float => vk::Format::R16G16B16A16_SFLOAT,
+        };
+        Ok(format)
+    }
+}
+
+impl RenderGraphPlan {
+    fn all_image_barriers(&self) -> impl Iterator<Item = &ImageBarrier> {
+        self.passes
+            .iter()
+            .flat_map(|pass| pass.image_barriers.iter())
+            .chain(self.final_barriers.iter())
+    }
+}
+
+impl RenderGraphResources {
+    pub fn new(
+        plan: &RenderGraphPlan,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        render_target: &RenderTarget,
+        image_views: &ImageViews,
+        color_resource: &ColorResource,
+        depth_resource: &DepthResource,
+        msaa_samples: vk::SampleCountFlags,
+    ) -> Result<Self> {
+        // the render target images were not created by us, so we only support the accesses they were created for
+        for barrier in plan.all_image_barriers() {
+            if barrier.image == GraphImage::RenderTarget
+                && !matches!(
+                    barrier.to,
+                    ImageAccess::ColorAttachment | ImageAccess::Final
+                )
+            {
+                return Err(VulkanError::RenderGraphInvalid(format!(
+                    "the render target cannot be accessed as {:?}",
+                    barrier.to
+                )));
+            }
+        }
+
+        let mut result = Self {
+            transient_images: Vec::with_capacity(plan.transient_images.len()),
+            transient_buffers: Vec::with_capacity(plan.transient_buffers.len()),
+            pass_objects: Vec::with_capacity(plan.passes.len()),
+        };
+        // if anything fails part way through we still want to free what was already created
+        let create_result = result.create_objects(
+            plan,
+            instance,
+            physical_device,
+            allocator,
+            logical_device,
+            render_target,
+            image_views,
+            color_resource,
+            depth_resource,
+            msaa_samples,
+        );
+        match create_result {
+            Ok(()) => Ok(result),
+            Err(error) => {
+                unsafe {
+                    result.destroy(allocator, logical_device);
+                }
+                Err(error)
+            }
+        }
+    }
+
+    fn create_objects(
+        &mut self,
+        plan: &RenderGraphPlan,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        render_target: &RenderTarget,
+        image_views: &ImageViews,
+        color_resource: &ColorResource,
+        depth_resource: &DepthResource,
+        msaa_samples: vk::SampleCountFlags,
+    ) -> Result<()> {
+        let extent = render_target.get_extent();
+
+        for (transient_idx, graph_format) in plan.transient_images.iter().enumerate() {
+            let format = graph_format.get_vk_format(instance, physical_device, render_target)?;
+            // the image must support every way in which any of the passes access it
+            let usage = plan
+                .all_image_barriers()
+                .filter(|barrier| barrier.image == GraphImage::Transient(transient_idx))
+                .fold(vk::ImageUsageFlags::empty(), |usage, barrier| {
+                    usage | barrier.to.get_usage()
+                });
+            let aspect_mask = if *graph_format == GraphImageFormat::Depth {
+                vk::ImageAspectFlags::DEPTH
+            } else {
+                vk::ImageAspectFlags::COLOR
+            };
+
+            let image = Image::new(
+                extent.width,
+                extent.height,
+                MipmapParam::NoMipmap,
+                vk::SampleCountFlags::TYPE_1,
+                format,
+                vk::ImageTiling::OPTIMAL,
+                usage,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                allocator,
+                logical_device,
+            )?;
+            let view = image.create_image_view(format, aspect_mask, logical_device);
+            let view = match view {
+                Ok(view) => view,
+                Err(error) => {
+                    unsafe {
+                        image.destroy(allocator, logical_device);
+                    }
+                    return Err(error);
+                }
+            };
+            self.transient_images.push(TransientImage {
+                image,
+                view,
+                aspect_mask,
+            });
+        }
+
+        for buffer_description in plan.transient_buffers.iter() {
+            let buffer = Buffer::new(
+                allocator,
+                logical_device,
+                buffer_description.size,
+                vk::BufferUsageFlags::VERTEX_BUFFER
+                    | vk::BufferUsageFlags::INDEX_BUFFER
+                    | vk::BufferUsageFlags::UNIFORM_BUFFER
+                    | vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_SRC
+                    | vk::BufferUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            )?;
+            self.transient_buffers.push(buffer);
+        }
+
+        for pass in plan.passes.iter() {
+            let pass_objects = match &pass.attachments {
+                PassAttachments::None => PassObjects {
+                    render_pass: vk::RenderPass::null(),
+                    framebuffers: Vec::new(),
+                    clear_values: Vec::new(),
+                },
+                PassAttachments::Scene { resolve_target } => self.create_scene_pass_objects(
+                    plan,
+                    *resolve_target,
+                    instance,
+                    physical_device,
+                    logical_device,
+                    render_target,
+                    image_views,
+                    color_resource,
+                    depth_resource,
+                    msaa_samples,
+                )?,
+                PassAttachments::Custom {
+                    color_attachments,
+                    depth_attachment,
+                } => self.create_custom_pass_objects(
+                    plan,
+                    color_attachments,
+                    *depth_attachment,
+                    instance,
+                    physical_device,
+                    logical_device,
+                    render_target,
+                    image_views,
+                )?,
+            };
+            self.pass_objects.push(pass_objects);
+        }
+
+        Ok(())
+    }
+
+    fn create_scene_pass_objects(
+        &self,
+        plan: &RenderGraphPlan,
+        resolve_target: GraphImage,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        logical_device: &ash::Device,
+        render_target: &RenderTarget,
+        image_views: &ImageViews,
+        color_resource: &ColorResource,
+        depth_resource: &DepthResource,
+        msaa_samples: vk::SampleCountFlags,
+    ) -> Result<PassObjects> {
+        if let GraphImage::Transient(transient_idx) = resolve_target {
+            if plan.transient_images.get(transient_idx) != Some(&GraphImageFormat::RenderTarget) {
+                return Err(VulkanError::RenderGraphInvalid(String::from(
+                    "the scene pass can only resolve into images with the render target format",
+                )));
+            }
+        }
+
+        // the graph moves the resolve target into the color attachment layout before the pass and out of it after
+        let render_pass = create_scene_render_pass(
+            instance,
+            logical_device,
+            physical_device,
+            render_target.get_format(),
+            msaa_samples,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        )?;
+        let mut pass_objects = PassObjects {
+            render_pass,
+            framebuffers: Vec::new(),
+            clear_values: GraphicsPipeline::CLEAR_COLORS.to_vec(),
+        };
+
+        for image_index in 0..render_target.get_image_count() {
+            let attachments = [
+                color_resource.view,
+                depth_resource.depth_image_view,
+                self.get_image_view(resolve_target, image_index, image_views)?,
+            ];
+            let framebuffer = create_framebuffer(
+                logical_device,
+                render_pass,
+                &attachments,
+                render_target.get_extent(),
+            );
+            match framebuffer {
+                Ok(framebuffer) => pass_objects.framebuffers.push(framebuffer),
+                Err(error) => {
+                    unsafe {
+                        pass_objects.destroy(logical_device);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(pass_objects)
+    }
+
+    fn create_custom_pass_objects(
+        &self,
+        plan: &RenderGraphPlan,
+        color_attachments: &[GraphAttachment],
+        depth_attachment: Option<GraphAttachment>,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        logical_device: &ash::Device,
+        render_target: &RenderTarget,
+        image_views: &ImageViews,
+    ) -> Result<PassObjects> {
+        let get_format = |image: GraphImage| match image {
+            GraphImage::RenderTarget => Ok(render_target.get_format()),
+            GraphImage::Transient(transient_idx) => plan
+                .transient_images
+                .get(transient_idx)
+                .ok_or(VulkanError::RenderGraphInvalid(format!(
+                    "transient image {} does not exist",
+                    transient_idx
+                )))?
+                .get_vk_format(instance, physical_device, render_target),
+        };
+
+        let mut attachment_descriptions = Vec::new();
+        let mut clear_values = Vec::new();
+        // every attachment is already in the right layout because of the barriers planned by the graph
+        for color_attachment in color_attachments.iter() {
+            attachment_descriptions.push(vk::AttachmentDescription {
+                format: get_format(color_attachment.image)?,
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: color_attachment.load.get_load_op(),
+                store_op: vk::AttachmentStoreOp::STORE,
+                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                ..Default::default()
+            });
+            clear_values.push(GraphicsPipeline::CLEAR_COLORS[0]);
+        }
+        let color_attachment_refs: Vec<_> = (0..color_attachments.len())
+            .map(|attachment_idx| {
+                Ok(vk::AttachmentReference {
+                    attachment: u32::try_from(attachment_idx)?,
+                    layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let depth_attachment_ref = if let Some(depth_attachment) = depth_attachment {
+            attachment_descriptions.push(vk::AttachmentDescription {
+                format: get_format(depth_attachment.image)?,
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: depth_attachment.load.get_load_op(),
+                store_op: vk::AttachmentStoreOp::STORE,
+                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                ..Default::default()
+            });
+            clear_values.push(GraphicsPipeline::CLEAR_COLORS[1]);
+            Some(vk::AttachmentReference {
+                attachment: u32::try_from(color_attachments.len())?,
+                layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            })
+        } else {
+            None
+        };
+
+        let subpass = vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: u32::try_from(color_attachment_refs.len())?,
+            p_color_attachments: color_attachment_refs.as_ptr(),
+            p_depth_stencil_attachment: depth_attachment_ref
+                .as_ref()
+                .map_or(ptr::null(), |depth_attachment_ref| depth_attachment_ref),
+            ..Default::default()
+        };
+
+        let render_pass_create_info = vk::RenderPassCreateInfo {
+            attachment_count: u32::try_from(attachment_descriptions.len())?,
+            p_attachments: attachment_descriptions.as_ptr(),
+            subpass_count: 1,
+            p_subpasses: &subpass,
+            ..Default::default()
+        };
+        let render_pass =
+            unsafe { logical_device.create_render_pass(&render_pass_create_info, None)? };
+
+        let mut pass_objects = PassObjects {
+            render_pass,
+            framebuffers: Vec::new(),
+            clear_values,
+        };
+
+        for image_index in 0..render_target.get_image_count() {
+            let framebuffer = color_attachments
+                .iter()
+                .chain(depth_attachment.iter())
+                .map(|attachment| self.get_image_view(attachment.image, image_index, image_views))
+                .collect::<Result<Vec<_>>>()
+                .and_then(|attachments| {
+                    create_framebuffer(
+                        logical_device,
+                        render_pass,
+                        &attachments,
+                        render_target.get_extent(),
+                    )
+                });
+            match framebuffer {
+                Ok(framebuffer) => pass_objects.framebuffers.push(framebuffer),
+                Err(error) => {
+                    unsafe {
+                        pass_objects.destroy(logical_device);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(pass_objects)
+    }
+
+    fn get_image_view(
+        &self,
+        image: GraphImage,
+        image_index: usize,
+        image_views: &ImageViews,
+    ) -> Result<vk::ImageView> {
+        match image {
+            GraphImage::RenderTarget => image_views.image_views.get(image_index).copied().ok_or(
+                VulkanError::RenderGraphInvalid(format!(
+                    "no render target image with index {}",
+                    image_index
+                )),
+            ),
+            GraphImage::Transient(transient_idx) => self
+                .transient_images
+                .get(transient_idx)
+                .map(|transient_image| transient_image.view)
+                .ok_or(VulkanError::RenderGraphInvalid(format!(
+                    "transient image {} does not exist",
+                    transient_idx
+                ))),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy render passes, framebuffers, images and free memory.
+    /// It must be called with valid vulkan state and none of the objects may be in use by an in-flight frame.
+    pub unsafe fn destroy(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
+        for pass_objects in self.pass_objects {
+            pass_objects.destroy(logical_device);
+        }
+        for buffer in self.transient_buffers {
+            buffer.destroy(allocator, logical_device);
+        }
+        for transient_image in self.transient_images {
+            logical_device.destroy_image_view(transient_image.view, None);
+            transient_image.image.destroy(allocator, logical_device);
+        }
+    }
+}
+
+impl PassObjects {
+    unsafe fn destroy(self, logical_device: &ash::Device) {
+        for framebuffer in self.framebuffers {
+            logical_device.destroy_framebuffer(framebuffer, None);
+        }
+        if self.render_pass != vk::RenderPass::null() {
+            logical_device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
+
+impl<'a> PassRecordingContext<'a> {
+    pub fn get_image(&self, image: GraphImage) -> Result<vk::Image> {
+        match image {
+            GraphImage::RenderTarget => self
+                .render_target_images
+                .get(self.image_index)
+                .copied()
+                .ok_or(VulkanError::RenderGraphInvalid(format!(
+                    "no render target image with index {}",
+                    self.image_index
+                ))),
+            GraphImage::Transient(transient_idx) => self
+                .resources
+                .transient_images
+                .get(transient_idx)
+                .map(|transient_image| transient_image.image.image)
+                .ok_or(VulkanError::RenderGraphInvalid(format!(
+                    "transient image {} does not exist",
+                    transient_idx
+                ))),
+        }
+    }
+
+    pub fn get_image_view(&self, image: GraphImage) -> Result<vk::ImageView> {
+        self.resources
+            .get_image_view(image, self.image_index, self.render_target_image_views)
+    }
+
+    pub fn get_buffer(&self, buffer: GraphBuffer) -> Result<vk::Buffer> {
+        self.resources
+            .transient_buffers
+            .get(buffer.0)
+            .map(|transient_buffer| transient_buffer.buffer)
+            .ok_or(VulkanError::RenderGraphInvalid(format!(
+                "transient buffer {} does not exist",
+                buffer.0
+            )))
+    }
+}
+//////////////////////// Fns ///////////////////////
+/// Records all the passes of the `plan` into the `command_buffer`, rendering into the render target image with `image_index`.
+/// The command buffer has to be in the recording state, see `command_buffers::begin_frame_command_buffer`.
+pub fn record_render_graph_command_buffer(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    image_index: usize,
+    plan: &RenderGraphPlan,
+    resources: &RenderGraphResources,
+    render_target: &RenderTarget,
+    image_views: &ImageViews,
+    draw_list: &[DrawCommandData],
+) -> Result<()> {
+    let render_target_images = render_target.get_images();
+
+    for (pass, pass_objects) in plan.passes.iter().zip(resources.pass_objects.iter()) {
+        let context = PassRecordingContext {
+            logical_device,
+            command_buffer,
+            image_index,
+            extent: render_target.get_extent(),
+            render_pass: pass_objects.render_pass,
+            resources,
+            render_target_images: &render_target_images,
+            render_target_image_views: image_views,
+        };
+        record_barriers(
+            &context,
+            &pass.image_barriers,
+            &pass.buffer_barriers,
+            render_target,
+            plan,
+        )?;
+
+        if pass_objects.render_pass != vk::RenderPass::null() {
+            let render_pass_begin_info = vk::RenderPassBeginInfo {
+                render_pass: pass_objects.render_pass,
+                framebuffer: pass_objects.framebuffers[image_index],
+                render_area: vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: render_target.get_extent(),
+                },
+                clear_value_count: u32::try_from(pass_objects.clear_values.len())?,
+                p_clear_values: pass_objects.clear_values.as_ptr(),
+                ..Default::default()
+            };
+            unsafe {
+                logical_device.cmd_begin_render_pass(
+                    command_buffer,
+                    &render_pass_begin_info,
+                    vk::SubpassContents::INLINE,
+                );
+            }
+            // pipelines used by the recorders of custom passes can rely on this dynamic state too
+            command_buffers::set_viewport_and_scissor(
+                logical_device,
+                command_buffer,
+                render_target.get_extent(),
+            );
+        }
+
+        if let PassAttachments::Scene { .. } = pass.attachments {
+            for draw_command in draw_list.iter() {
+                command_buffers::record_draw_command(logical_device, command_buffer, draw_command)?;
+            }
+        }
+        if let Some(recorder) = &pass.recorder {
+            recorder.record(&context)?;
+        }
+
+        if pass_objects.render_pass != vk::RenderPass::null() {
+            unsafe {
+                logical_device.cmd_end_render_pass(command_buffer);
+            }
+        }
+    }
+
+    let context = PassRecordingContext {
+        logical_device,
+        command_buffer,
+        image_index,
+        extent: render_target.get_extent(),
+        render_pass: vk::RenderPass::null(),
+        resources,
+        render_target_images: &render_target_images,
+        render_target_image_views: image_views,
+    };
+    record_barriers(&context, &plan.final_barriers, &[], render_target, plan)?;
+
+    Ok(())
+}
+
+fn record_barriers(
+    context: &PassRecordingContext,
+    image_barriers: &[ImageBarrier],
+    buffer_barriers: &[BufferBarrier],
+    render_target: &RenderTarget,
+    plan: &RenderGraphPlan,
+) -> Result<()> {
+    if image_barriers.is_empty() && buffer_barriers.is_empty() {
+        return Ok(());
+    }
+
+    let mut src_stage_mask = vk::PipelineStageFlags::empty();
+    let mut dst_stage_mask = vk::PipelineStageFlags::empty();
+
+    let mut vk_image_barriers = Vec::with_capacity(image_barriers.len());
+    for barrier in image_barriers.iter() {
+        let aspect_mask = match barrier.image {
+            GraphImage::RenderTarget => vk::ImageAspectFlags::COLOR,
+            GraphImage::Transient(transient_idx) => {
+                context.resources.transient_images[transient_idx].aspect_mask
+            }
+        };
+        let old_layout = match barrier.from {
+            Some(from) if !barrier.discard_contents => from.get_layout(render_target),
+            _ => vk::ImageLayout::UNDEFINED,
+        };
+        // even if the contents are discarded we still have to wait until the previous access is done with the memory
+        let (src_stage, src_access) = match (barrier.from, barrier.image) {
+            (Some(from), _) => (from.get_stage_mask(), from.get_access_mask()),
+            // the wait on the image available semaphore happens in the color attachment output stage
+            // so the layout transition of the render target must not happen earlier than that
+            (None, GraphImage::RenderTarget) => (
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::empty(),
+            ),
+            (None, GraphImage::Transient(_)) => (
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::AccessFlags::empty(),
+            ),
+        };
+        src_stage_mask |= src_stage;
+        dst_stage_mask |= barrier.to.get_stage_mask();
+
+        vk_image_barriers.push(vk::ImageMemoryBarrier {
+            old_layout,
+            new_layout: barrier.to.get_layout(render_target),
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: context.get_image(barrier.image)?,
+            subresource_range: vk::ImageSubresourceRange::builder()
+                .aspect_mask(aspect_mask)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1)
+                .build(),
+            src_access_mask: src_access,
+            dst_access_mask: barrier.to.get_access_mask(),
+            ..Default::default()
+        });
+    }
+
+    let mut vk_buffer_barriers = Vec::with_capacity(buffer_barriers.len());
+    for barrier in buffer_barriers.iter() {
+        src_stage_mask |= barrier.from.get_stage_mask();
+        dst_stage_mask |= barrier.to.get_stage_mask();
+
+        vk_buffer_barriers.push(vk::BufferMemoryBarrier {
+            src_access_mask: barrier.from.get_access_mask(),
+            dst_access_mask: barrier.to.get_access_mask(),
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer: context.get_buffer(barrier.buffer)?,
+            offset: 0,
+            size: plan.transient_buffers[barrier.buffer.0].size,
+            ..Default::default()
+        });
+    }
+
+    unsafe {
+        context.logical_device.cmd_pipeline_barrier(
+            context.command_buffer,
+            src_stage_mask,
+            dst_stage_mask,
+            vk::DependencyFlags::empty(),
+            &[],
+            &vk_buffer_barriers,
+            &vk_image_barriers,
+        );
+    }
+
+    Ok(())
+}
+
+fn create_framebuffer(
+    logical_device: &ash::Device,
+    render_pass: vk::RenderPass,
+    attachments: &[vk::ImageView],
+    extent: vk::Extent2D,
+) -> Result<vk::Framebuffer> {
+    let framebuffer_create_info = vk::FramebufferCreateInfo {
+        render_pass,
+        attachment_count: u32::try_from(attachments.len())?,
+        p_attachments: attachments.as_ptr(),
+        width: extent.width,
+        height: extent.height,
+        layers: 1,
+        ..Default::default()
+    };
+
+    let framebuffer = unsafe { logical_device.create_framebuffer(&framebuffer_create_info, None)? };
+    Ok(framebuffer)
+}
@@ -1,21 +1,28 @@
-use crate::util::result::Result;
+use crate::util::result::{Result, VulkanError};
 
 use ash::vk;
 
 use std::collections::HashMap;
 
+/// The semaphores and fences of every frame in flight.
+/// The CPU can record up to `frames_in_flight` frames before it has to wait for the GPU to finish the oldest one.
 pub struct SynchronizationContainer {
-    image_available_semaphores: [vk::Semaphore; SynchronizationContainer::MAX_FRAMES_IN_FLIGHT],
-    render_finished_semaphores: [vk::Semaphore; SynchronizationContainer::MAX_FRAMES_IN_FLIGHT],
-    in_flight_fences: [vk::Fence; SynchronizationContainer::MAX_FRAMES_IN_FLIGHT],
+    image_available_semaphores: Vec<vk::Semaphore>,
+    render_finished_semaphores: Vec<vk::Semaphore>,
+    in_flight_fences: Vec<vk::Fence>,
     images_in_flight_fences: HashMap<usize, vk::Fence>,
     current_frame_idx: usize,
+    /// how many frames were submitted so far
+    submitted_frame_count: u64,
+    /// for every in flight fence, how many frames were submitted when it was last used
+    fence_frame_counts: Vec<u64>,
 }
 
 impl SynchronizationContainer {
-    const MAX_FRAMES_IN_FLIGHT: usize = 2;
-
-    pub fn create(logical_device: &ash::Device) -> Result<Self> {
+    pub fn create(logical_device: &ash::Device, frames_in_flight: usize) -> Result<Self> {
+        if frames_in_flight == 0 {
+            return Err(VulkanError::FramesInFlightInvalid(frames_in_flight));
+        }
         let semaphore_create_info = vk::SemaphoreCreateInfo {
             ..Default::default()
         };
@@ -25,17 +32,17 @@ impl SynchronizationContainer {
             ..Default::default()
         };
 
-        let mut image_available_semaphores = [vk::Semaphore::null(); Self::MAX_FRAMES_IN_FLIGHT];
-        let mut render_finished_semaphores = [vk::Semaphore::null(); Self::MAX_FRAMES_IN_FLIGHT];
-        let mut in_flight_fences =
-            [vk::Fence::null(); SynchronizationContainer::MAX_FRAMES_IN_FLIGHT];
-
-        for i in 0..Self::MAX_FRAMES_IN_FLIGHT {
-            image_available_semaphores[i] =
-                unsafe { logical_device.create_semaphore(&semaphore_create_info, None)? };
-            render_finished_semaphores[i] =
-                unsafe { logical_device.create_semaphore(&semaphore_create_info, None)? };
-            in_flight_fences[i] = unsafe { logical_device.create_fence(&fence_create_info, None)? };
+        let mut image_available_semaphores = Vec::with_capacity(frames_in_flight);
+        let mut render_finished_semaphores = Vec::with_capacity(frames_in_flight);
+        let mut in_flight_fences = Vec::with_capacity(frames_in_flight);
+
+        for _ in 0..frames_in_flight {
+            image_available_semaphores
+                .push(unsafe { logical_device.create_semaphore(&semaphore_create_info, None)? });
+            render_finished_semaphores
+                .push(unsafe { logical_device.create_semaphore(&semaphore_create_info, None)? });
+            in_flight_fences
+                .push(unsafe { logical_device.create_fence(&fence_create_info, None)? });
         }
 
         let images_in_flight_fences = HashMap::new();
@@ -46,11 +53,39 @@ impl SynchronizationContainer {
             in_flight_fences,
             images_in_flight_fences,
             current_frame_idx: 0,
+            submitted_frame_count: 0,
+            fence_frame_counts: vec![0; frames_in_flight],
         })
     }
 
     pub fn update_frame_counter(&mut self) {
-        self.current_frame_idx = (self.current_frame_idx + 1) % Self::MAX_FRAMES_IN_FLIGHT
+        self.current_frame_idx = (self.current_frame_idx + 1) % self.get_frames_in_flight()
+    }
+
+    /// Has to be called once the frame of the current in flight fence was submitted.
+    pub fn mark_frame_submitted(&mut self) {
+        self.submitted_frame_count += 1;
+        self.fence_frame_counts[self.current_frame_idx] = self.submitted_frame_count;
+    }
+
+    pub fn get_submitted_frame_count(&self) -> u64 {
+        self.submitted_frame_count
+    }
+
+    /// Returns how many of the submitted frames the GPU has finished, without waiting for any of them.
+    /// A signaled fence means that the frame it was submitted with and all frames before it are finished.
+    pub fn get_finished_frame_count(&self, logical_device: &ash::Device) -> Result<u64> {
+        let mut finished_frame_count = 0;
+        for (&fence, &frame_count) in self.in_flight_fences.iter().zip(&self.fence_frame_counts) {
+            if unsafe { logical_device.get_fence_status(fence)? } {
+                finished_frame_count = finished_frame_count.max(frame_count);
+            }
+        }
+        Ok(finished_frame_count)
+    }
+
+    pub fn get_frames_in_flight(&self) -> usize {
+        self.in_flight_fences.len()
     }
 
     /// # Safety
@@ -68,6 +103,10 @@ impl SynchronizationContainer {
         }
     }
 
+    pub fn get_current_frame_idx(&self) -> usize {
+        self.current_frame_idx
+    }
+
     pub fn get_image_available_semaphore(&self) -> vk::Semaphore {
         self.image_available_semaphores[self.current_frame_idx]
     }
@@ -0,0 +1,538 @@
+use crate::{
+    buffers::{buffer::Buffer, memory::allocator::MemoryAllocator},
+    devices::queues::{QueueFamilyIndices, QueueMap, QueueType},
+    textures::images::Image,
+    util::result::{Result, VulkanError},
+};
+use ash::vk;
+use log::{debug, error};
+use std::mem;
+//////////////////////// Structs ///////////////////////
+/// Records the staging copies of all uploads into a batch which is submitted at once, instead of waiting for every copy.
+/// If the device has a transfer queue family without graphics support, then the copies run on that queue
+/// and the ownership of the uploaded resources is transferred to the graphics queue family.
+/// The staging buffers of a batch are freed once the fence of the batch is signaled.
+pub struct UploadContext {
+    transfer_queue: vk::Queue,
+    transfer_family: u32,
+    graphics_queue: vk::Queue,
+    graphics_family: u32,
+    transfer_command_pool: vk::CommandPool,
+    graphics_command_pool: vk::CommandPool,
+    recording_batch: Option<UploadBatch>,
+    submitted_batches: Vec<UploadBatch>,
+}
+
+struct UploadBatch {
+    transfer_command_buffer: vk::CommandBuffer,
+    /// the same as the transfer command buffer when there is no dedicated transfer queue
+    graphics_command_buffer: vk::CommandBuffer,
+    staging_buffers: Vec<Buffer>,
+    /// signaled by the transfer submission and waited on by the graphics submission
+    transfer_finished: vk::Semaphore,
+    upload_finished: vk::Fence,
+}
+//////////////////////// Impls ///////////////////////
+impl UploadContext {
+    pub fn new(
+        logical_device: &ash::Device,
+        queue_indices: &QueueFamilyIndices,
+        queues: &QueueMap,
+    ) -> Result<Self> {
+        let graphics_family = *queue_indices
+            .queue_index_map
+            .get(&QueueType::QueueWithFlag(vk::QueueFlags::GRAPHICS))
+            .ok_or(VulkanError::QueueGraphicsNotFound)?;
+        let graphics_queue = queues.get_graphics_queue()?;
+        // graphics queues always support transfer operations, so they are the fallback
+        let (transfer_family, transfer_queue) = match (
+            queue_indices.get_transfer_family(),
+            queues.get_transfer_queue(),
+        ) {
+            (Some(transfer_family), Some(transfer_queue)) => (transfer_family, transfer_queue),
+            _ => (graphics_family, graphics_queue),
+        };
+
+        let graphics_command_pool = create_transient_command_pool(logical_device, graphics_family)?;
+        let transfer_command_pool = if transfer_family != graphics_family {
+            create_transient_command_pool(logical_device, transfer_family)?
+        } else {
+            graphics_command_pool
+        };
+
+        Ok(Self {
+            transfer_queue,
+            transfer_family,
+            graphics_queue,
+            graphics_family,
+            transfer_command_pool,
+            graphics_command_pool,
+            recording_batch: None,
+            submitted_batches: Vec::new(),
+        })
+    }
+
+    pub fn has_dedicated_transfer_queue(&self) -> bool {
+        self.transfer_family != self.graphics_family
+    }
+
+    /// Records a copy of the whole `staging_buffer` into `dst_buffer`.
+    /// The `dst_usage` decides which stages of later graphics work wait for the copy.
+    pub fn copy_to_buffer(
+        &mut self,
+        staging_buffer: Buffer,
+        dst_buffer: vk::Buffer,
+        size: vk::DeviceSize,
+        dst_usage: vk::BufferUsageFlags,
+        logical_device: &ash::Device,
+    ) -> Result<()> {
+        let (transfer_family, graphics_family) = (self.transfer_family, self.graphics_family);
+        let is_dedicated = self.has_dedicated_transfer_queue();
+        let (dst_stage, dst_access) = get_buffer_destination(dst_usage);
+        let batch = self.get_recording_batch(logical_device)?;
+
+        let copy_regions = [vk::BufferCopy {
+            size,
+            ..Default::default()
+        }];
+        let barrier = vk::BufferMemoryBarrier {
+            buffer: dst_buffer,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: dst_access,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            ..Default::default()
+        };
+
+        unsafe {
+            logical_device.cmd_copy_buffer(
+                batch.transfer_command_buffer,
+                staging_buffer.buffer,
+                dst_buffer,
+                &copy_regions,
+            );
+
+            if is_dedicated {
+                // the release on the transfer queue and the acquire on the graphics queue have to describe the same transfer
+                let ownership_barrier = vk::BufferMemoryBarrier {
+                    src_queue_family_index: transfer_family,
+                    dst_queue_family_index: graphics_family,
+                    ..barrier
+                };
+                logical_device.cmd_pipeline_barrier(
+                    batch.transfer_command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[vk::BufferMemoryBarrier {
+                        dst_access_mask: vk::AccessFlags::empty(),
+                        ..ownership_barrier
+                    }],
+                    &[],
+                );
+                logical_device.cmd_pipeline_barrier(
+                    batch.graphics_command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    dst_stage,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[vk::BufferMemoryBarrier {
+                        src_access_mask: vk::AccessFlags::empty(),
+                        ..ownership_barrier
+                    }],
+                    &[],
+                );
+            } else {
+                logical_device.cmd_pipeline_barrier(
+                    batch.transfer_command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    dst_stage,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[barrier],
+                    &[],
+                );
+            }
+        }
+
+        batch.staging_buffers.push(staging_buffer);
+        Ok(())
+    }
+
+    /// Records a copy of the `staging_buffer` into the first mip level of all layers of `image`.
+    /// Afterwards all mip levels of the image are in the `TRANSFER_DST_OPTIMAL` layout and owned by the graphics queue family,
+    /// so the rest of the upload has to be recorded into the graphics command buffer of the batch.
+    pub fn copy_to_image(
+        &mut self,
+        staging_buffer: Buffer,
+        image: &Image,
+        logical_device: &ash::Device,
+    ) -> Result<()> {
+        self.copy_mip_levels_to_image(staging_buffer, image, &[0], logical_device)
+    }
+
+    /// Like `copy_to_image`, but copies one mip level for each of the `mip_level_offsets` into the staging buffer.
+    /// Every mip level holds all array layers of the image one after another.
+    pub fn copy_mip_levels_to_image(
+        &mut self,
+        staging_buffer: Buffer,
+        image: &Image,
+        mip_level_offsets: &[vk::DeviceSize],
+        logical_device: &ash::Device,
+    ) -> Result<()> {
+        let (transfer_family, graphics_family) = (self.transfer_family, self.graphics_family);
+        let is_dedicated = self.has_dedicated_transfer_queue();
+        let batch = self.get_recording_batch(logical_device)?;
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(image.mip_levels)
+            .base_array_layer(0)
+            .layer_count(image.array_layers)
+            .build();
+        let to_transfer_barrier = vk::ImageMemoryBarrier {
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: image.image,
+            subresource_range,
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            ..Default::default()
+        };
+        let buffer_image_copies: Vec<_> = (0u32..)
+            .zip(mip_level_offsets.iter())
+            .map(|(mip_level, &buffer_offset)| vk::BufferImageCopy {
+                buffer_offset,
+                // 0 for row_length and height just says pixels are tightly packed
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(mip_level)
+                    .base_array_layer(0)
+                    .layer_count(image.array_layers)
+                    .build(),
+                image_offset: vk::Offset3D::builder().x(0).y(0).z(0).build(),
+                image_extent: vk::Extent3D::builder()
+                    .width((image.width >> mip_level).max(1))
+                    .height((image.height >> mip_level).max(1))
+                    .depth(1)
+                    .build(),
+            })
+            .collect();
+
+        unsafe {
+            logical_device.cmd_pipeline_barrier(
+                batch.transfer_command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer_barrier],
+            );
+            logical_device.cmd_copy_buffer_to_image(
+                batch.transfer_command_buffer,
+                staging_buffer.buffer,
+                image.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &buffer_image_copies,
+            );
+
+            if is_dedicated {
+                // the layout stays the same, only the queue family that owns the image changes
+                let ownership_barrier = vk::ImageMemoryBarrier {
+                    old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    src_queue_family_index: transfer_family,
+                    dst_queue_family_index: graphics_family,
+                    ..to_transfer_barrier
+                };
+                logical_device.cmd_pipeline_barrier(
+                    batch.transfer_command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[vk::ImageMemoryBarrier {
+                        src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                        dst_access_mask: vk::AccessFlags::empty(),
+                        ..ownership_barrier
+                    }],
+                );
+                logical_device.cmd_pipeline_barrier(
+                    batch.graphics_command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[vk::ImageMemoryBarrier {
+                        src_access_mask: vk::AccessFlags::empty(),
+                        dst_access_mask: vk::AccessFlags::TRANSFER_READ
+                            | vk::AccessFlags::TRANSFER_WRITE,
+                        ..ownership_barrier
+                    }],
+                );
+            }
+        }
+
+        batch.staging_buffers.push(staging_buffer);
+        Ok(())
+    }
+
+    /// The command buffer of the current batch that runs on the graphics queue after all copies of the batch are done.
+    pub fn get_graphics_command_buffer(
+        &mut self,
+        logical_device: &ash::Device,
+    ) -> Result<vk::CommandBuffer> {
+        Ok(self
+            .get_recording_batch(logical_device)?
+            .graphics_command_buffer)
+    }
+
+    /// Submits everything that was recorded since the last submit. This does not wait for the uploads to finish.
+    /// Graphics work that is submitted afterwards sees the uploaded data, since the graphics submission of the batch
+    /// ends with barriers for the stages that read it.
+    pub fn submit(&mut self, logical_device: &ash::Device) -> Result<()> {
+        let batch = match self.recording_batch.take() {
+            Some(batch) => batch,
+            None => return Ok(()),
+        };
+        debug!(
+            "Submitting upload batch with {} staging buffers",
+            batch.staging_buffers.len()
+        );
+
+        unsafe {
+            if self.has_dedicated_transfer_queue() {
+                logical_device.end_command_buffer(batch.transfer_command_buffer)?;
+                logical_device.end_command_buffer(batch.graphics_command_buffer)?;
+
+                let transfer_submit_info = [vk::SubmitInfo {
+                    command_buffer_count: 1,
+                    p_command_buffers: &batch.transfer_command_buffer,
+                    signal_semaphore_count: 1,
+                    p_signal_semaphores: &batch.transfer_finished,
+                    ..Default::default()
+                }];
+                logical_device.queue_submit(
+                    self.transfer_queue,
+                    &transfer_submit_info,
+                    vk::Fence::null(),
+                )?;
+
+                let wait_stage = vk::PipelineStageFlags::ALL_COMMANDS;
+                let graphics_submit_info = [vk::SubmitInfo {
+                    wait_semaphore_count: 1,
+                    p_wait_semaphores: &batch.transfer_finished,
+                    p_wait_dst_stage_mask: &wait_stage,
+                    command_buffer_count: 1,
+                    p_command_buffers: &batch.graphics_command_buffer,
+                    ..Default::default()
+                }];
+                logical_device.queue_submit(
+                    self.graphics_queue,
+                    &graphics_submit_info,
+                    batch.upload_finished,
+                )?;
+            } else {
+                logical_device.end_command_buffer(batch.transfer_command_buffer)?;
+
+                let submit_info = [vk::SubmitInfo {
+                    command_buffer_count: 1,
+                    p_command_buffers: &batch.transfer_command_buffer,
+                    ..Default::default()
+                }];
+                logical_device.queue_submit(
+                    self.graphics_queue,
+                    &submit_info,
+                    batch.upload_finished,
+                )?;
+            }
+        }
+
+        self.submitted_batches.push(batch);
+        Ok(())
+    }
+
+    /// Frees the staging buffers and command buffers of all submitted batches whose fence is signaled.
+    ///
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy buffers and free memory.
+    /// It must be called with valid vulkan state.
+    pub unsafe fn collect_finished(
+        &mut self,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) -> Result<()> {
+        let mut pending_batches = Vec::with_capacity(self.submitted_batches.len());
+        for batch in mem::take(&mut self.submitted_batches) {
+            if logical_device.get_fence_status(batch.upload_finished)? {
+                self.destroy_batch(batch, allocator, logical_device);
+            } else {
+                pending_batches.push(batch);
+            }
+        }
+        self.submitted_batches = pending_batches;
+        Ok(())
+    }
+
+    /// Submits everything that was recorded and blocks until all uploads are finished.
+    pub fn wait_until_finished(
+        &mut self,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) -> Result<()> {
+        self.submit(logical_device)?;
+        let fences: Vec<_> = self
+            .submitted_batches
+            .iter()
+            .map(|batch| batch.upload_finished)
+            .collect();
+        unsafe {
+            if !fences.is_empty() {
+                logical_device.wait_for_fences(&fences, true, u64::MAX)?;
+            }
+            self.collect_finished(allocator, logical_device)
+        }
+    }
+
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy the command pools.
+    /// It must be called with valid vulkan state.
+    pub unsafe fn destroy(
+        &mut self,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) {
+        if let Err(error) = self.wait_until_finished(allocator, logical_device) {
+            error!("Failed to finish uploads: {}", error);
+        }
+        logical_device.destroy_command_pool(self.graphics_command_pool, None);
+        if self.transfer_command_pool != self.graphics_command_pool {
+            logical_device.destroy_command_pool(self.transfer_command_pool, None);
+        }
+    }
+
+    fn get_recording_batch(&mut self, logical_device: &ash::Device) -> Result<&mut UploadBatch> {
+        if self.recording_batch.is_none() {
+            self.recording_batch = Some(self.begin_batch(logical_device)?);
+        }
+        self.recording_batch
+            .as_mut()
+            .ok_or(VulkanError::CommandBufferNotAvailable(0))
+    }
+
+    fn begin_batch(&self, logical_device: &ash::Device) -> Result<UploadBatch> {
+        let transfer_command_buffer =
+            begin_command_buffer(logical_device, self.transfer_command_pool)?;
+        let (graphics_command_buffer, transfer_finished) = if self.has_dedicated_transfer_queue() {
+            let semaphore_create_info = vk::SemaphoreCreateInfo::default();
+            (
+                begin_command_buffer(logical_device, self.graphics_command_pool)?,
+                unsafe { logical_device.create_semaphore(&semaphore_create_info, None)? },
+            )
+        } else {
+            (transfer_command_buffer, vk::Semaphore::null())
+        };
+        let fence_create_info = vk::FenceCreateInfo::default();
+        let upload_finished = unsafe { logical_device.create_fence(&fence_create_info, None)? };
+
+        Ok(UploadBatch {
+            transfer_command_buffer,
+            graphics_command_buffer,
+            staging_buffers: Vec::new(),
+            transfer_finished,
+            upload_finished,
+        })
+    }
+
+    unsafe fn destroy_batch(
+        &self,
+        batch: UploadBatch,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) {
+        for staging_buffer in batch.staging_buffers {
+            staging_buffer.destroy(allocator, logical_device);
+        }
+        logical_device
+            .free_command_buffers(self.transfer_command_pool, &[batch.transfer_command_buffer]);
+        if self.has_dedicated_transfer_queue() {
+            logical_device
+                .free_command_buffers(self.graphics_command_pool, &[batch.graphics_command_buffer]);
+            logical_device.destroy_semaphore(batch.transfer_finished, None);
+        }
+        logical_device.destroy_fence(batch.upload_finished, None);
+    }
+}
+//////////////////////// Fns ///////////////////////
+fn create_transient_command_pool(
+    logical_device: &ash::Device,
+    queue_family_index: u32,
+) -> Result<vk::CommandPool> {
+    let pool_info = vk::CommandPoolCreateInfo {
+        flags: vk::CommandPoolCreateFlags::TRANSIENT,
+        queue_family_index,
+        ..Default::default()
+    };
+    let command_pool = unsafe { logical_device.create_command_pool(&pool_info, None)? };
+    Ok(command_pool)
+}
+
+fn begin_command_buffer(
+    logical_device: &ash::Device,
+    command_pool: vk::CommandPool,
+) -> Result<vk::CommandBuffer> {
+    let cb_alloc_info = vk::CommandBufferAllocateInfo {
+        level: vk::CommandBufferLevel::PRIMARY,
+        command_pool,
+        command_buffer_count: 1,
+        ..Default::default()
+    };
+    let command_buffer = unsafe { logical_device.allocate_command_buffers(&cb_alloc_info)? }
+        .into_iter()
+        .next()
+        .ok_or(VulkanError::CommandBufferNotAvailable(0))?;
+
+    let cb_begin_info = vk::CommandBufferBeginInfo {
+        flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+        ..Default::default()
+    };
+    unsafe {
+        logical_device.begin_command_buffer(command_buffer, &cb_begin_info)?;
+    }
+
+    Ok(command_buffer)
+}
+
+/// The stages and accesses of graphics work which reads a buffer with the `usage`.
+fn get_buffer_destination(
+    usage: vk::BufferUsageFlags,
+) -> (vk::PipelineStageFlags, vk::AccessFlags) {
+    if usage.contains(vk::BufferUsageFlags::VERTEX_BUFFER) {
+        (
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+        )
+    } else if usage.contains(vk::BufferUsageFlags::INDEX_BUFFER) {
+        (
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::INDEX_READ,
+        )
+    } else {
+        (
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::MEMORY_READ,
+        )
+    }
+}
@@ -1,17 +1,19 @@
+pub mod pipeline_cache;
+pub mod pipeline_descriptor;
 pub mod render_pass;
 pub mod shader_module;
+pub mod shader_reflection;
 
 use crate::{
-    graphics_pipeline::{render_pass::create_render_pass, shader_module::create_shader_module},
-    models::vertex::Vertex,
-    presentation::swap_chain::SwapChainContainer,
+    graphics_pipeline::pipeline_descriptor::PipelineDescriptor,
+    models::{instance::InstanceData, vertex::VertexLayout},
     util::result::{Result, VulkanError},
 };
 use ash::{vk};
-use std::{convert::TryFrom, ffi::CString, path::Path, ptr};
+use mimic_common::uniforms::MAX_PUSH_CONSTANTS_SIZE;
+use std::{convert::TryFrom, ffi::CString, ptr};
 //////////////////////// Structs ///////////////////////
 pub struct GraphicsPipeline {
-    pub render_pass: vk::RenderPass,
     pub pipeline_layout: vk::PipelineLayout,
     pub pipeline: vk::Pipeline,
 }
@@ -34,19 +36,36 @@ impl GraphicsPipeline {
         },
     ];
 
+    /// the shader stages which can read the push constants
+    pub const PUSH_CONSTANTS_STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::from_raw(
+        vk::ShaderStageFlags::VERTEX.as_raw() | vk::ShaderStageFlags::FRAGMENT.as_raw(),
+    );
+
+    /// The attributes of the vertex buffer with the `vertex_layout` followed by the attributes of the instance buffer.
+    pub fn get_vertex_input_attributes(
+        vertex_layout: &VertexLayout,
+    ) -> Result<Vec<vk::VertexInputAttributeDescription>> {
+        let first_instance_location = vertex_layout.get_location_count()?;
+        Ok(vertex_layout
+            .get_attribute_descriptions()?
+            .iter()
+            .chain(InstanceData::get_attribute_descriptions(first_instance_location)?.iter())
+            .copied()
+            .collect())
+    }
+
+    /// The shader modules are owned by the caller and have to outlive the creation of the pipeline.
     pub fn new(
-        vertex_shader_file: &Path,
-        fragment_shader_file: &Path,
-        instance: &ash::Instance,
+        vert_shader: vk::ShaderModule,
+        frag_shader: vk::ShaderModule,
+        descriptor: &PipelineDescriptor,
+        vertex_layout: &VertexLayout,
         logical_device: &ash::Device,
-        physical_device: vk::PhysicalDevice,
-        swap_chain_container: &SwapChainContainer,
-        uniform_descriptors: &vk::DescriptorSetLayout,
+        render_pass: vk::RenderPass,
+        descriptor_set_layouts: &[vk::DescriptorSetLayout],
         msaa_samples: vk::SampleCountFlags,
+        pipeline_cache: vk::PipelineCache,
     ) -> Result<Self> {
-        let vert_shader = create_shader_module(vertex_shader_file, logical_device)?;
-        let frag_shader = create_shader_module(fragment_shader_file, logical_device)?;
-
         let main_function = match CString::new("main") {
             Ok(value) => value,
             Err(_) => return Err(VulkanError::PipelineCreateError),
@@ -76,56 +95,52 @@ impl GraphicsPipeline {
         ////////////////////////////
         // FIXED FUNCTION stages
         ////////////////////////////
-        let vertex_input_binding = Vertex::get_binding_description()?;
-        let vertex_input_attributes = Vertex::get_attribute_descriptions()?;
+        // every draw binds an instance buffer, objects that aren't instanced have a single default instance
+        let vertex_input_bindings = [
+            vertex_layout.get_binding_description()?,
+            InstanceData::get_binding_description()?,
+        ];
+        let vertex_input_attributes = Self::get_vertex_input_attributes(vertex_layout)?;
 
         let vertex_input_create_info = vk::PipelineVertexInputStateCreateInfo {
-            vertex_binding_description_count: 1,
-            p_vertex_binding_descriptions: &vertex_input_binding,
+            vertex_binding_description_count: u32::try_from(vertex_input_bindings.len())?,
+            p_vertex_binding_descriptions: vertex_input_bindings.as_ptr(),
             vertex_attribute_description_count: u32::try_from(vertex_input_attributes.len())?,
             p_vertex_attribute_descriptions: vertex_input_attributes.as_ptr(),
             ..Default::default()
         };
 
         let input_assembly_create_info = vk::PipelineInputAssemblyStateCreateInfo {
-            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            topology: descriptor.topology.to_vk(),
             primitive_restart_enable: vk::FALSE,
             ..Default::default()
         };
 
-        // viewport defines the transformation from image to framebuffer
-        let viewport = vk::Viewport {
-            x: 0.0f32,
-            y: 0.0f32,
-            width: swap_chain_container.swap_chain_extent.width as f32,
-            height: swap_chain_container.swap_chain_extent.height as f32,
-            min_depth: 0.0f32,
-            max_depth: 1.0f32,
-        };
-
-        // scissor defines a filter of which part of the image to show
-        let scissor = vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: swap_chain_container.swap_chain_extent,
-        };
-
+        // the viewport and scissor are set when the command buffer is recorded
+        // that way the pipeline doesn't depend on the size of the render target and survives a resize
         let viewport_create_info = vk::PipelineViewportStateCreateInfo {
             viewport_count: 1,
-            p_viewports: &viewport,
+            p_viewports: ptr::null(),
             scissor_count: 1,
-            p_scissors: &scissor,
+            p_scissors: ptr::null(),
+            ..Default::default()
+        };
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo {
+            dynamic_state_count: u32::try_from(dynamic_states.len())?,
+            p_dynamic_states: dynamic_states.as_ptr(),
             ..Default::default()
         };
 
         let rasterization_create_info = vk::PipelineRasterizationStateCreateInfo {
             depth_clamp_enable: vk::FALSE,
             rasterizer_discard_enable: vk::FALSE,
-            polygon_mode: vk::PolygonMode::FILL,
+            polygon_mode: descriptor.polygon_mode.to_vk(),
             line_width: 1.0f32,
-            cull_mode: vk::CullModeFlags::BACK,
-            // TODO
+            cull_mode: descriptor.cull_mode.to_vk(),
             // front_face value depends a lot on what model we are trying to show
-            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            front_face: descriptor.front_face.to_vk(),
             depth_bias_enable: vk::FALSE,
             ..Default::default()
         };
@@ -136,16 +151,7 @@ impl GraphicsPipeline {
             ..Default::default()
         };
 
-        let color_blend_attachment_state = vk::PipelineColorBlendAttachmentState {
-            color_write_mask: vk::ColorComponentFlags::RGBA,
-            blend_enable: vk::TRUE,
-            src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
-            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
-            color_blend_op: vk::BlendOp::ADD,
-            src_alpha_blend_factor: vk::BlendFactor::ONE,
-            dst_alpha_blend_factor: vk::BlendFactor::ZERO,
-            alpha_blend_op: vk::BlendOp::ADD,
-        };
+        let color_blend_attachment_state = descriptor.blend_mode.to_vk();
 
         let color_blending_create_info = vk::PipelineColorBlendStateCreateInfo {
             logic_op_enable: vk::FALSE,
@@ -154,12 +160,21 @@ impl GraphicsPipeline {
             ..Default::default()
         };
 
-        // enable depth testing in graphics pipleine
+        // depth testing in graphics pipleine
+        // lower means closer, so by default keep fragments that have less depth
+        let depth_compare_op = descriptor.depth_test.to_vk();
         let depth_stencil = vk::PipelineDepthStencilStateCreateInfo {
-            depth_test_enable: vk::TRUE,
-            depth_write_enable: vk::TRUE,
-            // lower means closer, so keep fragments that have less depth
-            depth_compare_op: vk::CompareOp::LESS,
+            depth_test_enable: if depth_compare_op.is_some() {
+                vk::TRUE
+            } else {
+                vk::FALSE
+            },
+            depth_write_enable: if descriptor.depth_write {
+                vk::TRUE
+            } else {
+                vk::FALSE
+            },
+            depth_compare_op: depth_compare_op.unwrap_or(vk::CompareOp::ALWAYS),
             depth_bounds_test_enable: vk::FALSE,
             min_depth_bounds: 0.0,
             max_depth_bounds: 1.0,
@@ -167,20 +182,21 @@ impl GraphicsPipeline {
             ..Default::default()
         };
 
+        // every pipeline has room for the per-object push constants so that all pipeline layouts are compatible
+        let push_constant_range = vk::PushConstantRange {
+            stage_flags: Self::PUSH_CONSTANTS_STAGES,
+            offset: 0,
+            size: u32::try_from(MAX_PUSH_CONSTANTS_SIZE)?,
+        };
+
         let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
-            set_layout_count: 1,
-            p_set_layouts: uniform_descriptors,
+            set_layout_count: u32::try_from(descriptor_set_layouts.len())?,
+            p_set_layouts: descriptor_set_layouts.as_ptr(),
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constant_range,
             ..Default::default()
         };
 
-        let render_pass = create_render_pass(
-            instance,
-            logical_device,
-            physical_device,
-            swap_chain_container,
-            msaa_samples,
-        )?;
-
         let pipeline_layout =
             unsafe { logical_device.create_pipeline_layout(&pipeline_layout_create_info, None)? };
 
@@ -196,7 +212,7 @@ impl GraphicsPipeline {
             p_multisample_state: &multisampling_create_info,
             p_depth_stencil_state: &depth_stencil,
             p_color_blend_state: &color_blending_create_info,
-            p_dynamic_state: ptr::null(),
+            p_dynamic_state: &dynamic_state_create_info,
             // layout defining uniforms etc
             layout: pipeline_layout,
             // render pass and index of subpass where pipeline will be used
@@ -210,7 +226,7 @@ impl GraphicsPipeline {
         let graphics_pipeline_infos = [pipeline_create_info];
         let graphics_pipeline_result = unsafe {
             logical_device.create_graphics_pipelines(
-                vk::PipelineCache::null(),
+                pipeline_cache,
                 &graphics_pipeline_infos,
                 None,
             )
@@ -226,15 +242,18 @@ impl GraphicsPipeline {
         }
         let pipeline = graphics_pipelines[0];
 
-        unsafe {
-            logical_device.destroy_shader_module(vert_shader, None);
-            logical_device.destroy_shader_module(frag_shader, None);
-        }
-
         Ok(Self {
-            render_pass,
             pipeline_layout,
             pipeline,
         })
     }
+
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy the pipeline and its layout.
+    /// It must be called with valid vulkan state.
+    pub unsafe fn destroy(self, logical_device: &ash::Device) {
+        logical_device.destroy_pipeline(self.pipeline, None);
+        logical_device.destroy_pipeline_layout(self.pipeline_layout, None);
+    }
 }
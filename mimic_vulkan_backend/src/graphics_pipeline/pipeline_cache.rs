@@ -0,0 +1,104 @@
+use crate::util::result::{Result, VulkanError};
+use ash::vk;
+use log::info;
+use std::{convert::TryFrom, fs, path::PathBuf};
+//////////////////////// Structs ///////////////////////
+/// A vulkan pipeline cache which is loaded from a file on startup and saved back to it on shutdown.
+/// This way pipelines don't have to be compiled from scratch every time the application is started.
+pub struct PipelineCache {
+    pub cache: vk::PipelineCache,
+    cache_file: PathBuf,
+}
+//////////////////////// Impls ///////////////////////
+impl PipelineCache {
+    // the header is made up of the header length, header version, vendor id, device id and the pipeline cache uuid
+    const HEADER_LENGTH: usize = 16 + vk::UUID_SIZE;
+
+    /// Creates the pipeline cache with the data stored in `cache_file`.
+    /// If the file doesn't exist or was written by a different device or driver then the cache starts out empty.
+    pub fn new(
+        cache_file: PathBuf,
+        logical_device: &ash::Device,
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<Self> {
+        let initial_data = match fs::read(&cache_file) {
+            Ok(data) if Self::is_header_valid(&data, physical_device_properties) => data,
+            Ok(_) => {
+                info!(
+                    "Pipeline cache {:?} doesn't match the device and will be replaced",
+                    cache_file
+                );
+                Vec::new()
+            }
+            Err(_) => {
+                info!("No pipeline cache found at {:?}", cache_file);
+                Vec::new()
+            }
+        };
+
+        let pipeline_cache_create_info = vk::PipelineCacheCreateInfo {
+            initial_data_size: initial_data.len(),
+            p_initial_data: initial_data.as_ptr() as *const std::ffi::c_void,
+            ..Default::default()
+        };
+
+        let cache =
+            unsafe { logical_device.create_pipeline_cache(&pipeline_cache_create_info, None)? };
+
+        Ok(Self { cache, cache_file })
+    }
+
+    /// Writes the current content of the pipeline cache to its file.
+    pub fn save(&self, logical_device: &ash::Device) -> Result<()> {
+        let data = unsafe { logical_device.get_pipeline_cache_data(self.cache)? };
+        fs::write(&self.cache_file, &data).map_err(|source| {
+            VulkanError::PipelineCacheSaveFailure {
+                source,
+                file: self.cache_file.clone(),
+            }
+        })?;
+        info!(
+            "Saved {} bytes of pipeline cache to {:?}",
+            data.len(),
+            self.cache_file
+        );
+        Ok(())
+    }
+
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy the pipeline cache.
+    /// It must be called with valid vulkan state.
+    pub unsafe fn destroy(&self, logical_device: &ash::Device) {
+        logical_device.destroy_pipeline_cache(self.cache, None);
+    }
+
+    // the header is written in the byte order of the host
+    // the driver is supposed to reject incompatible data but not all of them do, so we check the header ourselves
+    fn is_header_valid(
+        data: &[u8],
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> bool {
+        if data.len() < Self::HEADER_LENGTH {
+            return false;
+        }
+
+        let read_u32 = |offset: usize| {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&data[offset..offset + 4]);
+            u32::from_ne_bytes(bytes)
+        };
+
+        let header_length = read_u32(0);
+        let header_version = read_u32(4);
+        let vendor_id = read_u32(8);
+        let device_id = read_u32(12);
+        let uuid = &data[16..Self::HEADER_LENGTH];
+
+        usize::try_from(header_length).map_or(false, |length| length >= Self::HEADER_LENGTH)
+            && header_version == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+            && vendor_id == physical_device_properties.vendor_id
+            && device_id == physical_device_properties.device_id
+            && uuid == &physical_device_properties.pipeline_cache_uuid[..]
+    }
+}
@@ -0,0 +1,160 @@
+use ash::vk;
+//////////////////////// Enums ///////////////////////
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+/// The winding order of the vertices of triangles that face the camera. Which one is right depends on the model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// the fragment color replaces what is in the color attachment
+    Opaque,
+    /// the fragment color is blended with what is in the color attachment using the alpha of the fragment
+    AlphaBlend,
+    /// the fragment color weighted by its alpha is added to what is in the color attachment
+    Additive,
+}
+
+/// Which fragments pass the depth test, compared to the depth that is already in the depth attachment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DepthTest {
+    Disabled,
+    Less,
+    LessOrEqual,
+    Equal,
+    Greater,
+    GreaterOrEqual,
+    Always,
+}
+
+/// Polygon modes other than `Fill` require the `fill_mode_non_solid` device feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PolygonMode {
+    Fill,
+    Line,
+    Point,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PrimitiveTopology {
+    TriangleList,
+    TriangleStrip,
+    LineList,
+    LineStrip,
+    PointList,
+}
+//////////////////////// Structs ///////////////////////
+/// The fixed function state of the graphics pipeline used to draw a render command.
+/// Render commands with equal descriptors and shaders share the same pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineDescriptor {
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub blend_mode: BlendMode,
+    pub depth_test: DepthTest,
+    /// whether fragments that pass the depth test write their depth into the depth attachment
+    pub depth_write: bool,
+    pub polygon_mode: PolygonMode,
+    pub topology: PrimitiveTopology,
+}
+//////////////////////// Impls ///////////////////////
+impl Default for PipelineDescriptor {
+    fn default() -> Self {
+        Self {
+            cull_mode: CullMode::Back,
+            front_face: FrontFace::CounterClockwise,
+            blend_mode: BlendMode::AlphaBlend,
+            depth_test: DepthTest::Less,
+            depth_write: true,
+            polygon_mode: PolygonMode::Fill,
+            topology: PrimitiveTopology::TriangleList,
+        }
+    }
+}
+
+impl CullMode {
+    pub fn to_vk(self) -> vk::CullModeFlags {
+        match self {
+            CullMode::None => vk::CullModeFlags::NONE,
+            CullMode::Front => vk::CullModeFlags::FRONT,
+            CullMode::Back => vk::CullModeFlags::BACK,
+            CullMode::FrontAndBack => vk::CullModeFlags::FRONT_AND_BACK,
+        }
+    }
+}
+
+impl FrontFace {
+    pub fn to_vk(self) -> vk::FrontFace {
+        match self {
+            FrontFace::CounterClockwise => vk::FrontFace::COUNTER_CLOCKWISE,
+            FrontFace::Clockwise => vk::FrontFace::CLOCKWISE,
+        }
+    }
+}
+
+impl BlendMode {
+    pub fn to_vk(self) -> vk::PipelineColorBlendAttachmentState {
+        let (blend_enable, dst_color_blend_factor) = match self {
+            BlendMode::Opaque => (vk::FALSE, vk::BlendFactor::ZERO),
+            BlendMode::AlphaBlend => (vk::TRUE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Additive => (vk::TRUE, vk::BlendFactor::ONE),
+        };
+        vk::PipelineColorBlendAttachmentState {
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            blend_enable,
+            src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+            dst_color_blend_factor,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ONE,
+            dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+            alpha_blend_op: vk::BlendOp::ADD,
+        }
+    }
+}
+
+impl DepthTest {
+    /// Returns `None` when the depth test is disabled.
+    pub fn to_vk(self) -> Option<vk::CompareOp> {
+        match self {
+            DepthTest::Disabled => None,
+            DepthTest::Less => Some(vk::CompareOp::LESS),
+            DepthTest::LessOrEqual => Some(vk::CompareOp::LESS_OR_EQUAL),
+            DepthTest::Equal => Some(vk::CompareOp::EQUAL),
+            DepthTest::Greater => Some(vk::CompareOp::GREATER),
+            DepthTest::GreaterOrEqual => Some(vk::CompareOp::GREATER_OR_EQUAL),
+            DepthTest::Always => Some(vk::CompareOp::ALWAYS),
+        }
+    }
+}
+
+impl PolygonMode {
+    pub fn to_vk(self) -> vk::PolygonMode {
+        match self {
+            PolygonMode::Fill => vk::PolygonMode::FILL,
+            PolygonMode::Line => vk::PolygonMode::LINE,
+            PolygonMode::Point => vk::PolygonMode::POINT,
+        }
+    }
+}
+
+impl PrimitiveTopology {
+    pub fn to_vk(self) -> vk::PrimitiveTopology {
+        match self {
+            PrimitiveTopology::TriangleList => vk::PrimitiveTopology::TRIANGLE_LIST,
+            PrimitiveTopology::TriangleStrip => vk::PrimitiveTopology::TRIANGLE_STRIP,
+            PrimitiveTopology::LineList => vk::PrimitiveTopology::LINE_LIST,
+            PrimitiveTopology::LineStrip => vk::PrimitiveTopology::LINE_STRIP,
+            PrimitiveTopology::PointList => vk::PrimitiveTopology::POINT_LIST,
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{depth::helpers, presentation::swap_chain::SwapChainContainer, util::result::Result};
+use crate::{depth::helpers, presentation::render_target::RenderTarget, util::result::Result};
 
 use ash::vk;
 use std::convert::TryFrom;
@@ -7,12 +7,36 @@ pub fn create_render_pass(
     instance: &ash::Instance,
     logical_device: &ash::Device,
     physical_device: vk::PhysicalDevice,
-    swap_chain_container: &SwapChainContainer,
+    render_target: &RenderTarget,
     msaa_samples: vk::SampleCountFlags,
+) -> Result<vk::RenderPass> {
+    create_scene_render_pass(
+        instance,
+        logical_device,
+        physical_device,
+        render_target.get_format(),
+        msaa_samples,
+        vk::ImageLayout::UNDEFINED,
+        // either presented to the display or copied out of when rendering offscreen
+        render_target.get_final_layout(),
+    )
+}
+
+/// Creates the render pass which draws the render commands into a multisampled color and depth attachment and then resolves
+/// the color into a single sampled image of `color_format`.
+/// Render passes that only differ in the layouts of the resolve attachment are compatible, so the same graphics pipelines can be used with all of them.
+pub fn create_scene_render_pass(
+    instance: &ash::Instance,
+    logical_device: &ash::Device,
+    physical_device: vk::PhysicalDevice,
+    color_format: vk::Format,
+    msaa_samples: vk::SampleCountFlags,
+    resolve_initial_layout: vk::ImageLayout,
+    resolve_final_layout: vk::ImageLayout,
 ) -> Result<vk::RenderPass> {
     // setup the descriptions for the attachments used by the render pass
     let color_attachment = vk::AttachmentDescription {
-        format: swap_chain_container.swap_chain_format.format,
+        format: color_format,
         samples: msaa_samples,
         load_op: vk::AttachmentLoadOp::CLEAR,
         store_op: vk::AttachmentStoreOp::STORE,
@@ -38,14 +62,14 @@ pub fn create_render_pass(
     };
 
     let color_attachment_resolve = vk::AttachmentDescription {
-        format: swap_chain_container.swap_chain_format.format,
+        format: color_format,
         samples: vk::SampleCountFlags::TYPE_1,
         load_op: vk::AttachmentLoadOp::DONT_CARE,
         store_op: vk::AttachmentStoreOp::STORE,
         stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
         stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
-        initial_layout: vk::ImageLayout::UNDEFINED,
-        final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        initial_layout: resolve_initial_layout,
+        final_layout: resolve_final_layout,
         ..Default::default()
     };
 
@@ -102,6 +126,18 @@ pub fn create_render_pass(
             | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
         ..Default::default()
     };
+    // frame captures copy out of the resolve attachment after the render pass,
+    // so its writes and the transition to the final layout have to finish before the copy reads it
+    let outgoing_dependency = vk::SubpassDependency {
+        src_subpass: 0,
+        dst_subpass: vk::SUBPASS_EXTERNAL,
+        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+        dst_stage_mask: vk::PipelineStageFlags::TRANSFER,
+        dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+        ..Default::default()
+    };
+    let subpass_dependencies = [subpass_dependency, outgoing_dependency];
 
     // the indices of the attachments in this array is what we use as the "attachment" field in the attachement refs
     let attachments = [color_attachment, depth_attachment, color_attachment_resolve];
@@ -111,8 +147,8 @@ pub fn create_render_pass(
         p_attachments: attachments.as_ptr(),
         subpass_count: 1,
         p_subpasses: &subpass,
-        dependency_count: 1,
-        p_dependencies: &subpass_dependency,
+        dependency_count: u32::try_from(subpass_dependencies.len())?,
+        p_dependencies: subpass_dependencies.as_ptr(),
         ..Default::default()
     };
 
@@ -1,14 +1,19 @@
+use crate::graphics_pipeline::shader_reflection::ShaderReflection;
 use crate::util::result::{Result, VulkanError};
 
 use ash::vk;
 use std::path::Path;
-
-pub fn create_shader_module(
-    file_name: &Path,
-    logical_device: &ash::Device,
-) -> Result<vk::ShaderModule> {
+//////////////////////// Structs ///////////////////////
+/// A shader module together with the interface that was reflected from its SPIR-V.
+pub struct Shader {
+    pub module: vk::ShaderModule,
+    pub reflection: ShaderReflection,
+}
+//////////////////////// Fns ///////////////////////
+pub fn create_shader_module(file_name: &Path, logical_device: &ash::Device) -> Result<Shader> {
     // the SPIR-V bytecode buffer can be freed right after the shader module has been created
     let code = read_shader_file(file_name)?;
+    let reflection = ShaderReflection::new(file_name, &code)?;
 
     let shader_module_create_info = vk::ShaderModuleCreateInfo {
         code_size: code.len(),
@@ -16,10 +21,9 @@ pub fn create_shader_module(
         ..Default::default()
     };
 
-    let shader_module =
-        unsafe { logical_device.create_shader_module(&shader_module_create_info, None)? };
+    let module = unsafe { logical_device.create_shader_module(&shader_module_create_info, None)? };
 
-    Ok(shader_module)
+    Ok(Shader { module, reflection })
 }
 
 fn read_shader_file(file_name: &Path) -> Result<Vec<u8>> {
@@ -0,0 +1,809 @@
+use crate::util::result::{Result, VulkanError};
+
+use ash::vk;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    path::Path,
+};
+//////////////////////// Consts ///////////////////////
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+const SPIRV_HEADER_LEN: usize = 5;
+// SPIR-V doesn't allow recursive types, but a malformed file could still declare an array of itself
+const MAX_ARRAY_NESTING: usize = 64;
+
+// opcodes
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+
+// decorations
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+// storage classes
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+// image dimensions
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+//////////////////////// Enums ///////////////////////
+/// The kind of numbers that a vertex input is read as by the shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumericType {
+    Float,
+    SignedInt,
+    UnsignedInt,
+}
+
+enum SpirvType {
+    Scalar(NumericType),
+    Vector { component_type: u32 },
+    Matrix { column_type: u32, column_count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element_type: u32, length_id: u32 },
+    RuntimeArray { element_type: u32 },
+    Struct,
+    Pointer { pointee_type: u32 },
+}
+//////////////////////// Structs ///////////////////////
+/// A descriptor binding that a shader declares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub descriptor_count: u32,
+    /// the shader stages which use the binding
+    pub stage_flags: vk::ShaderStageFlags,
+}
+
+/// An input variable of a vertex shader. Matrices and arrays take up `location_count` consecutive locations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReflectedInput {
+    pub location: u32,
+    pub location_count: u32,
+    pub numeric_type: NumericType,
+}
+
+/// The interface of a shader to the pipeline, as declared in its SPIR-V.
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub shader_file: OsString,
+    pub stage: vk::ShaderStageFlags,
+    pub bindings: Vec<ReflectedBinding>,
+    /// the user defined inputs of a vertex shader. built-in inputs like `gl_VertexIndex` are not included
+    /// and the inputs of other stages are not reflected, since they are checked by the shader compiler
+    pub inputs: Vec<ReflectedInput>,
+}
+
+#[derive(Default)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    built_in: bool,
+    block: bool,
+    buffer_block: bool,
+}
+
+struct Variable {
+    pointer_type: u32,
+    storage_class: u32,
+    decorations_id: u32,
+}
+//////////////////////// Impls ///////////////////////
+impl ShaderReflection {
+    /// Reflects the entry point stage, descriptor bindings and inputs from the SPIR-V `code` of the `shader_file`.
+    pub fn new(shader_file: &Path, code: &[u8]) -> Result<Self> {
+        let failure = |reason: &str| VulkanError::ShaderReflectionFailure {
+            shader_file: shader_file.as_os_str().to_owned(),
+            reason: String::from(reason),
+        };
+
+        if !code.len().is_multiple_of(4) || code.len() / 4 < SPIRV_HEADER_LEN {
+            return Err(failure(
+                "the file is too short or not a multiple of 4 bytes",
+            ));
+        }
+        let mut words: Vec<u32> = code
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        if words[0] != SPIRV_MAGIC {
+            if words[0].swap_bytes() != SPIRV_MAGIC {
+                return Err(failure(
+                    "the file doesn't start with the SPIR-V magic number",
+                ));
+            }
+            for word in words.iter_mut() {
+                *word = word.swap_bytes();
+            }
+        }
+
+        let mut stage = None;
+        let mut decorations: HashMap<u32, Decorations> = HashMap::new();
+        let mut types: HashMap<u32, SpirvType> = HashMap::new();
+        let mut constants: HashMap<u32, u32> = HashMap::new();
+        let mut variables = Vec::new();
+
+        let mut position = SPIRV_HEADER_LEN;
+        while position < words.len() {
+            let word_count = (words[position] >> 16) as usize;
+            let opcode = words[position] & 0xffff;
+            if word_count == 0 || position + word_count > words.len() {
+                return Err(failure("an instruction has an invalid word count"));
+            }
+            let operands = &words[position + 1..position + word_count];
+            position += word_count;
+
+            let operand = |index: usize| {
+                operands
+                    .get(index)
+                    .copied()
+                    .ok_or_else(|| failure("an instruction is missing operands"))
+            };
+            match opcode {
+                OP_ENTRY_POINT => {
+                    if stage.is_some() {
+                        return Err(failure(
+                            "only shaders with a single entry point are supported",
+                        ));
+                    }
+                    stage = Some(execution_model_to_stage(operand(0)?).ok_or_else(|| {
+                        failure("the entry point is not a graphics shader stage")
+                    })?);
+                }
+                OP_DECORATE => {
+                    let entry = decorations.entry(operand(0)?).or_default();
+                    match operand(1)? {
+                        DECORATION_BLOCK => entry.block = true,
+                        DECORATION_BUFFER_BLOCK => entry.buffer_block = true,
+                        DECORATION_BUILT_IN => entry.built_in = true,
+                        DECORATION_LOCATION => entry.location = Some(operand(2)?),
+                        DECORATION_BINDING => entry.binding = Some(operand(2)?),
+                        DECORATION_DESCRIPTOR_SET => entry.set = Some(operand(2)?),
+                        _ => {}
+                    }
+                }
+                OP_TYPE_INT => {
+                    let numeric_type = if operand(2)? == 0 {
+                        NumericType::UnsignedInt
+                    } else {
+                        NumericType::SignedInt
+                    };
+                    types.insert(operand(0)?, SpirvType::Scalar(numeric_type));
+                }
+                OP_TYPE_FLOAT => {
+                    types.insert(operand(0)?, SpirvType::Scalar(NumericType::Float));
+                }
+                OP_TYPE_VECTOR => {
+                    types.insert(
+                        operand(0)?,
+                        SpirvType::Vector {
+                            component_type: operand(1)?,
+                        },
+                    );
+                }
+                OP_TYPE_MATRIX => {
+                    types.insert(
+                        operand(0)?,
+                        SpirvType::Matrix {
+                            column_type: operand(1)?,
+                            column_count: operand(2)?,
+                        },
+                    );
+                }
+                OP_TYPE_IMAGE => {
+                    types.insert(
+                        operand(0)?,
+                        SpirvType::Image {
+                            dim: operand(2)?,
+                            sampled: operand(6)?,
+                        },
+                    );
+                }
+                OP_TYPE_SAMPLER => {
+                    types.insert(operand(0)?, SpirvType::Sampler);
+                }
+                OP_TYPE_SAMPLED_IMAGE => {
+                    types.insert(operand(0)?, SpirvType::SampledImage);
+                }
+                OP_TYPE_ARRAY => {
+                    types.insert(
+                        operand(0)?,
+                        SpirvType::Array {
+                            element_type: operand(1)?,
+                            length_id: operand(2)?,
+                        },
+                    );
+                }
+                OP_TYPE_RUNTIME_ARRAY => {
+                    types.insert(
+                        operand(0)?,
+                        SpirvType::RuntimeArray {
+                            element_type: operand(1)?,
+                        },
+                    );
+                }
+                OP_TYPE_STRUCT => {
+                    types.insert(operand(0)?, SpirvType::Struct);
+                }
+                OP_TYPE_POINTER => {
+                    types.insert(
+                        operand(0)?,
+                        SpirvType::Pointer {
+                            pointee_type: operand(2)?,
+                        },
+                    );
+                }
+                OP_CONSTANT => {
+                    // only the low word matters, since array lengths are small 32 bit constants
+                    constants.insert(operand(1)?, operand(2)?);
+                }
+                OP_VARIABLE => {
+                    variables.push(Variable {
+                        pointer_type: operand(0)?,
+                        storage_class: operand(2)?,
+                        decorations_id: operand(1)?,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let stage = stage.ok_or_else(|| failure("the shader has no entry point"))?;
+        let no_decorations = Decorations::default();
+        let mut bindings = Vec::new();
+        let mut inputs = Vec::new();
+        for variable in variables.iter() {
+            let variable_decorations = decorations
+                .get(&variable.decorations_id)
+                .unwrap_or(&no_decorations);
+            // only resources and vertex inputs are part of the interface of the shader to the pipeline
+            let is_resource = matches!(
+                variable.storage_class,
+                STORAGE_CLASS_UNIFORM_CONSTANT
+                    | STORAGE_CLASS_UNIFORM
+                    | STORAGE_CLASS_STORAGE_BUFFER
+            );
+            let is_vertex_input = variable.storage_class == STORAGE_CLASS_INPUT
+                && stage == vk::ShaderStageFlags::VERTEX
+                && !variable_decorations.built_in;
+            if !is_resource && !is_vertex_input {
+                continue;
+            }
+            let pointee_type = match types.get(&variable.pointer_type) {
+                Some(SpirvType::Pointer { pointee_type }) => *pointee_type,
+                _ => return Err(failure("a variable doesn't have a pointer type")),
+            };
+            let (element_type, element_count) =
+                unwrap_arrays(pointee_type, &types, &constants, 0).map_err(failure)?;
+
+            if is_resource {
+                let descriptor_type =
+                    get_descriptor_type(element_type, variable.storage_class, &types, &decorations)
+                        .ok_or_else(|| failure("a resource variable has an unsupported type"))?;
+                bindings.push(ReflectedBinding {
+                    set: variable_decorations.set.unwrap_or(0),
+                    binding: variable_decorations
+                        .binding
+                        .ok_or_else(|| failure("a resource variable has no binding"))?,
+                    descriptor_type,
+                    descriptor_count: element_count,
+                    stage_flags: stage,
+                });
+            } else {
+                let (numeric_type, locations_per_element) = get_input_type(element_type, &types)
+                    .ok_or_else(|| failure("an input variable has an unsupported type"))?;
+                inputs.push(ReflectedInput {
+                    location: variable_decorations
+                        .location
+                        .ok_or_else(|| failure("an input variable has no location"))?,
+                    location_count: locations_per_element
+                        .checked_mul(element_count)
+                        .ok_or_else(|| failure("an input variable takes up too many locations"))?,
+                    numeric_type,
+                });
+            }
+        }
+        bindings.sort_by_key(|binding| (binding.set, binding.binding));
+        inputs.sort_by_key(|input| input.location);
+
+        Ok(Self {
+            shader_file: shader_file.as_os_str().to_owned(),
+            stage,
+            bindings,
+            inputs,
+        })
+    }
+
+    /// Checks that every input of this vertex shader is provided by one of the `vertex_attributes`
+    /// and that the attribute is read as the same kind of numbers.
+    pub fn check_vertex_inputs(
+        &self,
+        vertex_attributes: &[vk::VertexInputAttributeDescription],
+    ) -> Result<()> {
+        for input in self.inputs.iter() {
+            let end_location = input
+                .location
+                .checked_add(input.location_count)
+                .ok_or_else(|| VulkanError::ShaderReflectionFailure {
+                    shader_file: self.shader_file.clone(),
+                    reason: format!(
+                        "the vertex input at location {} takes up locations past the last one",
+                        input.location
+                    ),
+                })?;
+            for location in input.location..end_location {
+                let attribute = vertex_attributes
+                    .iter()
+                    .find(|attribute| attribute.location == location)
+                    .ok_or_else(|| {
+                        self.mismatch(format!(
+                            "the vertex input at location {} is not provided by the vertex buffers",
+                            location
+                        ))
+                    })?;
+                let provided_type = format_to_numeric_type(attribute.format);
+                if provided_type != input.numeric_type {
+                    return Err(self.mismatch(format!(
+                        "the vertex input at location {} is read as {:?}, but provided as {:?}",
+                        location, input.numeric_type, provided_type
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn mismatch(&self, reason: String) -> VulkanError {
+        VulkanError::ShaderInterfaceMismatch {
+            shader_file: self.shader_file.clone(),
+            reason,
+        }
+    }
+}
+//////////////////////// Fns ///////////////////////
+/// Combines the bindings of the vertex and fragment shader of a pipeline and checks that they fit
+/// the descriptor sets which mimic binds: set 0 holds the uniform buffer at binding 0 and set 1 holds the
+/// combined image samplers which all sample the texture of the submesh, and the uniform buffers which all hold its material.
+/// With `bindless_textures` set 2 holds the array of all textures at binding 0.
+/// Returns the bindings of set 1.
+pub fn reflect_texture_bindings(
+    vertex_shader: &ShaderReflection,
+    fragment_shader: &ShaderReflection,
+    bindless_textures: bool,
+) -> Result<Vec<ReflectedBinding>> {
+    if vertex_shader.stage != vk::ShaderStageFlags::VERTEX {
+        return Err(vertex_shader.mismatch(format!(
+            "expected a vertex shader, but the entry point is a {:?} shader",
+            vertex_shader.stage
+        )));
+    }
+    if fragment_shader.stage != vk::ShaderStageFlags::FRAGMENT {
+        return Err(fragment_shader.mismatch(format!(
+            "expected a fragment shader, but the entry point is a {:?} shader",
+            fragment_shader.stage
+        )));
+    }
+
+    let mut merged_bindings: BTreeMap<(u32, u32), ReflectedBinding> = BTreeMap::new();
+    for shader in [vertex_shader, fragment_shader].iter() {
+        for binding in shader.bindings.iter() {
+            match binding.set {
+                0 if binding.binding != 0
+                    || binding.descriptor_type != vk::DescriptorType::UNIFORM_BUFFER
+                    || binding.descriptor_count != 1 =>
+                {
+                    return Err(shader.mismatch(format!(
+                        "set 0 can only hold a single uniform buffer at binding 0, but binding {} is {} {:?}",
+                        binding.binding, binding.descriptor_count, binding.descriptor_type
+                    )));
+                }
+                1 if binding.descriptor_type != vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+                    && (binding.descriptor_type != vk::DescriptorType::UNIFORM_BUFFER
+                        || binding.descriptor_count != 1) =>
+                {
+                    return Err(shader.mismatch(format!(
+                        "set 1 can only hold combined image samplers and single material uniform buffers, but binding {} is {} {:?}",
+                        binding.binding, binding.descriptor_count, binding.descriptor_type
+                    )));
+                }
+                2 if bindless_textures
+                    && (binding.binding != 0
+                        || binding.descriptor_type
+                            != vk::DescriptorType::COMBINED_IMAGE_SAMPLER) =>
+                {
+                    return Err(shader.mismatch(format!(
+                        "set 2 can only hold the bindless texture array at binding 0, but binding {} is {} {:?}",
+                        binding.binding, binding.descriptor_count, binding.descriptor_type
+                    )));
+                }
+                0 | 1 => {}
+                2 if bindless_textures => {}
+                set => {
+                    return Err(shader.mismatch(format!(
+                        "only the descriptor sets 0 and 1 are bound, but binding {} is in set {}",
+                        binding.binding, set
+                    )));
+                }
+            }
+
+            match merged_bindings.get_mut(&(binding.set, binding.binding)) {
+                Some(merged_binding) => {
+                    if merged_binding.descriptor_type != binding.descriptor_type
+                        || merged_binding.descriptor_count != binding.descriptor_count
+                    {
+                        return Err(shader.mismatch(format!(
+                            "binding {} of set {} is declared differently in the vertex shader",
+                            binding.binding, binding.set
+                        )));
+                    }
+                    merged_binding.stage_flags |= binding.stage_flags;
+                }
+                None => {
+                    merged_bindings.insert((binding.set, binding.binding), *binding);
+                }
+            }
+        }
+    }
+
+    Ok(merged_bindings
+        .into_values()
+        .filter(|binding| binding.set == 1)
+        .collect())
+}
+
+fn execution_model_to_stage(execution_model: u32) -> Option<vk::ShaderStageFlags> {
+    match execution_model {
+        0 => Some(vk::ShaderStageFlags::VERTEX),
+        1 => Some(vk::ShaderStageFlags::TESSELLATION_CONTROL),
+        2 => Some(vk::ShaderStageFlags::TESSELLATION_EVALUATION),
+        3 => Some(vk::ShaderStageFlags::GEOMETRY),
+        4 => Some(vk::ShaderStageFlags::FRAGMENT),
+        _ => None,
+    }
+}
+
+/// Returns the element type and the total element count of a type that may be a (nested) array.
+/// Runtime arrays count as a single element. `depth` is the number of arrays that were already unwrapped.
+fn unwrap_arrays(
+    type_id: u32,
+    types: &HashMap<u32, SpirvType>,
+    constants: &HashMap<u32, u32>,
+    depth: usize,
+) -> std::result::Result<(u32, u32), &'static str> {
+    if depth > MAX_ARRAY_NESTING {
+        return Err("an array is nested too deeply");
+    }
+    match types.get(&type_id) {
+        Some(SpirvType::Array {
+            element_type,
+            length_id,
+        }) => {
+            let length = *constants
+                .get(length_id)
+                .ok_or("an array has an unknown length")?;
+            let (element_type, element_count) =
+                unwrap_arrays(*element_type, types, constants, depth + 1)?;
+            let element_count = length
+                .checked_mul(element_count)
+                .ok_or("an array has too many elements")?;
+            Ok((element_type, element_count))
+        }
+        Some(SpirvType::RuntimeArray { element_type }) => {
+            unwrap_arrays(*element_type, types, constants, depth + 1)
+        }
+        _ => Ok((type_id, 1)),
+    }
+}
+
+fn get_descriptor_type(
+    type_id: u32,
+    storage_class: u32,
+    types: &HashMap<u32, SpirvType>,
+    decorations: &HashMap<u32, Decorations>,
+) -> Option<vk::DescriptorType> {
+    match types.get(&type_id)? {
+        SpirvType::SampledImage => Some(vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        SpirvType::Sampler => Some(vk::DescriptorType::SAMPLER),
+        // sampled is 2 for images that are only accessed without a sampler
+        SpirvType::Image { dim, sampled } => match (*dim, *sampled) {
+            (DIM_BUFFER, 2) => Some(vk::DescriptorType::STORAGE_TEXEL_BUFFER),
+            (DIM_BUFFER, _) => Some(vk::DescriptorType::UNIFORM_TEXEL_BUFFER),
+            (DIM_SUBPASS_DATA, _) => Some(vk::DescriptorType::INPUT_ATTACHMENT),
+            (_, 2) => Some(vk::DescriptorType::STORAGE_IMAGE),
+            _ => Some(vk::DescriptorType::SAMPLED_IMAGE),
+        },
+        SpirvType::Struct => {
+            let struct_decorations = decorations.get(&type_id)?;
+            match storage_class {
+                STORAGE_CLASS_STORAGE_BUFFER => Some(vk::DescriptorType::STORAGE_BUFFER),
+                STORAGE_CLASS_UNIFORM if struct_decorations.buffer_block => {
+                    Some(vk::DescriptorType::STORAGE_BUFFER)
+                }
+                STORAGE_CLASS_UNIFORM if struct_decorations.block => {
+                    Some(vk::DescriptorType::UNIFORM_BUFFER)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the kind of numbers of an input type and how many locations it takes up.
+fn get_input_type(type_id: u32, types: &HashMap<u32, SpirvType>) -> Option<(NumericType, u32)> {
+    // the components of vectors are scalars and the columns of matrices are vectors,
+    // so the types are looked up directly instead of recursively
+    let get_vector_type = |vector_type: u32| match types.get(&vector_type)? {
+        SpirvType::Scalar(numeric_type) => Some(*numeric_type),
+        SpirvType::Vector { component_type } => match types.get(component_type)? {
+            SpirvType::Scalar(numeric_type) => Some(*numeric_type),
+            _ => None,
+        },
+        _ => None,
+    };
+    match types.get(&type_id)? {
+        SpirvType::Matrix {
+            column_type,
+            column_count,
+        } => match types.get(column_type)? {
+            // every column of a matrix takes up its own location
+            SpirvType::Vector { .. } => Some((get_vector_type(*column_type)?, *column_count)),
+            _ => None,
+        },
+        _ => Some((get_vector_type(type_id)?, 1)),
+    }
+}
+
+fn format_to_numeric_type(format: vk::Format) -> NumericType {
+    match format {
+        vk::Format::R8_SINT
+        | vk::Format::R8G8_SINT
+        | vk::Format::R8G8B8A8_SINT
+        | vk::Format::R16_SINT
+        | vk::Format::R16G16_SINT
+        | vk::Format::R16G16B16A16_SINT
+        | vk::Format::R32_SINT
+        | vk::Format::R32G32_SINT
+        | vk::Format::R32G32B32_SINT
+        | vk::Format::R32G32B32A32_SINT => NumericType::SignedInt,
+        vk::Format::R8_UINT
+        | vk::Format::R8G8_UINT
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::R16_UINT
+        | vk::Format::R16G16_UINT
+        | vk::Format::R16G16B16A16_UINT
+        | vk::Format::R32_UINT
+        | vk::Format::R32G32_UINT
+        | vk::Format::R32G32B32_UINT
+        | vk::Format::R32G32B32A32_UINT => NumericType::UnsignedInt,
+        // normalized and scaled formats are read as floats too
+        _ => NumericType::Float,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXECUTION_MODEL_VERTEX: u32 = 0;
+
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let word_count = operands.len() as u32 + 1;
+        let mut words = vec![(word_count << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn spirv(instructions: &[Vec<u32>]) -> Vec<u8> {
+        let header = [SPIRV_MAGIC, 0x0001_0000, 0, 100, 0];
+        header
+            .iter()
+            .chain(instructions.iter().flatten())
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+
+    fn reflect(instructions: &[Vec<u32>]) -> Result<ShaderReflection> {
+        ShaderReflection::new(Path::new("test.vert.spv"), &spirv(instructions))
+    }
+
+    /// A vertex shader with a vec3 input at location 0, a mat4 input at location 1
+    /// and a uniform buffer at set 0 binding 0.
+    fn vertex_shader() -> Vec<Vec<u32>> {
+        vec![
+            instruction(OP_ENTRY_POINT, &[EXECUTION_MODEL_VERTEX, 1]),
+            instruction(OP_DECORATE, &[8, DECORATION_LOCATION, 0]),
+            instruction(OP_DECORATE, &[9, DECORATION_LOCATION, 1]),
+            instruction(OP_DECORATE, &[10, DECORATION_BLOCK]),
+            instruction(OP_DECORATE, &[12, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[12, DECORATION_BINDING, 0]),
+            instruction(OP_TYPE_FLOAT, &[2, 32]),
+            instruction(OP_TYPE_VECTOR, &[3, 2, 3]),
+            instruction(OP_TYPE_VECTOR, &[4, 2, 4]),
+            instruction(OP_TYPE_MATRIX, &[5, 4, 4]),
+            instruction(OP_TYPE_POINTER, &[6, STORAGE_CLASS_INPUT, 3]),
+            instruction(OP_TYPE_POINTER, &[7, STORAGE_CLASS_INPUT, 5]),
+            instruction(OP_VARIABLE, &[6, 8, STORAGE_CLASS_INPUT]),
+            instruction(OP_VARIABLE, &[7, 9, STORAGE_CLASS_INPUT]),
+            instruction(OP_TYPE_STRUCT, &[10, 5]),
+            instruction(OP_TYPE_POINTER, &[11, STORAGE_CLASS_UNIFORM, 10]),
+            instruction(OP_VARIABLE, &[11, 12, STORAGE_CLASS_UNIFORM]),
+            // a sampled 2D float image and a uint type for array lengths
+            instruction(OP_TYPE_IMAGE, &[13, 2, 1, 0, 0, 0, 1, 0]),
+            instruction(OP_TYPE_SAMPLED_IMAGE, &[14, 13]),
+            instruction(OP_TYPE_INT, &[15, 32, 0]),
+        ]
+    }
+
+    /// Declares a combined image sampler variable at set 1 binding 1 whose type is `array_type`.
+    fn sampler_variable(array_type: u32) -> Vec<Vec<u32>> {
+        vec![
+            instruction(OP_DECORATE, &[51, DECORATION_DESCRIPTOR_SET, 1]),
+            instruction(OP_DECORATE, &[51, DECORATION_BINDING, 1]),
+            instruction(
+                OP_TYPE_POINTER,
+                &[50, STORAGE_CLASS_UNIFORM_CONSTANT, array_type],
+            ),
+            instruction(OP_VARIABLE, &[50, 51, STORAGE_CLASS_UNIFORM_CONSTANT]),
+        ]
+    }
+
+    fn is_reflection_failure(result: &Result<ShaderReflection>) -> bool {
+        matches!(result, Err(VulkanError::ShaderReflectionFailure { .. }))
+    }
+
+    #[test]
+    fn inputs_and_bindings_are_reflected() {
+        let reflection = reflect(&vertex_shader()).unwrap();
+
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(
+            reflection.inputs,
+            vec![
+                ReflectedInput {
+                    location: 0,
+                    location_count: 1,
+                    numeric_type: NumericType::Float,
+                },
+                ReflectedInput {
+                    location: 1,
+                    location_count: 4,
+                    numeric_type: NumericType::Float,
+                },
+            ]
+        );
+        assert_eq!(
+            reflection.bindings,
+            vec![ReflectedBinding {
+                set: 0,
+                binding: 0,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+            }]
+        );
+    }
+
+    #[test]
+    fn nested_arrays_count_all_their_elements() {
+        let mut instructions = vertex_shader();
+        instructions.push(instruction(OP_CONSTANT, &[15, 40, 4]));
+        instructions.push(instruction(OP_CONSTANT, &[15, 41, 3]));
+        instructions.push(instruction(OP_TYPE_ARRAY, &[42, 14, 40]));
+        instructions.push(instruction(OP_TYPE_ARRAY, &[43, 42, 41]));
+        instructions.extend(sampler_variable(43));
+
+        let reflection = reflect(&instructions).unwrap();
+
+        assert_eq!(reflection.bindings.len(), 2);
+        assert_eq!(
+            reflection.bindings[1].descriptor_type,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+        );
+        assert_eq!(reflection.bindings[1].descriptor_count, 12);
+    }
+
+    #[test]
+    fn arrays_of_themselves_are_a_failure() {
+        let mut instructions = vertex_shader();
+        instructions.push(instruction(OP_CONSTANT, &[15, 40, 4]));
+        instructions.push(instruction(OP_TYPE_ARRAY, &[42, 42, 40]));
+        instructions.extend(sampler_variable(42));
+
+        assert!(is_reflection_failure(&reflect(&instructions)));
+    }
+
+    #[test]
+    fn vectors_of_themselves_are_a_failure() {
+        let mut instructions = vertex_shader();
+        instructions.push(instruction(OP_TYPE_VECTOR, &[60, 60, 4]));
+        instructions.push(instruction(OP_TYPE_POINTER, &[61, STORAGE_CLASS_INPUT, 60]));
+        instructions.push(instruction(OP_VARIABLE, &[61, 62, STORAGE_CLASS_INPUT]));
+        instructions.push(instruction(OP_DECORATE, &[62, DECORATION_LOCATION, 5]));
+
+        assert!(is_reflection_failure(&reflect(&instructions)));
+    }
+
+    #[test]
+    fn overflowing_array_lengths_are_a_failure() {
+        let mut instructions = vertex_shader();
+        instructions.push(instruction(OP_CONSTANT, &[15, 40, 0x1_0000]));
+        instructions.push(instruction(OP_TYPE_ARRAY, &[42, 14, 40]));
+        instructions.push(instruction(OP_TYPE_ARRAY, &[43, 42, 40]));
+        instructions.extend(sampler_variable(43));
+
+        assert!(is_reflection_failure(&reflect(&instructions)));
+    }
+
+    #[test]
+    fn overflowing_input_location_counts_are_a_failure() {
+        let mut instructions = vertex_shader();
+        instructions.push(instruction(OP_CONSTANT, &[15, 40, 0x4000_0000]));
+        instructions.push(instruction(OP_TYPE_ARRAY, &[60, 5, 40]));
+        instructions.push(instruction(OP_TYPE_POINTER, &[61, STORAGE_CLASS_INPUT, 60]));
+        instructions.push(instruction(OP_VARIABLE, &[61, 62, STORAGE_CLASS_INPUT]));
+        instructions.push(instruction(OP_DECORATE, &[62, DECORATION_LOCATION, 5]));
+
+        assert!(is_reflection_failure(&reflect(&instructions)));
+    }
+
+    #[test]
+    fn inputs_past_the_last_location_are_a_failure() {
+        let mut instructions = vertex_shader();
+        instructions.push(instruction(
+            OP_DECORATE,
+            &[9, DECORATION_LOCATION, u32::MAX],
+        ));
+        let reflection = reflect(&instructions).unwrap();
+        let attributes = [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                ..Default::default()
+            },
+            vk::VertexInputAttributeDescription {
+                location: u32::MAX,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                ..Default::default()
+            },
+        ];
+
+        assert!(matches!(
+            reflection.check_vertex_inputs(&attributes),
+            Err(VulkanError::ShaderReflectionFailure { .. })
+        ));
+    }
+
+    #[test]
+    fn truncated_instructions_are_a_failure() {
+        let mut instructions = vertex_shader();
+        instructions.push(vec![(4 << 16) | OP_DECORATE, 8]);
+
+        assert!(is_reflection_failure(&reflect(&instructions)));
+    }
+}
@@ -1,3 +1,6 @@
+pub mod gltf_model;
 pub mod index;
+pub mod instance;
+pub mod material;
 pub mod textured_model;
 pub mod vertex;
@@ -0,0 +1,491 @@
+use crate::{
+    models::{
+        index::IndexType,
+        material::MeshMaterial,
+        textured_model::{BoundingBox, Mesh, MeshLoadingFlags, Submesh},
+    },
+    util::result::{Result, VulkanError},
+};
+use gltf::{image::Format, mesh::Mode};
+use log::info;
+use mimic_common::{
+    result::MimicCommonError,
+    texture::{ColorSpace, MemoryTextureSource, PixelFormat},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    path::Path,
+};
+//////////////////////// Structs ///////////////////////
+/// The meshes, node hierarchy, materials and images of a glTF 2.0 file (.gltf or .glb).
+pub struct GltfModel {
+    pub meshes: Vec<GltfMesh>,
+    /// all nodes of the file, the children of a node are indices into this vec
+    pub nodes: Vec<GltfNode>,
+    /// the root nodes of the default scene, or of the first scene if the file doesn't set a default scene
+    pub scene_roots: Vec<usize>,
+    pub materials: Vec<PbrMaterial>,
+    /// the embedded and external images of the file, converted to RGBA8.
+    /// The base color and emissive images are sRGB, the others hold linear data like normals
+    pub images: Vec<MemoryTextureSource>,
+    /// the axis and handedness flags, which are applied to the scene mesh once the node transforms were applied
+    axis_flags: MeshLoadingFlags,
+}
+
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+/// The part of a mesh that is drawn with one material.
+pub struct GltfPrimitive {
+    pub mesh: Mesh,
+    /// index into `GltfModel::materials` or `None` if the primitive uses the default material
+    pub material: Option<usize>,
+}
+
+pub struct GltfNode {
+    pub name: Option<String>,
+    /// the transform relative to the parent node
+    pub transform: glm::Mat4,
+    /// index into `GltfModel::meshes`
+    pub mesh: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+/// The factors of a glTF metallic-roughness material. The textures are indices into `GltfModel::images`.
+pub struct PbrMaterial {
+    pub name: Option<String>,
+    pub base_color_factor: glm::Vec4,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub emissive_factor: glm::Vec3,
+    pub double_sided: bool,
+    pub base_color_texture: Option<usize>,
+    pub metallic_roughness_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    pub occlusion_texture: Option<usize>,
+    pub emissive_texture: Option<usize>,
+}
+//////////////////////// Impls ///////////////////////
+impl GltfModel {
+    /// The primitives stay in the space of their nodes, so the `Z_UP` and `FLIP_HANDEDNESS` flags are only applied by `into_scene_mesh`.
+    pub fn new(filepath: &Path, loading_props: MeshLoadingFlags) -> Result<Self> {
+        let axis_flags =
+            loading_props & (MeshLoadingFlags::Z_UP | MeshLoadingFlags::FLIP_HANDEDNESS);
+        // import reads the external buffers and images relative to the file and decodes the embedded ones
+        let (document, buffers, images) = gltf::import(filepath)?;
+
+        let mut meshes = Vec::new();
+        for mesh in document.meshes() {
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                primitives.push(GltfPrimitive {
+                    mesh: load_primitive(
+                        &primitive,
+                        mesh.name().unwrap_or_default(),
+                        &buffers,
+                        filepath,
+                        loading_props - axis_flags,
+                    )?,
+                    material: primitive.material().index(),
+                });
+            }
+            meshes.push(GltfMesh {
+                name: mesh.name().map(String::from),
+                primitives,
+            });
+        }
+
+        let nodes = document
+            .nodes()
+            .map(|node| GltfNode {
+                name: node.name().map(String::from),
+                // the matrix is stored column by column, just like glm expects it
+                transform: glm::make_mat4(&node.transform().matrix().concat()),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                children: node.children().map(|child| child.index()).collect(),
+            })
+            .collect();
+
+        let scene_roots = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .map(|scene| scene.nodes().map(|node| node.index()).collect())
+            .unwrap_or_default();
+
+        let materials: Vec<PbrMaterial> = document
+            .materials()
+            .map(|material| {
+                let pbr = material.pbr_metallic_roughness();
+                PbrMaterial {
+                    name: material.name().map(String::from),
+                    base_color_factor: glm::make_vec4(&pbr.base_color_factor()),
+                    metallic_factor: pbr.metallic_factor(),
+                    roughness_factor: pbr.roughness_factor(),
+                    emissive_factor: glm::make_vec3(&material.emissive_factor()),
+                    double_sided: material.double_sided(),
+                    base_color_texture: pbr
+                        .base_color_texture()
+                        .map(|info| info.texture().source().index()),
+                    metallic_roughness_texture: pbr
+                        .metallic_roughness_texture()
+                        .map(|info| info.texture().source().index()),
+                    normal_texture: material
+                        .normal_texture()
+                        .map(|normal| normal.texture().source().index()),
+                    occlusion_texture: material
+                        .occlusion_texture()
+                        .map(|occlusion| occlusion.texture().source().index()),
+                    emissive_texture: material
+                        .emissive_texture()
+                        .map(|info| info.texture().source().index()),
+                }
+            })
+            .collect();
+
+        let color_images: HashSet<usize> = materials
+            .iter()
+            .flat_map(|material| vec![material.base_color_texture, material.emissive_texture])
+            .flatten()
+            .collect();
+        let images = images
+            .iter()
+            .enumerate()
+            .map(|(image_idx, image)| {
+                let color_space = if color_images.contains(&image_idx) {
+                    ColorSpace::Srgb
+                } else {
+                    ColorSpace::Linear
+                };
+                MemoryTextureSource::with_format(
+                    image.width,
+                    image.height,
+                    to_rgba8(image),
+                    PixelFormat::Rgba8,
+                    color_space,
+                )
+            })
+            .collect::<std::result::Result<_, MimicCommonError>>()?;
+
+        let model = Self {
+            meshes,
+            nodes,
+            scene_roots,
+            materials,
+            images,
+            axis_flags,
+        };
+        info!(
+            "Model \"{:?}\" loaded. Meshes: {}, nodes: {}, materials: {}, images: {}",
+            filepath,
+            model.meshes.len(),
+            model.nodes.len(),
+            model.materials.len(),
+            model.images.len()
+        );
+        Ok(model)
+    }
+
+    /// Merges the primitives of all nodes of the scene into a single mesh, with the node transforms applied to the vertices.
+    /// A vertex stream is kept if any primitive has it, just like for obj files.
+    /// The primitives without it get generated normals and tangents, white colors and texture coordinates at the origin.
+    /// Every primitive becomes a submesh with its material, named after its node or else its mesh.
+    /// The base color images of the materials are moved into the mesh, the other images are dropped.
+    pub fn into_scene_mesh(mut self) -> Result<Mesh> {
+        // the images are renumbered so that the mesh only holds the ones that it samples
+        let mut images: Vec<Option<MemoryTextureSource>> =
+            self.images.drain(..).map(Some).collect();
+        let mut image_indices = HashMap::new();
+        let mut mesh_images = Vec::new();
+        let mut materials = Vec::new();
+        for material in self.materials.iter() {
+            let base_color_image = match material.base_color_texture {
+                Some(image_idx) => match image_indices.get(&image_idx) {
+                    Some(&mesh_image_idx) => Some(mesh_image_idx),
+                    None => match images.get_mut(image_idx).and_then(Option::take) {
+                        Some(image) => {
+                            image_indices.insert(image_idx, mesh_images.len());
+                            mesh_images.push(image);
+                            Some(mesh_images.len() - 1)
+                        }
+                        None => None,
+                    },
+                },
+                None => None,
+            };
+            materials.push(MeshMaterial::from_gltf_material(material, base_color_image));
+        }
+
+        let mut placed_meshes = Vec::new();
+        let mut nodes_to_visit: Vec<(usize, glm::Mat4)> = self
+            .scene_roots
+            .iter()
+            .map(|&root| (root, glm::identity()))
+            .collect();
+        while let Some((node_idx, parent_transform)) = nodes_to_visit.pop() {
+            let node = &self.nodes[node_idx];
+            let transform = parent_transform * node.transform;
+            if let Some(mesh_idx) = node.mesh {
+                // the parts of the scene mesh are named after the nodes, or else after the meshes
+                let mesh = &self.meshes[mesh_idx];
+                let name = node
+                    .name
+                    .as_ref()
+                    .or(mesh.name.as_ref())
+                    .map_or("", |name| name.as_str());
+                for primitive in mesh.primitives.iter() {
+                    placed_meshes.push((transform, name, primitive));
+                }
+            }
+            nodes_to_visit.extend(node.children.iter().map(|&child| (child, transform)));
+        }
+
+        let has_normals = placed_meshes
+            .iter()
+            .any(|(_, _, primitive)| !primitive.mesh.normals.is_empty());
+        let has_tangents = placed_meshes
+            .iter()
+            .any(|(_, _, primitive)| !primitive.mesh.tangents.is_empty());
+        let has_colors = placed_meshes
+            .iter()
+            .any(|(_, _, primitive)| !primitive.mesh.colors.is_empty());
+        let has_tex_coords = placed_meshes
+            .iter()
+            .any(|(_, _, primitive)| !primitive.mesh.tex_coords.is_empty());
+
+        let mut scene_mesh = Mesh::default();
+        for (transform, name, primitive) in placed_meshes {
+            let mut mesh = &primitive.mesh;
+            let generated_mesh;
+            if (has_normals && mesh.normals.is_empty())
+                || (has_tangents && mesh.tangents.is_empty())
+            {
+                let mut generated = Mesh {
+                    positions: mesh.positions.clone(),
+                    normals: mesh.normals.clone(),
+                    tangents: mesh.tangents.clone(),
+                    colors: mesh.colors.clone(),
+                    tex_coords: mesh.tex_coords.clone(),
+                    indices: mesh.indices.clone(),
+                    ..Mesh::default()
+                };
+                if generated.normals.is_empty() {
+                    generated.generate_normals();
+                }
+                if has_tangents && generated.tangents.is_empty() {
+                    generated.generate_tangents();
+                }
+                generated_mesh = generated;
+                mesh = &generated_mesh;
+            }
+            let vertex_count = mesh.positions.len();
+            let first_vertex = IndexType::try_from(scene_mesh.positions.len())?;
+            scene_mesh.submeshes.push(Submesh {
+                name: String::from(name),
+                first_index: u32::try_from(scene_mesh.indices.len())?,
+                index_count: u32::try_from(mesh.indices.len())?,
+                material: primitive.material,
+                bounds: BoundingBox::default(),
+            });
+            let linear = glm::mat4_to_mat3(&transform);
+            // normals are transformed by the inverse transpose so that they stay orthogonal to the surface under non-uniform scaling
+            let normal_matrix = linear
+                .try_inverse()
+                .map(|inverse| inverse.transpose())
+                .unwrap_or(linear);
+
+            scene_mesh
+                .positions
+                .extend(mesh.positions.iter().map(|position| {
+                    let position = glm::vec4(position.x, position.y, position.z, 1.0);
+                    glm::vec4_to_vec3(&(transform * position))
+                }));
+            if has_normals {
+                scene_mesh.normals.extend(
+                    mesh.normals
+                        .iter()
+                        .map(|normal| (normal_matrix * normal).normalize()),
+                );
+            }
+            if has_tangents {
+                scene_mesh
+                    .tangents
+                    .extend(mesh.tangents.iter().map(|tangent| {
+                        let direction = (linear * glm::vec4_to_vec3(tangent)).normalize();
+                        glm::vec4(direction.x, direction.y, direction.z, tangent.w)
+                    }));
+            }
+            if has_colors && mesh.colors.is_empty() {
+                scene_mesh
+                    .colors
+                    .extend(std::iter::repeat_n(glm::vec3(1.0, 1.0, 1.0), vertex_count));
+            } else if has_colors {
+                scene_mesh.colors.extend_from_slice(&mesh.colors);
+            }
+            if has_tex_coords && mesh.tex_coords.is_empty() {
+                scene_mesh
+                    .tex_coords
+                    .extend(std::iter::repeat_n(glm::vec2(0.0, 0.0), vertex_count));
+            } else if has_tex_coords {
+                scene_mesh.tex_coords.extend_from_slice(&mesh.tex_coords);
+            }
+
+            // a mirroring transform flips the winding order of the faces, so we flip it back
+            let is_mirrored = linear.determinant() < 0.0;
+            for face in mesh.indices.chunks_exact(3) {
+                let face = if is_mirrored {
+                    [face[0], face[2], face[1]]
+                } else {
+                    [face[0], face[1], face[2]]
+                };
+                scene_mesh
+                    .indices
+                    .extend(face.iter().map(|index| index + first_vertex));
+            }
+        }
+        scene_mesh.materials = materials;
+        scene_mesh.images = mesh_images;
+        // the axis flags convert the whole scene, including the node transforms
+        scene_mesh.apply_axis_flags(self.axis_flags);
+        scene_mesh.compute_submesh_bounds();
+        Ok(scene_mesh)
+    }
+}
+//////////////////////// Fns ///////////////////////
+pub fn is_gltf_file(filepath: &Path) -> bool {
+    match filepath
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some(extension) => {
+            extension.eq_ignore_ascii_case("gltf") || extension.eq_ignore_ascii_case("glb")
+        }
+        None => false,
+    }
+}
+
+fn load_primitive(
+    primitive: &gltf::Primitive,
+    mesh_name: &str,
+    buffers: &[gltf::buffer::Data],
+    filepath: &Path,
+    loading_props: MeshLoadingFlags,
+) -> Result<Mesh> {
+    if primitive.mode() != Mode::Triangles {
+        return Err(VulkanError::GltfPrimitiveUnsupported {
+            file: filepath.to_path_buf(),
+            reason: format!(
+                "mode {:?} is not supported, only triangles",
+                primitive.mode()
+            ),
+        });
+    }
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let positions: Vec<glm::Vec3> = match reader.read_positions() {
+        Some(positions) => positions
+            .map(|position| glm::make_vec3(&position))
+            .collect(),
+        None => {
+            return Err(VulkanError::GltfPrimitiveUnsupported {
+                file: filepath.to_path_buf(),
+                reason: String::from("the primitive has no positions"),
+            })
+        }
+    };
+    let normals = reader
+        .read_normals()
+        .map(|normals| normals.map(|normal| glm::make_vec3(&normal)).collect())
+        .unwrap_or_default();
+    // the w of a gltf tangent is the handedness of the bitangent, which matches our generated tangents
+    let tangents = reader
+        .read_tangents()
+        .map(|tangents| tangents.map(|tangent| glm::make_vec4(&tangent)).collect())
+        .unwrap_or_default();
+    let colors = reader
+        .read_colors(0)
+        .map(|colors| {
+            colors
+                .into_rgb_f32()
+                .map(|color| glm::make_vec3(&color))
+                .collect()
+        })
+        .unwrap_or_default();
+    let tex_coords = reader
+        .read_tex_coords(0)
+        .map(|tex_coords| {
+            tex_coords
+                .into_f32()
+                .map(|tex_coord| glm::make_vec2(&tex_coord))
+                .collect()
+        })
+        .unwrap_or_default();
+    // primitives without indices draw their vertices in order
+    let indices: Vec<IndexType> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..IndexType::try_from(positions.len())?).collect(),
+    };
+
+    // the material of the primitive is kept by the `GltfPrimitive`
+    let submeshes = vec![Submesh {
+        name: String::from(mesh_name),
+        first_index: 0,
+        index_count: u32::try_from(indices.len())?,
+        material: None,
+        bounds: BoundingBox::default(),
+    }];
+
+    let mut mesh = Mesh {
+        positions,
+        normals,
+        tangents,
+        colors,
+        tex_coords,
+        custom_attributes: HashMap::new(),
+        indices,
+        submeshes,
+        materials: Vec::new(),
+        images: Vec::new(),
+    };
+    // the normals are generated in the space of the primitive, before the node transforms
+    mesh.apply_loading_flags(filepath, loading_props)?;
+    Ok(mesh)
+}
+
+/// Converts the decoded pixels of a gltf image to RGBA8, keeping the high byte of 16 bit channels.
+fn to_rgba8(image: &gltf::image::Data) -> Vec<u8> {
+    let (channel_count, channel_size) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 | Format::B8G8R8 => (3, 1),
+        Format::R8G8B8A8 | Format::B8G8R8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+    };
+    let is_bgr = matches!(image.format, Format::B8G8R8 | Format::B8G8R8A8);
+
+    let mut pixels = Vec::with_capacity(image.pixels.len() / channel_size / channel_count * 4);
+    for pixel in image.pixels.chunks_exact(channel_count * channel_size) {
+        let channel = |c: usize| match channel_size {
+            1 => pixel[c],
+            _ => (u16::from_ne_bytes([pixel[2 * c], pixel[2 * c + 1]]) >> 8) as u8,
+        };
+        let rgba = match channel_count {
+            // one and two channel images are decoded from grayscale and grayscale with alpha
+            1 => [channel(0), channel(0), channel(0), u8::MAX],
+            2 => [channel(0), channel(0), channel(0), channel(1)],
+            3 => [channel(0), channel(1), channel(2), u8::MAX],
+            _ => [channel(0), channel(1), channel(2), channel(3)],
+        };
+        if is_bgr {
+            pixels.extend_from_slice(&[rgba[2], rgba[1], rgba[0], rgba[3]]);
+        } else {
+            pixels.extend_from_slice(&rgba);
+        }
+    }
+    pixels
+}
@@ -0,0 +1,73 @@
+use crate::{buffers::memory::MemoryCopyable, util::result::Result};
+use ash::vk;
+use memoffset::offset_of;
+use std::convert::TryFrom;
+//////////////////////// Structs ///////////////////////
+/// The per-instance data of an instanced draw. The vertex shader reads it from the instance binding.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InstanceData {
+    pub transform: glm::Mat4,
+    pub color: glm::Vec4,
+}
+//////////////////////// Impls ///////////////////////
+impl Default for InstanceData {
+    /// An untransformed, white instance. Objects which aren't instanced are drawn as a single default instance.
+    fn default() -> Self {
+        Self {
+            transform: glm::Mat4::identity(),
+            color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl MemoryCopyable for [InstanceData] {
+    unsafe fn copy_to_mapped_memory(&self, data_target_ptr: *mut std::ffi::c_void) {
+        let data_ptr = data_target_ptr as *mut InstanceData;
+        data_ptr.copy_from_nonoverlapping(self.as_ptr(), self.len());
+    }
+}
+
+impl InstanceData {
+    /// the per-vertex data is in binding 0, the per-instance data follows in binding 1
+    pub const BINDING: u32 = 1;
+
+    pub fn get_binding_description() -> Result<vk::VertexInputBindingDescription> {
+        Ok(vk::VertexInputBindingDescription {
+            binding: Self::BINDING,
+            stride: u32::try_from(std::mem::size_of::<Self>())?,
+            // the attributes advance once per instance instead of once per vertex
+            input_rate: vk::VertexInputRate::INSTANCE,
+        })
+    }
+
+    /// The attributes start at `first_location`, which is the first shader location after the per-vertex attributes.
+    pub fn get_attribute_descriptions(
+        first_location: u32,
+    ) -> Result<[vk::VertexInputAttributeDescription; 5]> {
+        // a mat4 attribute takes up 4 locations, one for each column
+        let transform_offset = u32::try_from(offset_of!(InstanceData, transform))?;
+        let column_size = u32::try_from(std::mem::size_of::<glm::Vec4>())?;
+        let transform_column = |column: u32| vk::VertexInputAttributeDescription {
+            binding: Self::BINDING,
+            // vertex shader locations 3 to 6 -> transform
+            location: first_location + column,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: transform_offset + column * column_size,
+        };
+
+        Ok([
+            transform_column(0),
+            transform_column(1),
+            transform_column(2),
+            transform_column(3),
+            vk::VertexInputAttributeDescription {
+                binding: Self::BINDING,
+                // vertex shader location 7 -> color
+                location: first_location + 4,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: u32::try_from(offset_of!(InstanceData, color))?,
+            },
+        ])
+    }
+}
@@ -0,0 +1,98 @@
+use crate::models::gltf_model::PbrMaterial;
+use mimic_common::texture::ColorSpace;
+use std::path::{Path, PathBuf};
+//////////////////////// Consts ///////////////////////
+/// The size of the material uniform block, which is three vec4 in std140 layout.
+pub const MATERIAL_UNIFORM_SIZE: usize = 12 * std::mem::size_of::<f32>();
+//////////////////////// Enums ///////////////////////
+/// Where the texture of a material comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MaterialTexture {
+    /// an image file which is loaded by the asset manager like the texture of a render command
+    File {
+        path: PathBuf,
+        color_space: ColorSpace,
+    },
+    /// an image that was decoded from the model file, index into `Mesh::images`
+    Image(usize),
+}
+//////////////////////// Structs ///////////////////////
+/// The material of a submesh, loaded from the .mtl file of an obj file or from the base color of a glTF material.
+///
+/// Shaders can read it through a uniform block in set 1, which has the layout:
+/// `{ vec4 ambient; vec4 diffuse; vec4 specular; }` where the w of `diffuse` holds the dissolve value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshMaterial {
+    pub name: String,
+    pub ambient: glm::Vec3,
+    pub diffuse: glm::Vec3,
+    pub specular: glm::Vec3,
+    /// the opacity of the material, 1 is fully opaque
+    pub dissolve: f32,
+    /// the texture that is sampled instead of the texture of the render command
+    pub diffuse_texture: Option<MaterialTexture>,
+}
+//////////////////////// Impls ///////////////////////
+impl Default for MeshMaterial {
+    /// The material of submeshes which don't have one. It leaves the texture color unchanged.
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            ambient: glm::vec3(0.0, 0.0, 0.0),
+            diffuse: glm::vec3(1.0, 1.0, 1.0),
+            specular: glm::vec3(0.0, 0.0, 0.0),
+            dissolve: 1.0,
+            diffuse_texture: None,
+        }
+    }
+}
+
+impl MeshMaterial {
+    /// The texture paths of the .mtl file are relative to the directory of the obj file in `obj_dir`.
+    pub fn from_obj_material(material: &tobj::Material, obj_dir: &Path) -> Self {
+        Self {
+            name: material.name.clone(),
+            ambient: glm::make_vec3(&material.ambient),
+            diffuse: glm::make_vec3(&material.diffuse),
+            specular: glm::make_vec3(&material.specular),
+            dissolve: material.dissolve,
+            diffuse_texture: if material.diffuse_texture.is_empty() {
+                None
+            } else {
+                Some(MaterialTexture::File {
+                    path: obj_dir.join(&material.diffuse_texture),
+                    color_space: ColorSpace::Srgb,
+                })
+            },
+        }
+    }
+
+    /// The base color of the glTF material becomes the diffuse color and the dissolve value.
+    /// The base color texture is an index into the images of the mesh, see `GltfModel::to_scene_mesh`.
+    pub fn from_gltf_material(material: &PbrMaterial, base_color_image: Option<usize>) -> Self {
+        Self {
+            name: material.name.clone().unwrap_or_default(),
+            diffuse: glm::vec4_to_vec3(&material.base_color_factor),
+            dissolve: material.base_color_factor.w,
+            diffuse_texture: base_color_image.map(MaterialTexture::Image),
+            ..Self::default()
+        }
+    }
+
+    pub fn get_uniform_data(&self) -> [f32; 12] {
+        [
+            self.ambient.x,
+            self.ambient.y,
+            self.ambient.z,
+            0.0,
+            self.diffuse.x,
+            self.diffuse.y,
+            self.diffuse.z,
+            self.dissolve,
+            self.specular.x,
+            self.specular.y,
+            self.specular.z,
+            0.0,
+        ]
+    }
+}
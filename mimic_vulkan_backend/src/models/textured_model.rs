@@ -1,68 +1,188 @@
 use crate::{
-    models::{index::IndexType, vertex::Vertex},
-    util::result::Result,
+    models::{
+        gltf_model::{self, GltfModel},
+        index::IndexType,
+        material::{MaterialTexture, MeshMaterial},
+        vertex::{VertexAttribute, VertexLayout},
+    },
+    util::result::{Result, VulkanError},
 };
 use bitflags::bitflags;
 use log::info;
-use std::{collections::HashMap, convert::TryFrom, path::Path};
+use mimic_common::texture::MemoryTextureSource;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    path::{Path, PathBuf},
+};
 use tobj::load_obj;
 //////////////////////// Bitflags ///////////////////////
 bitflags! {
+    /// How the vertices of a model file are converted while it is loaded.
     pub struct MeshLoadingFlags: u8 {
+        /// flips the v texture coordinate, for files whose texture origin is at the bottom left like obj
         const INVERTED_UP = 0b00000001;
+        /// rotates a model whose up axis is z so that its up axis is y
+        const Z_UP = 0b00000010;
+        /// mirrors the z axis to convert between left- and right-handed coordinates, the faces stay front facing
+        const FLIP_HANDEDNESS = 0b00000100;
+        /// replaces the normals of the file with the average of the face normals around each vertex
+        const SMOOTH_NORMALS = 0b00001000;
+        /// replaces the normals of the file with the face normals, so that every face is shaded flat.
+        /// This takes precedence over `SMOOTH_NORMALS`
+        const FLAT_NORMALS = 0b00010000;
     }
 }
 //////////////////////// Structs ///////////////////////
+/// The vertex data of a mesh, stored as one stream per attribute.
+/// Streams which are empty are generated or filled with defaults when the vertices are built for a `VertexLayout`.
+#[derive(Default)]
 pub struct Mesh {
-    pub vertices: Vec<Vertex>,
+    pub positions: Vec<glm::Vec3>,
+    pub normals: Vec<glm::Vec3>,
+    pub tangents: Vec<glm::Vec4>,
+    pub colors: Vec<glm::Vec3>,
+    pub tex_coords: Vec<glm::Vec2>,
+    /// the flattened data of the `VertexAttribute::Custom` attributes by their id
+    pub custom_attributes: HashMap<u32, Vec<f32>>,
     pub indices: Vec<IndexType>,
+    /// the named parts of the mesh, which together cover all indices
+    pub submeshes: Vec<Submesh>,
+    pub materials: Vec<MeshMaterial>,
+    /// the images that were decoded from the model file, which the materials refer to with `MaterialTexture::Image`
+    pub images: Vec<MemoryTextureSource>,
+}
+
+/// A named range of the indices of a mesh, which is drawn with one material.
+/// An object or group of an obj file is split into one submesh per material, which all have the name of the object.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Submesh {
+    pub name: String,
+    pub first_index: u32,
+    pub index_count: u32,
+    /// index into `Mesh::materials` or `None` if the submesh uses the default material
+    pub material: Option<usize>,
+    /// the bounds of the vertices of the submesh after the loading flags were applied
+    pub bounds: BoundingBox,
+}
+
+/// An axis aligned box.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingBox {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
 }
 //////////////////////// Impls ///////////////////////
+impl MeshLoadingFlags {
+    /// The flags which load the file into the texture coordinates that vulkan expects.
+    /// Obj texture coordinates start at the bottom left, gltf ones at the top left just like in vulkan.
+    pub fn default_for_file(filepath: &Path) -> Self {
+        if gltf_model::is_gltf_file(filepath) {
+            MeshLoadingFlags::empty()
+        } else {
+            MeshLoadingFlags::INVERTED_UP
+        }
+    }
+}
+
 impl Mesh {
+    /// Loads a Wavefront obj or a glTF 2.0 file, depending on the file extension.
+    /// The primitives of a glTF file are merged into one mesh, see `GltfModel::to_scene_mesh`.
     pub fn new(filepath: &Path, loading_props: MeshLoadingFlags) -> Result<Self> {
-        let (models, _materials) = load_obj(&filepath, true)?;
+        if gltf_model::is_gltf_file(filepath) {
+            GltfModel::new(filepath, loading_props)?.into_scene_mesh()
+        } else {
+            Self::from_obj(filepath, loading_props)
+        }
+    }
 
-        let mut vertices = Vec::new();
+    /// Every object or group of the file becomes a submesh with the material that it uses in the .mtl file.
+    /// Vertex and face indices which are out of bounds are reported as `VulkanError::MeshMalformed`.
+    fn from_obj(filepath: &Path, loading_props: MeshLoadingFlags) -> Result<Self> {
+        let (models, obj_materials) = load_obj(&filepath, true)?;
+        let obj_dir = filepath.parent().unwrap_or_else(|| Path::new(""));
+        let materials = obj_materials
+            .iter()
+            .map(|material| MeshMaterial::from_obj_material(material, obj_dir))
+            .collect();
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut tex_coords = Vec::new();
         let mut indices = Vec::new();
+        let mut submeshes = Vec::new();
+
+        // the normals and texture coordinates are kept if any model of the file has them.
+        // The models without them get generated normals and texture coordinates at the origin
+        let has_normals = models.iter().any(|model| !model.mesh.normals.is_empty());
+        let has_tex_coords = models.iter().any(|model| !model.mesh.texcoords.is_empty());
+        let mut is_normal_generated = Vec::new();
 
         let mut idx_cnt = 0;
+        // every model has its own vertices, so the vertex indices are only unique within a model
         let mut index_map = HashMap::new();
-        for (_i, model) in models.iter().enumerate() {
+        for (model_idx, model) in models.iter().enumerate() {
             let face_num = model.mesh.num_face_indices.len();
+            let first_index = u32::try_from(indices.len())?;
 
             // we passed true to load_obj which triangulates faces
+            if model.mesh.indices.len() < 3 * face_num {
+                return Err(malformed(
+                    filepath,
+                    format!(
+                        "model {} has {} faces but only {} indices",
+                        model.name,
+                        face_num,
+                        model.mesh.indices.len()
+                    ),
+                ));
+            }
             for face in 0..face_num {
                 for i in 0..3 {
                     let v_u32 = model.mesh.indices[3 * face + i];
-                    let idx_entry = index_map.entry(v_u32).or_insert(idx_cnt);
+                    let idx_entry = index_map.entry((model_idx, v_u32)).or_insert(idx_cnt);
 
                     // check if this vertex index is a duplicate of a vertex we already saw
                     if *idx_entry == idx_cnt {
-                        // new vertex -- parse it and put it in vertices
+                        // new vertex -- parse it and put it in the streams
                         let v = usize::try_from(v_u32)?;
+                        let out_of_bounds = |stream: &str| {
+                            malformed(
+                                filepath,
+                                format!("vertex {} of model {} has no {}", v, model.name, stream),
+                            )
+                        };
 
-                        let pos = glm::vec3(
-                            model.mesh.positions[3 * v],
-                            model.mesh.positions[3 * v + 1],
-                            model.mesh.positions[3 * v + 2],
-                        );
+                        let position = model
+                            .mesh
+                            .positions
+                            .get(3 * v..3 * v + 3)
+                            .ok_or_else(|| out_of_bounds("position"))?;
+                        positions.push(glm::make_vec3(position));
 
-                        let color = glm::vec3(1.0, 1.0, 1.0);
+                        if has_normals && model.mesh.normals.is_empty() {
+                            normals.push(glm::vec3(0.0, 0.0, 0.0));
+                            is_normal_generated.push(true);
+                        } else if has_normals {
+                            let normal = model
+                                .mesh
+                                .normals
+                                .get(3 * v..3 * v + 3)
+                                .ok_or_else(|| out_of_bounds("normal"))?;
+                            normals.push(glm::make_vec3(normal));
+                            is_normal_generated.push(false);
+                        }
 
-                        let tu = model.mesh.texcoords[2 * v];
-                        let tv = model.mesh.texcoords[2 * v + 1];
-                        let tv = if loading_props.contains(MeshLoadingFlags::INVERTED_UP) {
-                            1.0 - tv
-                        } else {
-                            tv
-                        };
-                        let tex_coord = glm::vec2(tu, tv);
-                        let vertex = Vertex {
-                            pos,
-                            color,
-                            tex_coord,
-                        };
-                        vertices.push(vertex);
+                        if has_tex_coords && model.mesh.texcoords.is_empty() {
+                            tex_coords.push(glm::vec2(0.0, 0.0));
+                        } else if has_tex_coords {
+                            let tex_coord = model
+                                .mesh
+                                .texcoords
+                                .get(2 * v..2 * v + 2)
+                                .ok_or_else(|| out_of_bounds("texture coordinate"))?;
+                            tex_coords.push(glm::make_vec2(tex_coord));
+                        }
 
                         // we used the idx_cnt value so increment
                         idx_cnt += 1;
@@ -71,8 +191,378 @@ impl Mesh {
                     indices.push(*idx_entry);
                 }
             }
+
+            submeshes.push(Submesh {
+                name: model.name.clone(),
+                first_index,
+                index_count: u32::try_from(indices.len())? - first_index,
+                material: model.mesh.material_id,
+                // the bounds are computed once the loading flags were applied
+                bounds: BoundingBox::default(),
+            });
+        }
+        info!(
+            "Model \"{:?}\" loaded. Vertices: {}, submeshes: {}",
+            filepath,
+            idx_cnt,
+            submeshes.len()
+        );
+        let mut mesh = Self {
+            positions,
+            normals,
+            tangents: Vec::new(),
+            colors: Vec::new(),
+            tex_coords,
+            custom_attributes: HashMap::new(),
+            indices,
+            submeshes,
+            materials,
+            images: Vec::new(),
+        };
+        if is_normal_generated.contains(&true) {
+            mesh.validate(filepath)?;
+            let file_normals = std::mem::take(&mut mesh.normals);
+            mesh.generate_normals();
+            for ((normal, file_normal), is_generated) in mesh
+                .normals
+                .iter_mut()
+                .zip(file_normals)
+                .zip(is_normal_generated)
+            {
+                if !is_generated {
+                    *normal = file_normal;
+                }
+            }
+        }
+        mesh.apply_loading_flags(filepath, loading_props)?;
+        Ok(mesh)
+    }
+
+    /// Checks that the mesh is well formed and converts its vertices as the `loading_props` ask for.
+    /// This is done by every loader after the mesh was read from the `filepath`.
+    pub fn apply_loading_flags(
+        &mut self,
+        filepath: &Path,
+        loading_props: MeshLoadingFlags,
+    ) -> Result<()> {
+        self.validate(filepath)?;
+
+        if loading_props.contains(MeshLoadingFlags::INVERTED_UP) {
+            for tex_coord in self.tex_coords.iter_mut() {
+                tex_coord.y = 1.0 - tex_coord.y;
+            }
+        }
+        self.apply_axis_flags(loading_props);
+
+        if loading_props.contains(MeshLoadingFlags::FLAT_NORMALS) {
+            self.split_faces();
+            self.generate_normals();
+            self.tangents.clear();
+        } else if loading_props.contains(MeshLoadingFlags::SMOOTH_NORMALS) {
+            self.generate_normals();
+            self.tangents.clear();
+        }
+        self.compute_submesh_bounds();
+        Ok(())
+    }
+
+    /// Converts the up axis and handedness of the vertices as the `loading_props` ask for.
+    /// Loaders whose files place their parts with transforms apply this once to the placed vertices.
+    pub fn apply_axis_flags(&mut self, loading_props: MeshLoadingFlags) {
+        if loading_props.contains(MeshLoadingFlags::Z_UP) {
+            // a rotation around the x axis which takes z to y and y to -z
+            let z_up_to_y_up = |v: &glm::Vec3| glm::vec3(v.x, v.z, -v.y);
+            self.transform_directions(z_up_to_y_up);
+        }
+        if loading_props.contains(MeshLoadingFlags::FLIP_HANDEDNESS) {
+            self.transform_directions(|v| glm::vec3(v.x, v.y, -v.z));
+            // the mirroring flips the winding order of the faces and the handedness of the bitangents
+            for face in self.indices.chunks_exact_mut(3) {
+                face.swap(1, 2);
+            }
+            for tangent in self.tangents.iter_mut() {
+                tangent.w = -tangent.w;
+            }
+        }
+    }
+
+    /// Sets the bounds of every submesh to the box around the vertices that its indices refer to.
+    pub fn compute_submesh_bounds(&mut self) {
+        let positions = &self.positions;
+        let indices = &self.indices;
+        for submesh in self.submeshes.iter_mut() {
+            let first_index = submesh.first_index as usize;
+            let submesh_indices = &indices[first_index..first_index + submesh.index_count as usize];
+            submesh.bounds = match submesh_indices.first() {
+                Some(&first) => submesh_indices.iter().fold(
+                    BoundingBox {
+                        min: positions[first as usize],
+                        max: positions[first as usize],
+                    },
+                    |bounds, &index| BoundingBox {
+                        min: glm::min2(&bounds.min, &positions[index as usize]),
+                        max: glm::max2(&bounds.max, &positions[index as usize]),
+                    },
+                ),
+                None => BoundingBox::default(),
+            };
+        }
+    }
+
+    pub fn get_vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Checks that the faces are triangles whose indices are in bounds and that every stream has a value for each vertex.
+    fn validate(&self, filepath: &Path) -> Result<()> {
+        if !self.indices.len().is_multiple_of(3) {
+            return Err(malformed(
+                filepath,
+                format!("{} indices don't make up triangles", self.indices.len()),
+            ));
+        }
+        let vertex_count = self.get_vertex_count();
+        if let Some(index) = self
+            .indices
+            .iter()
+            .find(|&&index| index as usize >= vertex_count)
+        {
+            return Err(malformed(
+                filepath,
+                format!(
+                    "index {} is out of bounds for {} vertices",
+                    index, vertex_count
+                ),
+            ));
+        }
+        let stream_lengths = [
+            ("normals", self.normals.len()),
+            ("tangents", self.tangents.len()),
+            ("colors", self.colors.len()),
+            ("texture coordinates", self.tex_coords.len()),
+        ];
+        for (stream, length) in stream_lengths.iter() {
+            if *length != 0 && *length != vertex_count {
+                return Err(malformed(
+                    filepath,
+                    format!("{} {} for {} vertices", length, stream, vertex_count),
+                ));
+            }
+        }
+        if let Some(submesh) = self.submeshes.iter().find(|submesh| {
+            submesh.first_index as usize + submesh.index_count as usize > self.indices.len()
+                || submesh
+                    .material
+                    .is_some_and(|material| material >= self.materials.len())
+        }) {
+            return Err(malformed(
+                filepath,
+                format!("submesh {:?} is out of bounds", submesh),
+            ));
+        }
+        if let Some(material) = self.materials.iter().find(|material| {
+            matches!(material.diffuse_texture, Some(MaterialTexture::Image(image)) if image >= self.images.len())
+        }) {
+            return Err(malformed(
+                filepath,
+                format!("the image of material {:?} is out of bounds", material.name),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Applies the linear `transform` to the positions, normals and tangents.
+    fn transform_directions(&mut self, transform: impl Fn(&glm::Vec3) -> glm::Vec3) {
+        for position in self.positions.iter_mut() {
+            *position = transform(position);
+        }
+        for normal in self.normals.iter_mut() {
+            *normal = transform(normal);
+        }
+        for tangent in self.tangents.iter_mut() {
+            let direction = transform(&glm::vec4_to_vec3(tangent));
+            *tangent = glm::vec4(direction.x, direction.y, direction.z, tangent.w);
+        }
+    }
+
+    /// Gives every face its own vertices, so that the faces don't share normals.
+    /// The order of the indices stays the same, so the submeshes are still valid.
+    fn split_faces(&mut self) {
+        fn split<T: Clone>(stream: &[T], indices: &[IndexType]) -> Vec<T> {
+            if stream.is_empty() {
+                Vec::new()
+            } else {
+                indices
+                    .iter()
+                    .map(|&index| stream[index as usize].clone())
+                    .collect()
+            }
+        }
+        let vertex_count = self.get_vertex_count();
+        self.positions = split(&self.positions, &self.indices);
+        self.tangents = split(&self.tangents, &self.indices);
+        self.colors = split(&self.colors, &self.indices);
+        self.tex_coords = split(&self.tex_coords, &self.indices);
+        for data in self.custom_attributes.values_mut() {
+            let component_count = data.len() / vertex_count.max(1);
+            *data = self
+                .indices
+                .iter()
+                .flat_map(|&index| {
+                    let start = index as usize * component_count;
+                    data[start..start + component_count].iter().copied()
+                })
+                .collect();
+        }
+        self.normals.clear();
+        self.indices = (0..self.positions.len() as IndexType).collect();
+    }
+
+    /// Interleaves the streams of the attributes of the `vertex_layout` into the data of a vertex buffer.
+    /// Normals and tangents which the mesh doesn't have are generated first.
+    pub fn build_vertices(&mut self, vertex_layout: &VertexLayout) -> Vec<f32> {
+        let attributes = vertex_layout.get_attributes();
+        if self.normals.is_empty()
+            && attributes.iter().any(|attribute| {
+                matches!(
+                    attribute,
+                    VertexAttribute::Normal | VertexAttribute::Tangent
+                )
+            })
+        {
+            self.generate_normals();
+        }
+        if self.tangents.is_empty() && attributes.contains(&VertexAttribute::Tangent) {
+            self.generate_tangents();
+        }
+
+        let mut vertices =
+            Vec::with_capacity(self.get_vertex_count() * vertex_layout.get_float_count());
+        for v in 0..self.get_vertex_count() {
+            for attribute in attributes.iter() {
+                match *attribute {
+                    VertexAttribute::Position => {
+                        vertices.extend_from_slice(self.positions[v].as_slice())
+                    }
+                    VertexAttribute::Normal => {
+                        vertices.extend_from_slice(self.normals[v].as_slice())
+                    }
+                    VertexAttribute::Tangent => {
+                        vertices.extend_from_slice(self.tangents[v].as_slice())
+                    }
+                    VertexAttribute::Color => match self.colors.get(v) {
+                        Some(color) => vertices.extend_from_slice(color.as_slice()),
+                        None => vertices.extend_from_slice(&[1.0, 1.0, 1.0]),
+                    },
+                    VertexAttribute::TexCoord => match self.tex_coords.get(v) {
+                        Some(tex_coord) => vertices.extend_from_slice(tex_coord.as_slice()),
+                        None => vertices.extend_from_slice(&[0.0, 0.0]),
+                    },
+                    VertexAttribute::Custom {
+                        id,
+                        component_count,
+                    } => {
+                        let component_count = component_count as usize;
+                        let data = self.custom_attributes.get(&id).and_then(|data| {
+                            data.get(v * component_count..(v + 1) * component_count)
+                        });
+                        match data {
+                            Some(data) => vertices.extend_from_slice(data),
+                            None => vertices.extend(std::iter::repeat_n(0.0, component_count)),
+                        }
+                    }
+                }
+            }
         }
-        info!("Model \"{:?}\" loaded. Vertices: {}", filepath, idx_cnt);
-        Ok(Self { vertices, indices })
+        vertices
     }
-}
\ No newline at end of file
+
+    /// Replaces the normals with the average of the normals of the faces around each vertex, weighted by the face area.
+    pub fn generate_normals(&mut self) {
+        let mut normals = vec![glm::vec3(0.0, 0.0, 0.0); self.get_vertex_count()];
+        for face in self.indices.chunks_exact(3) {
+            let [a, b, c] = [face[0] as usize, face[1] as usize, face[2] as usize];
+            // the length of the cross product is twice the area of the face
+            let face_normal = glm::cross(
+                &(self.positions[b] - self.positions[a]),
+                &(self.positions[c] - self.positions[a]),
+            );
+            for &vertex in [a, b, c].iter() {
+                normals[vertex] += face_normal;
+            }
+        }
+        self.normals = normals
+            .into_iter()
+            .map(|normal| {
+                if normal.norm_squared() > 0.0 {
+                    normal.normalize()
+                } else {
+                    glm::vec3(0.0, 1.0, 0.0)
+                }
+            })
+            .collect();
+    }
+
+    /// Generates tangents that point along the u texture coordinate and are orthogonal to the normals.
+    /// The normals have to exist already.
+    pub fn generate_tangents(&mut self) {
+        let vertex_count = self.get_vertex_count();
+        let mut tangents = vec![glm::vec3(0.0, 0.0, 0.0); vertex_count];
+        let mut bitangents = vec![glm::vec3(0.0, 0.0, 0.0); vertex_count];
+        if self.tex_coords.len() == vertex_count {
+            for face in self.indices.chunks_exact(3) {
+                let [a, b, c] = [face[0] as usize, face[1] as usize, face[2] as usize];
+                let edge1 = self.positions[b] - self.positions[a];
+                let edge2 = self.positions[c] - self.positions[a];
+                let delta_uv1 = self.tex_coords[b] - self.tex_coords[a];
+                let delta_uv2 = self.tex_coords[c] - self.tex_coords[a];
+                let determinant = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+                if determinant.abs() <= f32::EPSILON {
+                    // the face has no area in texture space so it can't orient the tangent
+                    continue;
+                }
+                let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) / determinant;
+                let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) / determinant;
+                for &vertex in [a, b, c].iter() {
+                    tangents[vertex] += tangent;
+                    bitangents[vertex] += bitangent;
+                }
+            }
+        }
+
+        self.tangents = (0..vertex_count)
+            .map(|v| {
+                let normal = self.normals[v];
+                // Gram-Schmidt orthogonalize the tangent against the normal
+                let tangent = tangents[v] - normal * glm::dot(&normal, &tangents[v]);
+                let tangent = if tangent.norm_squared() > f32::EPSILON {
+                    tangent.normalize()
+                } else {
+                    any_orthogonal(&normal)
+                };
+                let handedness = if glm::dot(&glm::cross(&normal, &tangent), &bitangents[v]) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                glm::vec4(tangent.x, tangent.y, tangent.z, handedness)
+            })
+            .collect();
+    }
+}
+//////////////////////// Fns ///////////////////////
+fn any_orthogonal(normal: &glm::Vec3) -> glm::Vec3 {
+    let axis = if normal.x.abs() < 0.9 {
+        glm::vec3(1.0, 0.0, 0.0)
+    } else {
+        glm::vec3(0.0, 1.0, 0.0)
+    };
+    glm::cross(normal, &axis).normalize()
+}
+
+fn malformed(filepath: &Path, reason: String) -> VulkanError {
+    VulkanError::MeshMalformed {
+        file: PathBuf::from(filepath),
+        reason,
+    }
+}
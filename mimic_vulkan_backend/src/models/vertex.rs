@@ -1,55 +1,137 @@
-use crate::{buffers::memory::MemoryCopyable, util::result::Result};
+use crate::{
+    buffers::memory::MemoryCopyable,
+    util::result::{Result, VulkanError},
+};
 use ash::vk;
-use memoffset::offset_of;
 use std::convert::TryFrom;
+//////////////////////// Enums ///////////////////////
+/// A per-vertex attribute that a `Mesh` can provide. All attributes are stored as 32 bit floats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VertexAttribute {
+    /// vec3
+    Position,
+    /// vec3, loaded from the model file or generated from the faces if the file has none
+    Normal,
+    /// vec4, generated from the normals and texture coordinates. w holds the handedness of the bitangent
+    Tangent,
+    /// vec3, white unless the mesh has vertex colors
+    Color,
+    /// vec2
+    TexCoord,
+    /// `component_count` floats of user data with the `id`, which are zero unless the mesh has data for the `id`
+    Custom { id: u32, component_count: u32 },
+}
 //////////////////////// Structs ///////////////////////
-#[repr(C)]
-pub struct Vertex {
-    pub pos: glm::Vec3,
-    pub color: glm::Vec3,
-    pub tex_coord: glm::Vec2,
+/// The attributes that are interleaved in the vertex buffer of a mesh, in order.
+/// The attribute at index `i` is read from vertex shader location `i`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+    attributes: Vec<VertexAttribute>,
 }
 //////////////////////// Impls ///////////////////////
-impl MemoryCopyable for [Vertex] {
+impl MemoryCopyable for [f32] {
     unsafe fn copy_to_mapped_memory(&self, data_target_ptr: *mut std::ffi::c_void) {
-        let data_ptr = data_target_ptr as *mut Vertex;
+        let data_ptr = data_target_ptr as *mut f32;
         data_ptr.copy_from_nonoverlapping(self.as_ptr(), self.len());
     }
 }
 
-impl Vertex {
-    pub fn get_binding_description() -> Result<vk::VertexInputBindingDescription> {
+impl VertexAttribute {
+    pub fn get_component_count(self) -> u32 {
+        match self {
+            VertexAttribute::Position | VertexAttribute::Normal | VertexAttribute::Color => 3,
+            VertexAttribute::Tangent => 4,
+            VertexAttribute::TexCoord => 2,
+            VertexAttribute::Custom {
+                component_count, ..
+            } => component_count,
+        }
+    }
+
+    fn get_format(self) -> vk::Format {
+        match self.get_component_count() {
+            1 => vk::Format::R32_SFLOAT,
+            2 => vk::Format::R32G32_SFLOAT,
+            3 => vk::Format::R32G32B32_SFLOAT,
+            _ => vk::Format::R32G32B32A32_SFLOAT,
+        }
+    }
+}
+
+impl Default for VertexLayout {
+    /// The position, color and texture coordinates, which is what the shaders of mimic read.
+    fn default() -> Self {
+        Self {
+            attributes: vec![
+                VertexAttribute::Position,
+                VertexAttribute::Color,
+                VertexAttribute::TexCoord,
+            ],
+        }
+    }
+}
+
+impl VertexLayout {
+    /// the per-vertex data is in binding 0
+    pub const BINDING: u32 = 0;
+
+    /// Every attribute can appear at most once and custom attributes need 1 to 4 components.
+    pub fn new(attributes: Vec<VertexAttribute>) -> Result<Self> {
+        for (i, attribute) in attributes.iter().enumerate() {
+            if attributes[..i].contains(attribute) {
+                return Err(VulkanError::VertexLayoutInvalid(format!(
+                    "{:?} appears more than once",
+                    attribute
+                )));
+            }
+            if !(1..=4).contains(&attribute.get_component_count()) {
+                return Err(VulkanError::VertexLayoutInvalid(format!(
+                    "{:?} needs between 1 and 4 components",
+                    attribute
+                )));
+            }
+        }
+        Ok(Self { attributes })
+    }
+
+    pub fn get_attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    /// The number of floats of one vertex.
+    pub fn get_float_count(&self) -> usize {
+        self.attributes
+            .iter()
+            .map(|attribute| attribute.get_component_count() as usize)
+            .sum()
+    }
+
+    /// The number of shader locations that the attributes take up. The per-instance attributes follow after them.
+    pub fn get_location_count(&self) -> Result<u32> {
+        Ok(u32::try_from(self.attributes.len())?)
+    }
+
+    pub fn get_binding_description(&self) -> Result<vk::VertexInputBindingDescription> {
         Ok(vk::VertexInputBindingDescription {
             // index of the binding in the array of bindings
-            binding: 0,
-            stride: u32::try_from(std::mem::size_of::<Self>())?,
+            binding: Self::BINDING,
+            stride: u32::try_from(self.get_float_count() * std::mem::size_of::<f32>())?,
             input_rate: vk::VertexInputRate::VERTEX,
         })
     }
 
-    pub fn get_attribute_descriptions() -> Result<[vk::VertexInputAttributeDescription; 3]> {
-        Ok([
-            vk::VertexInputAttributeDescription {
-                binding: 0,
-                // vertex shader location 0 -> position
-                location: 0,
-                format: vk::Format::R32G32B32_SFLOAT,
-                offset: u32::try_from(offset_of!(Vertex, pos))?,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 0,
-                // vertex shader location 1 -> color
-                location: 1,
-                format: vk::Format::R32G32B32_SFLOAT,
-                offset: u32::try_from(offset_of!(Vertex, color))?,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 0,
-                // vertex shader location 2 -> texture coords
-                location: 2,
-                format: vk::Format::R32G32_SFLOAT,
-                offset: u32::try_from(offset_of!(Vertex, tex_coord))?,
-            },
-        ])
+    pub fn get_attribute_descriptions(&self) -> Result<Vec<vk::VertexInputAttributeDescription>> {
+        let mut offset = 0;
+        let mut attribute_descriptions = Vec::with_capacity(self.attributes.len());
+        for (location, attribute) in self.attributes.iter().enumerate() {
+            attribute_descriptions.push(vk::VertexInputAttributeDescription {
+                binding: Self::BINDING,
+                location: u32::try_from(location)?,
+                format: attribute.get_format(),
+                offset: u32::try_from(offset)?,
+            });
+            offset += attribute.get_component_count() as usize * std::mem::size_of::<f32>();
+        }
+        Ok(attribute_descriptions)
     }
 }
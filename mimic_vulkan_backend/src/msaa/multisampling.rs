@@ -1,5 +1,6 @@
 use crate::{
-    presentation::swap_chain::SwapChainContainer,
+    buffers::memory::allocator::MemoryAllocator,
+    presentation::render_target::RenderTarget,
     textures::images::{Image, MipmapParam},
     util::result::Result,
 };
@@ -14,24 +15,22 @@ pub struct ColorResource {
 impl ColorResource {
     pub fn new(
         msaa_samples: vk::SampleCountFlags,
-        instance: &ash::Instance,
+        allocator: &mut MemoryAllocator,
         logical_device: &ash::Device,
-        physical_device: vk::PhysicalDevice,
-        swap_chain_container: &SwapChainContainer,
+        render_target: &RenderTarget,
     ) -> Result<Self> {
-        let color_format = swap_chain_container.swap_chain_format.format;
+        let color_format = render_target.get_format();
 
         let image = Image::new(
-            swap_chain_container.swap_chain_extent.width,
-            swap_chain_container.swap_chain_extent.height,
+            render_target.get_extent().width,
+            render_target.get_extent().height,
             MipmapParam::NoMipmap,
             msaa_samples,
             color_format,
             vk::ImageTiling::OPTIMAL,
             vk::ImageUsageFlags::TRANSIENT_ATTACHMENT | vk::ImageUsageFlags::COLOR_ATTACHMENT,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            instance,
-            physical_device,
+            allocator,
             logical_device,
         )?;
 
@@ -45,9 +44,8 @@ impl ColorResource {
     ///
     /// This method calls unsafe, low-level vulkan api functions to destroy images and free memory.
     /// It must be called with valid vulkan state.
-    pub unsafe fn drop(self, logical_device: &ash::Device) {
+    pub unsafe fn drop(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
         logical_device.destroy_image_view(self.view, None);
-        logical_device.destroy_image(self.image.image, None);
-        logical_device.free_memory(self.image.memory, None);
+        self.image.destroy(allocator, logical_device);
     }
 }
@@ -1,2 +1,4 @@
+pub mod frame_capture;
 pub mod image_views;
+pub mod render_target;
 pub mod swap_chain;
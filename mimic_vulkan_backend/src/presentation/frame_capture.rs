@@ -0,0 +1,306 @@
+use crate::{
+    buffers::{buffer::Buffer, memory::allocator::MemoryAllocator},
+    devices::queues::QueueMap,
+    drawing::command_buffers::{begin_single_time_commands, end_single_time_commands},
+    presentation::render_target::RenderTarget,
+    util::result::{Result, VulkanError},
+};
+use ash::vk;
+use image::RgbaImage;
+use std::convert::TryFrom;
+//////////////////////// Enums ///////////////////////
+/// The order of the color channels of a captured image as it is laid out in device memory.
+#[derive(Clone, Copy)]
+enum ChannelOrder {
+    Rgba,
+    Bgra,
+}
+//////////////////////// Structs ///////////////////////
+/// A host visible buffer which a rendered image is copied into, so that it can be read back once the copy finished.
+/// Swap chain images are handed to the presentation engine at the end of their frame,
+/// so their copy has to be recorded into the command buffer of the frame before it is presented.
+pub struct FrameCapture {
+    readback_buffer: Buffer,
+    extent: vk::Extent2D,
+    channel_order: ChannelOrder,
+    /// the swap chain is composited as opaque so whatever ended up in its alpha channel is not what was shown
+    is_opaque: bool,
+}
+//////////////////////// Impls ///////////////////////
+impl FrameCapture {
+    /// Creates the readback buffer for an image of the `render_target`.
+    pub fn new(
+        render_target: &RenderTarget,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) -> Result<Self> {
+        if let RenderTarget::SwapChain(swap_chain_container) = render_target {
+            if !swap_chain_container
+                .swap_chain_usage
+                .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+            {
+                return Err(VulkanError::CaptureUnsupported(String::from(
+                    "swap chain images cannot be used as a transfer source",
+                )));
+            }
+        }
+        let channel_order = get_channel_order(render_target.get_format())?;
+        let extent = render_target.get_extent();
+
+        let pixel_count = usize::try_from(extent.width)? * usize::try_from(extent.height)?;
+        let buffer_size = vk::DeviceSize::try_from(pixel_count * 4)?;
+        let readback_buffer = Buffer::new(
+            allocator,
+            logical_device,
+            buffer_size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        Ok(Self {
+            readback_buffer,
+            extent,
+            channel_order,
+            is_opaque: matches!(render_target, RenderTarget::SwapChain(_)),
+        })
+    }
+
+    /// Records the copy of the render target image with `image_index` into the readback buffer.
+    /// The image is expected to be in the final layout of the render pass and it is returned to that layout after the copy.
+    pub fn record_copy(
+        &self,
+        render_target: &RenderTarget,
+        image_index: usize,
+        command_buffer: vk::CommandBuffer,
+        logical_device: &ash::Device,
+    ) -> Result<()> {
+        let image = *render_target.get_images().get(image_index).ok_or_else(|| {
+            VulkanError::CaptureUnsupported(format!(
+                "no render target image with index {}",
+                image_index
+            ))
+        })?;
+        record_image_to_buffer_copy(
+            image,
+            render_target.get_final_layout(),
+            self.extent,
+            &self.readback_buffer,
+            command_buffer,
+            logical_device,
+        );
+        Ok(())
+    }
+
+    /// Converts the copied pixels to RGBA8 and frees the readback buffer.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer which the copy was recorded into must have finished executing.
+    pub unsafe fn read(
+        self,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) -> Result<RgbaImage> {
+        let pixel_count =
+            usize::try_from(self.extent.width)? * usize::try_from(self.extent.height)?;
+        let pixels = match self.readback_buffer.allocation.get_mapped_ptr() {
+            Some(data_ptr) => {
+                let mut pixels = vec![0u8; pixel_count * 4];
+                pixels
+                    .as_mut_ptr()
+                    .copy_from_nonoverlapping(data_ptr as *const u8, pixels.len());
+                Ok(pixels)
+            }
+            None => Err(VulkanError::MemoryNotHostVisible),
+        };
+        let extent = self.extent;
+        let channel_order = self.channel_order;
+        let is_opaque = self.is_opaque;
+        self.destroy(allocator, logical_device);
+
+        let mut pixels = pixels?;
+        for pixel in pixels.chunks_exact_mut(4) {
+            if let ChannelOrder::Bgra = channel_order {
+                pixel.swap(0, 2);
+            }
+            if is_opaque {
+                pixel[3] = u8::MAX;
+            }
+        }
+
+        RgbaImage::from_raw(extent.width, extent.height, pixels).ok_or_else(|| {
+            VulkanError::CaptureUnsupported(String::from(
+                "captured pixels do not match the image size",
+            ))
+        })
+    }
+
+    /// Frees the readback buffer without reading it.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer which the copy was recorded into must not be executing anymore.
+    pub unsafe fn destroy(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
+        self.readback_buffer.destroy(allocator, logical_device);
+    }
+}
+//////////////////////// Fns ///////////////////////
+/// Reads the rendered image with `image_index` from the `render_target` back to the cpu as RGBA8 pixels.
+/// The image is expected to be in the final layout of the render pass and it is returned to that layout after the copy.
+/// The caller has to make sure that no in-flight frame is still writing to the image and that it isn't presented,
+/// so this only works for the offscreen image. Swap chain images are copied by their frame using `FrameCapture`.
+pub fn capture_render_target_image(
+    render_target: &RenderTarget,
+    image_index: usize,
+    allocator: &mut MemoryAllocator,
+    logical_device: &ash::Device,
+    command_pool: vk::CommandPool,
+    queues: &QueueMap,
+) -> Result<RgbaImage> {
+    let frame_capture = FrameCapture::new(render_target, allocator, logical_device)?;
+
+    let copy_result =
+        begin_single_time_commands(logical_device, command_pool).and_then(|command_buffer| {
+            frame_capture.record_copy(
+                render_target,
+                image_index,
+                command_buffer,
+                logical_device,
+            )?;
+            end_single_time_commands(command_buffer, logical_device, queues, command_pool)
+        });
+
+    unsafe {
+        match copy_result {
+            Ok(()) => frame_capture.read(allocator, logical_device),
+            Err(error) => {
+                frame_capture.destroy(allocator, logical_device);
+                Err(error)
+            }
+        }
+    }
+}
+
+/// Records the copy of the whole color image into the `buffer`, tightly packed.
+/// The image is transitioned from `layout` to a transfer source layout for the copy and then back to `layout`.
+fn record_image_to_buffer_copy(
+    image: vk::Image,
+    layout: vk::ImageLayout,
+    extent: vk::Extent2D,
+    buffer: &Buffer,
+    command_buffer: vk::CommandBuffer,
+    logical_device: &ash::Device,
+) {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1)
+        .build();
+
+    // wait for the render pass to finish writing the color attachment before we read from it.
+    // The transfer stage chains the barrier to the outgoing dependency of the render pass, which orders the final layout transition
+    let to_transfer_barrier = vk::ImageMemoryBarrier {
+        old_layout: layout,
+        new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresource_range,
+        src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+        dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+        ..Default::default()
+    };
+
+    let buffer_image_copy = vk::BufferImageCopy {
+        buffer_offset: 0,
+        // 0 for row_length and height just says pixels are tightly packed
+        buffer_row_length: 0,
+        buffer_image_height: 0,
+        image_subresource: vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1)
+            .build(),
+        image_offset: vk::Offset3D::builder().x(0).y(0).z(0).build(),
+        image_extent: vk::Extent3D::builder()
+            .width(extent.width)
+            .height(extent.height)
+            .depth(1)
+            .build(),
+    };
+
+    // the image goes back to the layout the rest of the frame expects it in
+    let from_transfer_barrier = vk::ImageMemoryBarrier {
+        old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        new_layout: layout,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresource_range,
+        src_access_mask: vk::AccessFlags::TRANSFER_READ,
+        dst_access_mask: vk::AccessFlags::empty(),
+        ..Default::default()
+    };
+
+    // the copied pixels have to be visible to the host once the fence of the command buffer is signaled
+    let to_host_barrier = vk::BufferMemoryBarrier {
+        src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+        dst_access_mask: vk::AccessFlags::HOST_READ,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        buffer: buffer.buffer,
+        offset: 0,
+        size: vk::WHOLE_SIZE,
+        ..Default::default()
+    };
+
+    unsafe {
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[to_transfer_barrier],
+        );
+        logical_device.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer.buffer,
+            &[buffer_image_copy],
+        );
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[from_transfer_barrier],
+        );
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[to_host_barrier],
+            &[],
+        );
+    }
+}
+
+// the bytes of an sRGB image are already gamma encoded, which is what image files expect
+// so for the 8 bit formats we only need to put the channels in the right order
+fn get_channel_order(format: vk::Format) -> Result<ChannelOrder> {
+    match format {
+        vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => Ok(ChannelOrder::Rgba),
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => Ok(ChannelOrder::Bgra),
+        _ => Err(VulkanError::CaptureFormatUnsupported(format)),
+    }
+}
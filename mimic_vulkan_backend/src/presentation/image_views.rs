@@ -1,4 +1,4 @@
-use crate::presentation::swap_chain::SwapChainContainer;
+use crate::presentation::render_target::RenderTarget;
 use crate::util::result::Result;
 
 use ash::vk;
@@ -8,17 +8,14 @@ pub struct ImageViews {
 }
 //////////////////////// Impls ///////////////////////
 impl ImageViews {
-    pub fn new(
-        logical_device: &ash::Device,
-        swap_chain_container: &SwapChainContainer,
-    ) -> Result<Self> {
-        let mut image_views = Vec::with_capacity(swap_chain_container.swap_chain_images.len());
+    pub fn new(logical_device: &ash::Device, render_target: &RenderTarget) -> Result<Self> {
+        let mut image_views = Vec::with_capacity(render_target.get_image_count());
 
-        for &image in &swap_chain_container.swap_chain_images {
+        for image in render_target.get_images() {
             let image_view_create_info = vk::ImageViewCreateInfo {
                 image,
                 view_type: vk::ImageViewType::TYPE_2D,
-                format: swap_chain_container.swap_chain_format.format,
+                format: render_target.get_format(),
                 components: vk::ComponentMapping::builder()
                     .r(vk::ComponentSwizzle::IDENTITY)
                     .g(vk::ComponentSwizzle::IDENTITY)
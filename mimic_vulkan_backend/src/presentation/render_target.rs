@@ -0,0 +1,112 @@
+use crate::{
+    buffers::memory::allocator::MemoryAllocator,
+    presentation::swap_chain::SwapChainContainer,
+    textures::images::{Image, MipmapParam},
+    util::result::Result,
+    window::WindowSize,
+};
+use ash::vk;
+//////////////////////// Enums ///////////////////////
+/// The images that a frame is rendered into.
+/// When we have a window we render into the swap-chain images and present them.
+/// When we are headless there is no surface so we render into a single offscreen image instead.
+pub enum RenderTarget {
+    SwapChain(SwapChainContainer),
+    Offscreen(OffscreenImage),
+}
+//////////////////////// Structs ///////////////////////
+/// A color image which takes the place of the swap-chain images when rendering without a window.
+#[derive(Default)]
+pub struct OffscreenImage {
+    pub image: Image,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+//////////////////////// Impls ///////////////////////
+impl RenderTarget {
+    pub fn get_extent(&self) -> vk::Extent2D {
+        match self {
+            RenderTarget::SwapChain(swap_chain_container) => swap_chain_container.swap_chain_extent,
+            RenderTarget::Offscreen(offscreen_image) => offscreen_image.extent,
+        }
+    }
+
+    pub fn get_format(&self) -> vk::Format {
+        match self {
+            RenderTarget::SwapChain(swap_chain_container) => {
+                swap_chain_container.swap_chain_format.format
+            }
+            RenderTarget::Offscreen(offscreen_image) => offscreen_image.format,
+        }
+    }
+
+    pub fn get_images(&self) -> Vec<vk::Image> {
+        match self {
+            RenderTarget::SwapChain(swap_chain_container) => {
+                swap_chain_container.swap_chain_images.clone()
+            }
+            RenderTarget::Offscreen(offscreen_image) => vec![offscreen_image.image.image],
+        }
+    }
+
+    pub fn get_image_count(&self) -> usize {
+        match self {
+            RenderTarget::SwapChain(swap_chain_container) => {
+                swap_chain_container.swap_chain_images.len()
+            }
+            RenderTarget::Offscreen(_) => 1,
+        }
+    }
+
+    /// The layout that the rendered (resolved) color image should be in once the render pass finishes.
+    pub fn get_final_layout(&self) -> vk::ImageLayout {
+        match self {
+            RenderTarget::SwapChain(_) => vk::ImageLayout::PRESENT_SRC_KHR,
+            // nobody presents an offscreen image, so we leave it ready to be copied out of
+            RenderTarget::Offscreen(_) => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        }
+    }
+}
+
+impl OffscreenImage {
+    pub const FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+    pub fn new(
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        window_size: &WindowSize,
+    ) -> Result<Self> {
+        let extent = vk::Extent2D {
+            width: window_size.monitor_resolution_width,
+            height: window_size.monitor_resolution_height,
+        };
+
+        let image = Image::new(
+            extent.width,
+            extent.height,
+            MipmapParam::NoMipmap,
+            vk::SampleCountFlags::TYPE_1,
+            Self::FORMAT,
+            vk::ImageTiling::OPTIMAL,
+            // the image is the resolve target of the render pass and we want to be able to read it back
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            allocator,
+            logical_device,
+        )?;
+
+        Ok(Self {
+            image,
+            format: Self::FORMAT,
+            extent,
+        })
+    }
+
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy images and free memory.
+    /// It must be called with valid vulkan state.
+    pub unsafe fn drop(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
+        self.image.destroy(allocator, logical_device);
+    }
+}
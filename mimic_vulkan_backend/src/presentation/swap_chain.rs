@@ -14,6 +14,7 @@ pub struct SwapChainContainer {
     pub swap_chain_images: Vec<vk::Image>,
     pub swap_chain_format: vk::SurfaceFormatKHR,
     pub swap_chain_extent: vk::Extent2D,
+    pub swap_chain_usage: vk::ImageUsageFlags,
 }
 
 pub struct SwapChainSupportDetails {
@@ -39,6 +40,11 @@ impl SwapChainContainer {
         let extent = swap_chain_support_details.choose_swap_extent(window_size);
         let min_image_count = swap_chain_support_details.choose_swap_min_image_count();
 
+        // if the surface allows it we also want to be able to copy out of the swap chain images to capture frames
+        let swap_chain_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (swap_chain_support_details.capabilities.supported_usage_flags
+                & vk::ImageUsageFlags::TRANSFER_SRC);
+
         let (sharing_mode, sharing_queue_count, sharing_indices) =
             queue_indices.get_image_sharing_details();
 
@@ -51,7 +57,7 @@ impl SwapChainContainer {
             present_mode,
             clipped: vk::TRUE,
             image_array_layers: 1,
-            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            image_usage: swap_chain_usage,
             image_sharing_mode: sharing_mode,
             queue_family_index_count: u32::try_from(sharing_queue_count)?,
             p_queue_family_indices: sharing_indices.as_ptr(),
@@ -72,6 +78,7 @@ impl SwapChainContainer {
             swap_chain_images,
             swap_chain_format: surface_format,
             swap_chain_extent: extent,
+            swap_chain_usage,
         })
     }
 }
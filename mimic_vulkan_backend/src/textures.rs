@@ -1 +1,2 @@
+pub mod compressed_texture;
 pub mod images;
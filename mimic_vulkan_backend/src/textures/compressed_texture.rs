@@ -0,0 +1,405 @@
+use crate::util::result::{Result, VulkanError};
+
+use ash::vk;
+use ddsfile::{D3DFormat, Dds, DxgiFormat};
+use mimic_common::texture::ColorSpace;
+use std::{
+    convert::TryFrom,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+//////////////////////// Types ///////////////////////
+type DecodeFn = fn(&[u8], usize, usize, &mut [u32]) -> std::result::Result<(), &'static str>;
+//////////////////////// Structs ///////////////////////
+/// A texture in a gpu block compression format (BCn, ETC2 or ASTC) loaded from a .ktx2 or .dds file.
+/// The mip levels are taken from the file instead of being generated at runtime.
+pub struct CompressedTextureSource {
+    pub path: Rc<PathBuf>,
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+    /// the data of each mip level, starting with the full size image
+    pub mip_levels: Vec<Vec<u8>>,
+}
+
+/// How the data of a block compressed format is laid out and how it can be decoded on the cpu.
+struct BlockFormat {
+    block_width: u32,
+    block_height: u32,
+    block_size: u32,
+    decode: DecodeFn,
+}
+//////////////////////// Impls ///////////////////////
+impl CompressedTextureSource {
+    /// The `color_space` is only used by legacy .dds files, whose formats don't say if they are srgb.
+    pub fn new(path: &Rc<PathBuf>, color_space: ColorSpace) -> Result<Self> {
+        let bytes = std::fs::read(path.as_path()).map_err(|source| {
+            VulkanError::TextureFileReadFailure {
+                source,
+                file: path.to_path_buf(),
+            }
+        })?;
+        if has_extension(path, "ktx2") {
+            Self::from_ktx2(path, &bytes)
+        } else {
+            Self::from_dds(path, &bytes, color_space)
+        }
+    }
+
+    fn from_ktx2(path: &Rc<PathBuf>, bytes: &[u8]) -> Result<Self> {
+        let reader = ktx2::Reader::new(bytes)
+            .map_err(|error| malformed(path, format!("Invalid ktx2 file: {:?}", error)))?;
+        let header = reader.header();
+
+        if header.supercompression_scheme.is_some() {
+            return Err(unsupported(
+                path,
+                "supercompressed ktx2 files are not supported",
+            ));
+        }
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            return Err(unsupported(path, "only 2d textures are supported"));
+        }
+        let format = match header.format {
+            Some(format) => vk::Format::from_raw(i32::try_from(format.value())?),
+            None => return Err(unsupported(path, "the ktx2 file has no vulkan format")),
+        };
+
+        let texture = Self {
+            path: Rc::clone(path),
+            format,
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            mip_levels: reader.levels().map(|level| level.data.to_vec()).collect(),
+        };
+        texture.validate()?;
+        Ok(texture)
+    }
+
+    fn from_dds(path: &Rc<PathBuf>, bytes: &[u8], color_space: ColorSpace) -> Result<Self> {
+        let dds = Dds::read(bytes)
+            .map_err(|error| malformed(path, format!("Invalid dds file: {}", error)))?;
+
+        if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+            return Err(unsupported(path, "only 2d textures are supported"));
+        }
+        // `get_dxgi_format` also maps the legacy DXT formats, always to srgb, so they are checked first
+        let format = match &dds.header10 {
+            Some(header10) => get_dxgi_vk_format(header10.dxgi_format),
+            None => dds
+                .get_d3d_format()
+                .and_then(|d3d_format| get_d3d_vk_format(d3d_format, color_space))
+                .or_else(|| dds.get_dxgi_format().and_then(get_dxgi_vk_format)),
+        }
+        .ok_or_else(|| unsupported(path, "the dds file is not block compressed"))?;
+        let block_format = get_block_format(format)
+            .ok_or_else(|| unsupported(path, format!("format {:?}", format)))?;
+
+        let (width, height) = (dds.get_width(), dds.get_height());
+        let data = dds
+            .get_data(0)
+            .map_err(|error| malformed(path, format!("Invalid dds file: {}", error)))?;
+        // the dds file stores the mip levels one after another, so they are split by their sizes
+        let mut mip_levels = Vec::new();
+        let mut offset: usize = 0;
+        let mip_level_count = dds.get_num_mipmap_levels().max(1);
+        if mip_level_count > get_full_mip_level_count(width, height) {
+            return Err(malformed(
+                path,
+                format!(
+                    "{} mip levels are more than the full mip chain",
+                    mip_level_count
+                ),
+            ));
+        }
+        for mip_level in 0..mip_level_count {
+            let level_size = block_format
+                .get_level_size(
+                    get_mip_extent(width, mip_level),
+                    get_mip_extent(height, mip_level),
+                )
+                .ok_or_else(|| malformed(path, format!("mip level {} is too large", mip_level)))?;
+            let level_end = offset
+                .checked_add(level_size)
+                .ok_or_else(|| malformed(path, format!("mip level {} is too large", mip_level)))?;
+            let level_data = data
+                .get(offset..level_end)
+                .ok_or_else(|| malformed(path, format!("mip level {} is truncated", mip_level)))?;
+            mip_levels.push(level_data.to_vec());
+            offset = level_end;
+        }
+
+        let texture = Self {
+            path: Rc::clone(path),
+            format,
+            width,
+            height,
+            mip_levels,
+        };
+        texture.validate()?;
+        Ok(texture)
+    }
+
+    /// The format which the texture is decompressed into when the device doesn't support its format.
+    pub fn get_decompressed_format(&self) -> vk::Format {
+        if is_srgb(self.format) {
+            vk::Format::R8G8B8A8_SRGB
+        } else {
+            vk::Format::R8G8B8A8_UNORM
+        }
+    }
+
+    /// Decodes every mip level into RGBA8 pixels, for devices which don't support the format of the texture.
+    pub fn decompress(&self) -> Result<Vec<Vec<u8>>> {
+        let block_format = get_block_format(self.format)
+            .ok_or_else(|| unsupported(&self.path, format!("format {:?}", self.format)))?;
+
+        let mut decompressed_levels = Vec::with_capacity(self.mip_levels.len());
+        for (mip_level, level_data) in self.mip_levels.iter().enumerate() {
+            let mip_level = u32::try_from(mip_level)?;
+            let width = usize::try_from(get_mip_extent(self.width, mip_level))?;
+            let height = usize::try_from(get_mip_extent(self.height, mip_level))?;
+            let mut bgra_pixels = vec![0u32; width * height];
+            (block_format.decode)(level_data, width, height, &mut bgra_pixels)
+                .map_err(|reason| malformed(&self.path, reason))?;
+            // the decoder packs each pixel as a little endian bgra value
+            decompressed_levels.push(
+                bgra_pixels
+                    .iter()
+                    .flat_map(|pixel| {
+                        let [b, g, r, a] = pixel.to_le_bytes();
+                        [r, g, b, a]
+                    })
+                    .collect(),
+            );
+        }
+        Ok(decompressed_levels)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let block_format = get_block_format(self.format)
+            .ok_or_else(|| unsupported(&self.path, format!("format {:?}", self.format)))?;
+        if self.width == 0 || self.mip_levels.is_empty() {
+            return Err(malformed(&self.path, "the texture is empty"));
+        }
+        if self.mip_levels.len() > get_full_mip_level_count(self.width, self.height) as usize {
+            return Err(malformed(
+                &self.path,
+                format!(
+                    "{} mip levels are more than the full mip chain",
+                    self.mip_levels.len()
+                ),
+            ));
+        }
+        for (mip_level, level_data) in self.mip_levels.iter().enumerate() {
+            let mip_level = u32::try_from(mip_level)?;
+            let level_size = block_format
+                .get_level_size(
+                    get_mip_extent(self.width, mip_level),
+                    get_mip_extent(self.height, mip_level),
+                )
+                .ok_or_else(|| {
+                    malformed(&self.path, format!("mip level {} is too large", mip_level))
+                })?;
+            if level_data.len() < level_size {
+                return Err(malformed(
+                    &self.path,
+                    format!("mip level {} is truncated", mip_level),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BlockFormat {
+    fn new(block_width: u32, block_height: u32, block_size: u32, decode: DecodeFn) -> Self {
+        Self {
+            block_width,
+            block_height,
+            block_size,
+            decode,
+        }
+    }
+
+    /// Returns `None` if the size of the level doesn't fit in memory.
+    fn get_level_size(&self, width: u32, height: u32) -> Option<usize> {
+        let blocks_x = u64::from(width).div_ceil(u64::from(self.block_width));
+        let blocks_y = u64::from(height).div_ceil(u64::from(self.block_height));
+        let level_size = blocks_x
+            .checked_mul(blocks_y)?
+            .checked_mul(u64::from(self.block_size))?;
+        usize::try_from(level_size).ok()
+    }
+}
+//////////////////////// Fns ///////////////////////
+/// Returns true if the file has to be loaded as a `CompressedTextureSource`.
+pub fn is_compressed_texture_file(filepath: &Path) -> bool {
+    has_extension(filepath, "ktx2") || has_extension(filepath, "dds")
+}
+
+fn has_extension(filepath: &Path, expected: &str) -> bool {
+    match filepath
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some(extension) => extension.eq_ignore_ascii_case(expected),
+        None => false,
+    }
+}
+
+fn get_mip_extent(extent: u32, mip_level: u32) -> u32 {
+    (extent >> mip_level).max(1)
+}
+
+/// The number of mip levels down to 1x1, which is floor(log2(max(width, height))) + 1.
+fn get_full_mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+fn get_block_format(format: vk::Format) -> Option<BlockFormat> {
+    use texture2ddecoder as decoder;
+    let block_format = match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK => {
+            BlockFormat::new(4, 4, 8, decoder::decode_bc1)
+        }
+        vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK => {
+            BlockFormat::new(4, 4, 8, decoder::decode_bc1a)
+        }
+        vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK => {
+            BlockFormat::new(4, 4, 16, decoder::decode_bc2)
+        }
+        vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK => {
+            BlockFormat::new(4, 4, 16, decoder::decode_bc3)
+        }
+        vk::Format::BC4_UNORM_BLOCK => BlockFormat::new(4, 4, 8, decoder::decode_bc4),
+        vk::Format::BC5_UNORM_BLOCK => BlockFormat::new(4, 4, 16, decoder::decode_bc5),
+        vk::Format::BC7_UNORM_BLOCK | vk::Format::BC7_SRGB_BLOCK => {
+            BlockFormat::new(4, 4, 16, decoder::decode_bc7)
+        }
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK => {
+            BlockFormat::new(4, 4, 8, decoder::decode_etc2_rgb)
+        }
+        vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK => {
+            BlockFormat::new(4, 4, 8, decoder::decode_etc2_rgba1)
+        }
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => {
+            BlockFormat::new(4, 4, 16, decoder::decode_etc2_rgba8)
+        }
+        vk::Format::ASTC_4X4_UNORM_BLOCK | vk::Format::ASTC_4X4_SRGB_BLOCK => {
+            BlockFormat::new(4, 4, 16, decoder::decode_astc_4_4)
+        }
+        vk::Format::ASTC_5X4_UNORM_BLOCK | vk::Format::ASTC_5X4_SRGB_BLOCK => {
+            BlockFormat::new(5, 4, 16, decoder::decode_astc_5_4)
+        }
+        vk::Format::ASTC_5X5_UNORM_BLOCK | vk::Format::ASTC_5X5_SRGB_BLOCK => {
+            BlockFormat::new(5, 5, 16, decoder::decode_astc_5_5)
+        }
+        vk::Format::ASTC_6X5_UNORM_BLOCK | vk::Format::ASTC_6X5_SRGB_BLOCK => {
+            BlockFormat::new(6, 5, 16, decoder::decode_astc_6_5)
+        }
+        vk::Format::ASTC_6X6_UNORM_BLOCK | vk::Format::ASTC_6X6_SRGB_BLOCK => {
+            BlockFormat::new(6, 6, 16, decoder::decode_astc_6_6)
+        }
+        vk::Format::ASTC_8X5_UNORM_BLOCK | vk::Format::ASTC_8X5_SRGB_BLOCK => {
+            BlockFormat::new(8, 5, 16, decoder::decode_astc_8_5)
+        }
+        vk::Format::ASTC_8X6_UNORM_BLOCK | vk::Format::ASTC_8X6_SRGB_BLOCK => {
+            BlockFormat::new(8, 6, 16, decoder::decode_astc_8_6)
+        }
+        vk::Format::ASTC_8X8_UNORM_BLOCK | vk::Format::ASTC_8X8_SRGB_BLOCK => {
+            BlockFormat::new(8, 8, 16, decoder::decode_astc_8_8)
+        }
+        vk::Format::ASTC_10X5_UNORM_BLOCK | vk::Format::ASTC_10X5_SRGB_BLOCK => {
+            BlockFormat::new(10, 5, 16, decoder::decode_astc_10_5)
+        }
+        vk::Format::ASTC_10X6_UNORM_BLOCK | vk::Format::ASTC_10X6_SRGB_BLOCK => {
+            BlockFormat::new(10, 6, 16, decoder::decode_astc_10_6)
+        }
+        vk::Format::ASTC_10X8_UNORM_BLOCK | vk::Format::ASTC_10X8_SRGB_BLOCK => {
+            BlockFormat::new(10, 8, 16, decoder::decode_astc_10_8)
+        }
+        vk::Format::ASTC_10X10_UNORM_BLOCK | vk::Format::ASTC_10X10_SRGB_BLOCK => {
+            BlockFormat::new(10, 10, 16, decoder::decode_astc_10_10)
+        }
+        vk::Format::ASTC_12X10_UNORM_BLOCK | vk::Format::ASTC_12X10_SRGB_BLOCK => {
+            BlockFormat::new(12, 10, 16, decoder::decode_astc_12_10)
+        }
+        vk::Format::ASTC_12X12_UNORM_BLOCK | vk::Format::ASTC_12X12_SRGB_BLOCK => {
+            BlockFormat::new(12, 12, 16, decoder::decode_astc_12_12)
+        }
+        _ => return None,
+    };
+    Some(block_format)
+}
+
+fn is_srgb(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::BC1_RGB_SRGB_BLOCK
+            | vk::Format::BC1_RGBA_SRGB_BLOCK
+            | vk::Format::BC2_SRGB_BLOCK
+            | vk::Format::BC3_SRGB_BLOCK
+            | vk::Format::BC7_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+            | vk::Format::ASTC_4X4_SRGB_BLOCK
+            | vk::Format::ASTC_5X4_SRGB_BLOCK
+            | vk::Format::ASTC_5X5_SRGB_BLOCK
+            | vk::Format::ASTC_6X5_SRGB_BLOCK
+            | vk::Format::ASTC_6X6_SRGB_BLOCK
+            | vk::Format::ASTC_8X5_SRGB_BLOCK
+            | vk::Format::ASTC_8X6_SRGB_BLOCK
+            | vk::Format::ASTC_8X8_SRGB_BLOCK
+            | vk::Format::ASTC_10X5_SRGB_BLOCK
+            | vk::Format::ASTC_10X6_SRGB_BLOCK
+            | vk::Format::ASTC_10X8_SRGB_BLOCK
+            | vk::Format::ASTC_10X10_SRGB_BLOCK
+            | vk::Format::ASTC_12X10_SRGB_BLOCK
+            | vk::Format::ASTC_12X12_SRGB_BLOCK
+    )
+}
+
+fn get_dxgi_vk_format(dxgi_format: DxgiFormat) -> Option<vk::Format> {
+    match dxgi_format {
+        DxgiFormat::BC1_UNorm => Some(vk::Format::BC1_RGBA_UNORM_BLOCK),
+        DxgiFormat::BC1_UNorm_sRGB => Some(vk::Format::BC1_RGBA_SRGB_BLOCK),
+        DxgiFormat::BC2_UNorm => Some(vk::Format::BC2_UNORM_BLOCK),
+        DxgiFormat::BC2_UNorm_sRGB => Some(vk::Format::BC2_SRGB_BLOCK),
+        DxgiFormat::BC3_UNorm => Some(vk::Format::BC3_UNORM_BLOCK),
+        DxgiFormat::BC3_UNorm_sRGB => Some(vk::Format::BC3_SRGB_BLOCK),
+        DxgiFormat::BC4_UNorm => Some(vk::Format::BC4_UNORM_BLOCK),
+        DxgiFormat::BC5_UNorm => Some(vk::Format::BC5_UNORM_BLOCK),
+        DxgiFormat::BC7_UNorm => Some(vk::Format::BC7_UNORM_BLOCK),
+        DxgiFormat::BC7_UNorm_sRGB => Some(vk::Format::BC7_SRGB_BLOCK),
+        _ => None,
+    }
+}
+
+/// The legacy dds header has no srgb formats, so the textures are read in the requested color space.
+fn get_d3d_vk_format(d3d_format: D3DFormat, color_space: ColorSpace) -> Option<vk::Format> {
+    match (d3d_format, color_space) {
+        (D3DFormat::DXT1, ColorSpace::Srgb) => Some(vk::Format::BC1_RGBA_SRGB_BLOCK),
+        (D3DFormat::DXT1, ColorSpace::Linear) => Some(vk::Format::BC1_RGBA_UNORM_BLOCK),
+        (D3DFormat::DXT3, ColorSpace::Srgb) => Some(vk::Format::BC2_SRGB_BLOCK),
+        (D3DFormat::DXT3, ColorSpace::Linear) => Some(vk::Format::BC2_UNORM_BLOCK),
+        (D3DFormat::DXT5, ColorSpace::Srgb) => Some(vk::Format::BC3_SRGB_BLOCK),
+        (D3DFormat::DXT5, ColorSpace::Linear) => Some(vk::Format::BC3_UNORM_BLOCK),
+        _ => None,
+    }
+}
+
+fn malformed<S: Into<String>>(path: &Path, reason: S) -> VulkanError {
+    VulkanError::TextureMalformed {
+        file: path.to_path_buf(),
+        reason: reason.into(),
+    }
+}
+
+fn unsupported<S: Into<String>>(path: &Path, reason: S) -> VulkanError {
+    VulkanError::TextureFormatUnsupported {
+        file: path.to_path_buf(),
+        reason: reason.into(),
+    }
+}
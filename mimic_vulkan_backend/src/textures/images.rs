@@ -1,24 +1,38 @@
 use crate::{
     buffers::{
         buffer::Buffer,
-        memory::{self, MemoryCopyable},
+        memory::{
+            self,
+            allocator::{Allocation, MemoryAllocator},
+            MemoryCopyable,
+        },
     },
     depth::helpers,
     devices::queues::QueueMap,
-    drawing::command_buffers::{begin_single_time_commands, end_single_time_commands},
+    drawing::{
+        command_buffers::{begin_single_time_commands, end_single_time_commands},
+        upload_context::UploadContext,
+    },
+    textures::compressed_texture::CompressedTextureSource,
     util::result::{Result, VulkanError},
 };
 
 use ash::{
     vk,
 };
-use mimic_common::texture::TextureSource;
+use log::warn;
+use mimic_common::{
+    cubemap::CubemapSource,
+    texture::{ColorSpace, PixelFormat, TextureSource},
+};
 use std::{cmp::max, convert::TryFrom, f32};
 //////////////////////// Enums ///////////////////////
 #[derive(Debug)]
 pub enum MipmapParam {
     NoMipmap,
     UseRuntimeMipmap,
+    /// the mip levels are loaded from the texture file
+    UsePrebuiltMipmap(u32),
 }
 //////////////////////// Structs ///////////////////////
 #[derive(Default)]
@@ -26,8 +40,11 @@ pub struct Image {
     pub image: vk::Image,
     pub width: u32,
     pub height: u32,
-    pub memory: vk::DeviceMemory,
+    pub allocation: Allocation,
     pub mip_levels: u32,
+    /// 6 for cubemaps, where each layer is one face
+    pub array_layers: u32,
+    pub view_type: vk::ImageViewType,
 }
 
 #[derive(Default)]
@@ -37,7 +54,30 @@ pub struct TextureImage {
     pub view: vk::ImageView,
     pub sampler: vk::Sampler,
 }
+
+/// The pixels of a texture or of the six faces of a cubemap, before they are uploaded.
+struct UncompressedPixels<'a> {
+    pixels: &'a [u8],
+    pixel_format: PixelFormat,
+    color_space: ColorSpace,
+    width: u32,
+    height: u32,
+    is_cubemap: bool,
+}
 //////////////////////// Impls ///////////////////////
+impl MipmapParam {
+    fn get_mip_levels(&self, width: u32, height: u32) -> u32 {
+        match self {
+            // number of mip level = how many times we can scale the image down by a half
+            MipmapParam::UseRuntimeMipmap => {
+                ((max(width, height) as f32).log2().floor() as u32) + 1
+            }
+            MipmapParam::NoMipmap => 1,
+            MipmapParam::UsePrebuiltMipmap(mip_levels) => *mip_levels,
+        }
+    }
+}
+
 impl Image {
     pub fn new(
         width: u32,
@@ -48,18 +88,9 @@ impl Image {
         tiling: vk::ImageTiling,
         usage: vk::ImageUsageFlags,
         memory_properties: vk::MemoryPropertyFlags,
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
         logical_device: &ash::Device,
     ) -> Result<Self> {
-        // number of mip level = how many times we can scale the image down by a half
-        let mip_levels = match mipmap_param {
-            MipmapParam::UseRuntimeMipmap => {
-                ((max(width, height) as f32).log2().floor() as u32) + 1
-            }
-            MipmapParam::NoMipmap => 1,
-        };
-
         let image_create_info = vk::ImageCreateInfo {
             image_type: vk::ImageType::TYPE_2D,
             extent: vk::Extent3D::builder()
@@ -67,7 +98,7 @@ impl Image {
                 .width(width)
                 .depth(1)
                 .build(),
-            mip_levels,
+            mip_levels: mipmap_param.get_mip_levels(width, height),
             array_layers: 1,
             format,
             tiling,
@@ -78,81 +109,92 @@ impl Image {
             ..Default::default()
         };
 
-        let vulkan_image = unsafe { logical_device.create_image(&image_create_info, None)? };
-
-        let memory_requirements =
-            unsafe { logical_device.get_image_memory_requirements(vulkan_image) };
-
-        let memory_type_index = memory::find_memory_type(
-            instance,
-            physical_device,
-            memory_requirements.memory_type_bits,
+        Self::create(
+            &image_create_info,
+            vk::ImageViewType::TYPE_2D,
             memory_properties,
-        )?;
+            allocator,
+            logical_device,
+        )
+    }
 
-        let memory_alloc_info = vk::MemoryAllocateInfo {
-            allocation_size: memory_requirements.size,
-            memory_type_index,
+    /// Creates a device local image with six square layers of `size` pixels, which is viewed as a cube.
+    pub fn new_cubemap(
+        size: u32,
+        mipmap_param: MipmapParam,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) -> Result<Self> {
+        let image_create_info = vk::ImageCreateInfo {
+            flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            image_type: vk::ImageType::TYPE_2D,
+            extent: vk::Extent3D::builder()
+                .height(size)
+                .width(size)
+                .depth(1)
+                .build(),
+            mip_levels: mipmap_param.get_mip_levels(size, size),
+            array_layers: 6,
+            format,
+            tiling: vk::ImageTiling::OPTIMAL,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            samples: vk::SampleCountFlags::TYPE_1,
             ..Default::default()
         };
 
-        let image_device_memory = unsafe {
-            let image_device_memory = logical_device.allocate_memory(&memory_alloc_info, None)?;
-            logical_device.bind_image_memory(vulkan_image, image_device_memory, 0)?;
-            image_device_memory
-        };
-
-        Ok(Self {
-            image: vulkan_image,
-            width,
-            height,
-            memory: image_device_memory,
-            mip_levels,
-        })
+        Self::create(
+            &image_create_info,
+            vk::ImageViewType::CUBE,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            allocator,
+            logical_device,
+        )
     }
 
-    pub fn copy_buffer_to_image(
-        &mut self,
-        buffer: &Buffer,
+    fn create(
+        image_create_info: &vk::ImageCreateInfo,
+        view_type: vk::ImageViewType,
+        memory_properties: vk::MemoryPropertyFlags,
+        allocator: &mut MemoryAllocator,
         logical_device: &ash::Device,
-        command_pool: vk::CommandPool,
-        queues: &QueueMap,
-    ) -> Result<()> {
-        let command_buffer = begin_single_time_commands(logical_device, command_pool)?;
+    ) -> Result<Self> {
+        let vulkan_image = unsafe { logical_device.create_image(image_create_info, None)? };
 
-        let buffer_image_copy = vk::BufferImageCopy {
-            buffer_offset: 0,
-            // 0 for row_length and height just says pixels are tightly packed
-            buffer_row_length: 0,
-            buffer_image_height: 0,
-            image_subresource: vk::ImageSubresourceLayers::builder()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .mip_level(0)
-                .base_array_layer(0)
-                .layer_count(1)
-                .build(),
-            image_offset: vk::Offset3D::builder().x(0).y(0).z(0).build(),
-            image_extent: vk::Extent3D::builder()
-                .width(self.width)
-                .height(self.height)
-                .depth(1)
-                .build(),
+        let allocation = match allocator.allocate_for_image(
+            logical_device,
+            vulkan_image,
+            image_create_info.tiling,
+            memory_properties,
+        ) {
+            Ok(allocation) => allocation,
+            Err(error) => {
+                unsafe { logical_device.destroy_image(vulkan_image, None) };
+                return Err(error);
+            }
         };
 
-        // specify an array of regions to copy
-        let regions = [buffer_image_copy];
-
-        unsafe {
-            logical_device.cmd_copy_buffer_to_image(
-                command_buffer,
-                buffer.buffer,
-                self.image,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                &regions,
-            );
-        }
+        Ok(Self {
+            image: vulkan_image,
+            width: image_create_info.extent.width,
+            height: image_create_info.extent.height,
+            allocation,
+            mip_levels: image_create_info.mip_levels,
+            array_layers: image_create_info.array_layers,
+            view_type,
+        })
+    }
 
-        end_single_time_commands(command_buffer, logical_device, queues, command_pool)
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy the image and free its memory.
+    /// It must be called with valid vulkan state.
+    pub unsafe fn destroy(&self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
+        logical_device.destroy_image(self.image, None);
+        allocator.free(&self.allocation, logical_device);
     }
 
     pub fn transition_image_layout(
@@ -221,7 +263,7 @@ impl Image {
                 .base_mip_level(0)
                 .level_count(self.mip_levels)
                 .base_array_layer(0)
-                .layer_count(1)
+                .layer_count(self.array_layers)
                 .build(),
             src_access_mask,
             dst_access_mask,
@@ -255,7 +297,7 @@ impl Image {
     ) -> Result<vk::ImageView> {
         let image_view_create_info = vk::ImageViewCreateInfo {
             image: self.image,
-            view_type: vk::ImageViewType::TYPE_2D,
+            view_type: self.view_type,
             format,
             components: vk::ComponentMapping::builder()
                 .r(vk::ComponentSwizzle::IDENTITY)
@@ -268,7 +310,7 @@ impl Image {
                 .base_mip_level(0)
                 .level_count(self.mip_levels)
                 .base_array_layer(0)
-                .layer_count(1)
+                .layer_count(self.array_layers)
                 .build(),
             ..Default::default()
         };
@@ -292,116 +334,340 @@ impl TextureImage {
         texture_source: Box<dyn TextureSource>,
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
         logical_device: &ash::Device,
-        command_pool: vk::CommandPool,
-        queues: &QueueMap,
+        upload_context: &mut UploadContext,
         physical_device_properties: &vk::PhysicalDeviceProperties,
     ) -> Result<Self> {
-        let staging_buffer = Buffer::new(
+        let (image, view, sampler) = Self::upload_pixels(
+            &UncompressedPixels {
+                pixels: texture_source.get_pixels(),
+                pixel_format: texture_source.get_pixel_format(),
+                color_space: texture_source.get_color_space(),
+                width: texture_source.get_width(),
+                height: texture_source.get_height(),
+                is_cubemap: false,
+            },
             instance,
             physical_device,
+            allocator,
             logical_device,
-            texture_source.get_image_size() as vk::DeviceSize,
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            upload_context,
+            physical_device_properties,
         )?;
 
-        unsafe {
-            memory::fill_buffer(logical_device, staging_buffer.memory, texture_source.get_pixels())?;
-        }
+        Ok(Self {
+            texture_source: Some(texture_source),
+            image,
+            view,
+            sampler,
+        })
+    }
 
-        let mut texture_image = Image::new(
-            texture_source.get_width(),
-            texture_source.get_height(),
-            MipmapParam::UseRuntimeMipmap,
-            vk::SampleCountFlags::TYPE_1,
-            vk::Format::R8G8B8A8_SRGB,
-            vk::ImageTiling::OPTIMAL,
-            // the image has the usage
-            // 1) transfer source for Blit operations to create mip levels
-            // 2) transfer dest for copying staging buffer into it
-            // 3) sampled for usage in a sampler in a shader
-            vk::ImageUsageFlags::TRANSFER_SRC
-                | vk::ImageUsageFlags::TRANSFER_DST
-                | vk::ImageUsageFlags::SAMPLED,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    /// Uploads the six faces of the `cubemap_source` into the layers of a cube compatible image, which is viewed as a cube.
+    pub fn from_cubemap(
+        cubemap_source: &CubemapSource,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        upload_context: &mut UploadContext,
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<Self> {
+        let (image, view, sampler) = Self::upload_pixels(
+            &UncompressedPixels {
+                pixels: cubemap_source.get_pixels(),
+                pixel_format: cubemap_source.get_pixel_format(),
+                color_space: cubemap_source.get_color_space(),
+                width: cubemap_source.get_size(),
+                height: cubemap_source.get_size(),
+                is_cubemap: true,
+            },
             instance,
             physical_device,
+            allocator,
             logical_device,
+            upload_context,
+            physical_device_properties,
         )?;
 
-        texture_image.transition_image_layout(
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::Format::R8G8B8A8_SRGB,
+        Ok(Self {
+            texture_source: None,
+            image,
+            view,
+            sampler,
+        })
+    }
+
+    /// Uploads the block compressed data and the mip levels of the `texture_source` as they are.
+    /// If the device can't sample the format of the texture, then it is decompressed to RGBA8 on the cpu first.
+    pub fn from_compressed(
+        texture_source: CompressedTextureSource,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        upload_context: &mut UploadContext,
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<Self> {
+        let is_format_supported = get_format_features(
+            instance,
+            physical_device,
+            texture_source.format,
+        )
+        .contains(
+            vk::FormatFeatureFlags::SAMPLED_IMAGE
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+        );
+        let decompressed_levels;
+        let (format, mip_levels) = if is_format_supported {
+            (texture_source.format, &texture_source.mip_levels)
+        } else {
+            warn!(
+                "Format {:?} of texture {:?} is not supported by the device. Decompressing it on the cpu",
+                texture_source.format, texture_source.path
+            );
+            decompressed_levels = texture_source.decompress()?;
+            (texture_source.get_decompressed_format(), &decompressed_levels)
+        };
+
+        // all mip levels are copied from a single staging buffer, one after another
+        let mip_level_count = u32::try_from(mip_levels.len())?;
+        let mut pixels = Vec::new();
+        let mut mip_level_offsets = Vec::with_capacity(mip_levels.len());
+        for level_data in mip_levels {
+            mip_level_offsets.push(vk::DeviceSize::try_from(pixels.len())?);
+            pixels.extend_from_slice(level_data);
+        }
+
+        let staging_buffer = Buffer::new(
+            allocator,
             logical_device,
-            command_pool,
-            queues,
+            vk::DeviceSize::try_from(pixels.len())?,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
 
-        texture_image.copy_buffer_to_image(
-            &staging_buffer,
+        // the staging buffer is owned by us until it is handed over to the upload context
+        if let Err(error) = unsafe { memory::fill_buffer(&staging_buffer.allocation, &pixels) } {
+            unsafe { staging_buffer.destroy(allocator, logical_device) };
+            return Err(error);
+        }
+
+        let texture_image = match Image::new(
+            texture_source.width,
+            texture_source.height,
+            MipmapParam::UsePrebuiltMipmap(mip_level_count),
+            vk::SampleCountFlags::TYPE_1,
+            format,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            allocator,
             logical_device,
-            command_pool,
-            queues,
-        )?;
+        ) {
+            Ok(texture_image) => texture_image,
+            Err(error) => {
+                unsafe { staging_buffer.destroy(allocator, logical_device) };
+                return Err(error);
+            }
+        };
 
-        // as part of generating mipmaps all the mip levels transition to the shader read optimal layout
-        Self::generate_mipmaps(
+        upload_context.copy_mip_levels_to_image(
+            staging_buffer,
             &texture_image,
-            vk::Format::R8G8B8A8_SRGB,
-            instance,
+            &mip_level_offsets,
             logical_device,
-            physical_device,
-            command_pool,
-            queues,
         )?;
 
-        unsafe {
-            logical_device.destroy_buffer(staging_buffer.buffer, None);
-            logical_device.free_memory(staging_buffer.memory, None);
-        }
-
-        let view = texture_image.create_image_view(
-            vk::Format::R8G8B8A8_SRGB,
-            vk::ImageAspectFlags::COLOR,
+        Self::transition_to_shader_read(
+            &texture_image,
             logical_device,
-        )?;
+            upload_context.get_graphics_command_buffer(logical_device)?,
+        );
+
+        let view =
+            texture_image.create_image_view(format, vk::ImageAspectFlags::COLOR, logical_device)?;
 
         let sampler = Self::create_texture_sampler(
             logical_device,
             physical_device_properties,
             texture_image.mip_levels,
+            vk::Filter::LINEAR,
         )?;
 
         Ok(Self {
-            texture_source: Some(texture_source),
+            texture_source: None,
             image: texture_image,
             view,
             sampler,
         })
     }
 
+    fn upload_pixels(
+        pixels: &UncompressedPixels,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        upload_context: &mut UploadContext,
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<(Image, vk::ImageView, vk::Sampler)> {
+        let pixel_format = pixels.pixel_format;
+        let color_space = pixels.color_space;
+        let mut format = get_texture_format(pixel_format, color_space);
+        let mut format_features = get_format_features(instance, physical_device, format);
+        // sampling the float formats is always supported, but sampling the srgb formats with less than four channels is optional
+        let expanded_pixels = if pixel_format.is_float()
+            || format_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+        {
+            None
+        } else {
+            warn!(
+                "Format {:?} is not supported by the device. Expanding the texture to RGBA8",
+                format
+            );
+            format = get_texture_format(PixelFormat::Rgba8, color_space);
+            format_features = get_format_features(instance, physical_device, format);
+            Some(expand_to_rgba8(
+                pixels.pixels,
+                pixel_format.get_bytes_per_pixel(),
+            ))
+        };
+        let (width, height, is_cubemap) = (pixels.width, pixels.height, pixels.is_cubemap);
+        let pixels = expanded_pixels.as_deref().unwrap_or(pixels.pixels);
+
+        // the mip levels are generated with linear blits, which many devices don't support for RGBA32F
+        let mipmap_param = if format_features.contains(
+            vk::FormatFeatureFlags::BLIT_SRC
+                | vk::FormatFeatureFlags::BLIT_DST
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+        ) {
+            MipmapParam::UseRuntimeMipmap
+        } else {
+            MipmapParam::NoMipmap
+        };
+        let filter = if format_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+        {
+            vk::Filter::LINEAR
+        } else {
+            vk::Filter::NEAREST
+        };
+
+        let staging_buffer = Buffer::new(
+            allocator,
+            logical_device,
+            vk::DeviceSize::try_from(pixels.len())?,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        // the staging buffer is owned by us until it is handed over to the upload context
+        if let Err(error) = unsafe { memory::fill_buffer(&staging_buffer.allocation, pixels) } {
+            unsafe { staging_buffer.destroy(allocator, logical_device) };
+            return Err(error);
+        }
+
+        // the image has the usage
+        // 1) transfer source for Blit operations to create mip levels
+        // 2) transfer dest for copying staging buffer into it
+        // 3) sampled for usage in a sampler in a shader
+        let usage = vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST
+            | vk::ImageUsageFlags::SAMPLED;
+        let texture_image = if is_cubemap {
+            Image::new_cubemap(
+                width,
+                mipmap_param,
+                format,
+                usage,
+                allocator,
+                logical_device,
+            )
+        } else {
+            Image::new(
+                width,
+                height,
+                mipmap_param,
+                vk::SampleCountFlags::TYPE_1,
+                format,
+                vk::ImageTiling::OPTIMAL,
+                usage,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                allocator,
+                logical_device,
+            )
+        };
+        let texture_image = match texture_image {
+            Ok(texture_image) => texture_image,
+            Err(error) => {
+                unsafe { staging_buffer.destroy(allocator, logical_device) };
+                return Err(error);
+            }
+        };
+
+        // the upload context frees the staging buffer once the copy is done
+        upload_context.copy_to_image(staging_buffer, &texture_image, logical_device)?;
+
+        // blitting needs a graphics queue, so the mipmaps are generated after the copy is handed over to it
+        // as part of generating mipmaps all the mip levels transition to the shader read optimal layout
+        if texture_image.mip_levels > 1 {
+            Self::generate_mipmaps(
+                &texture_image,
+                format,
+                instance,
+                logical_device,
+                physical_device,
+                upload_context.get_graphics_command_buffer(logical_device)?,
+            )?;
+        } else {
+            Self::transition_to_shader_read(
+                &texture_image,
+                logical_device,
+                upload_context.get_graphics_command_buffer(logical_device)?,
+            );
+        }
+
+        let view =
+            texture_image.create_image_view(format, vk::ImageAspectFlags::COLOR, logical_device)?;
+
+        let sampler = Self::create_texture_sampler(
+            logical_device,
+            physical_device_properties,
+            texture_image.mip_levels,
+            filter,
+        )?;
+
+        Ok((texture_image, view, sampler))
+    }
+
     /// # Safety
     ///
     /// This function calls unsafe, low-level vulkan api functions to destroy samplers, images and free memory.
     /// It must be called with valid vulkan state in self.
-    pub unsafe fn cleanup(self, logical_device: &ash::Device) {
+    pub unsafe fn cleanup(self, allocator: &mut MemoryAllocator, logical_device: &ash::Device) {
         logical_device.destroy_sampler(self.sampler, None);
         logical_device.destroy_image_view(self.view, None);
 
-        logical_device.destroy_image(self.image.image, None);
-        logical_device.free_memory(self.image.memory, None);
+        self.image.destroy(allocator, logical_device);
     }
 
     pub fn create_texture_sampler(
         logical_device: &ash::Device,
         physical_device_properties: &vk::PhysicalDeviceProperties,
         mip_levels: u32,
+        filter: vk::Filter,
     ) -> Result<vk::Sampler> {
+        // formats without linear filtering support also can't be filtered linearly between mip levels
+        let mipmap_mode = if filter == vk::Filter::LINEAR {
+            vk::SamplerMipmapMode::LINEAR
+        } else {
+            vk::SamplerMipmapMode::NEAREST
+        };
         let sampler_create_info = vk::SamplerCreateInfo {
-            mag_filter: vk::Filter::LINEAR,
-            min_filter: vk::Filter::LINEAR,
+            mag_filter: filter,
+            min_filter: filter,
             address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
             address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
             address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
@@ -411,7 +677,7 @@ impl TextureImage {
             unnormalized_coordinates: vk::FALSE,
             compare_enable: vk::FALSE,
             compare_op: vk::CompareOp::ALWAYS,
-            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            mipmap_mode,
             max_lod: mip_levels as f32,
             min_lod: 0.0,
             mip_lod_bias: 0.0,
@@ -423,6 +689,43 @@ impl TextureImage {
         Ok(sampler)
     }
 
+    /// Moves all mip levels from the layout they were copied in to the layout they are sampled in.
+    fn transition_to_shader_read(
+        image: &Image,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let memory_barrier = vk::ImageMemoryBarrier {
+            image: image.image,
+            old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            subresource_range: vk::ImageSubresourceRange::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(0)
+                .level_count(image.mip_levels)
+                .base_array_layer(0)
+                .layer_count(image.array_layers)
+                .build(),
+            ..Default::default()
+        };
+
+        unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[memory_barrier],
+            );
+        }
+    }
+
     // TODO: that runtime generation of mipmaps is worse than reading them from the file
     fn generate_mipmaps(
         image: &Image,
@@ -430,8 +733,7 @@ impl TextureImage {
         instance: &ash::Instance,
         logical_device: &ash::Device,
         physical_device: vk::PhysicalDevice,
-        command_pool: vk::CommandPool,
-        queues: &QueueMap,
+        command_buffer: vk::CommandBuffer,
     ) -> Result<()> {
         // before doing anything, we need to check if the format supports linear blitting
         let physical_device_properties =
@@ -443,8 +745,6 @@ impl TextureImage {
             return Err(VulkanError::ImageLinearBlittingNotSupported);
         }
 
-        let command_buffer = begin_single_time_commands(logical_device, command_pool)?;
-
         let mut memory_barrier = vk::ImageMemoryBarrier {
             image: image.image,
             src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
@@ -452,7 +752,7 @@ impl TextureImage {
             subresource_range: vk::ImageSubresourceRange::builder()
                 .aspect_mask(vk::ImageAspectFlags::COLOR)
                 .base_array_layer(0)
-                .layer_count(1)
+                .layer_count(image.array_layers)
                 .level_count(1)
                 .build(),
             ..Default::default()
@@ -508,7 +808,7 @@ impl TextureImage {
                 .aspect_mask(vk::ImageAspectFlags::COLOR)
                 .mip_level(prev_mip_level)
                 .base_array_layer(0)
-                .layer_count(1)
+                .layer_count(image.array_layers)
                 .build();
             // the two corners of the space to blit to
             image_blit_builder.dst_offsets = [
@@ -523,7 +823,7 @@ impl TextureImage {
                 .aspect_mask(vk::ImageAspectFlags::COLOR)
                 .mip_level(cur_mip_level)
                 .base_array_layer(0)
-                .layer_count(1)
+                .layer_count(image.array_layers)
                 .build();
 
             // do the mip -> mip downscale blit
@@ -582,6 +882,47 @@ impl TextureImage {
             );
         }
 
-        end_single_time_commands(command_buffer, logical_device, queues, command_pool)
+        Ok(())
+    }
+}
+//////////////////////// Fns ///////////////////////
+/// The vulkan format of the pixels of a `TextureSource`. The float formats have no srgb variant.
+pub fn get_texture_format(pixel_format: PixelFormat, color_space: ColorSpace) -> vk::Format {
+    match (pixel_format, color_space) {
+        (PixelFormat::R8, ColorSpace::Srgb) => vk::Format::R8_SRGB,
+        (PixelFormat::R8, ColorSpace::Linear) => vk::Format::R8_UNORM,
+        (PixelFormat::Rg8, ColorSpace::Srgb) => vk::Format::R8G8_SRGB,
+        (PixelFormat::Rg8, ColorSpace::Linear) => vk::Format::R8G8_UNORM,
+        (PixelFormat::Rgba8, ColorSpace::Srgb) => vk::Format::R8G8B8A8_SRGB,
+        (PixelFormat::Rgba8, ColorSpace::Linear) => vk::Format::R8G8B8A8_UNORM,
+        (PixelFormat::Rgba16F, _) => vk::Format::R16G16B16A16_SFLOAT,
+        (PixelFormat::Rgba32F, _) => vk::Format::R32G32B32A32_SFLOAT,
     }
 }
+
+fn get_format_features(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    format: vk::Format,
+) -> vk::FormatFeatureFlags {
+    unsafe {
+        instance
+            .get_physical_device_format_properties(physical_device, format)
+            .optimal_tiling_features
+    }
+}
+
+/// Pads the one and two channel 8 bit `pixels` to RGBA8 the same way the shaders read them, with blue as 0 and alpha as 1.
+fn expand_to_rgba8(pixels: &[u8], bytes_per_pixel: u32) -> Vec<u8> {
+    pixels
+        .chunks_exact(bytes_per_pixel as usize)
+        .flat_map(|pixel| {
+            [
+                pixel[0],
+                pixel.get(1).copied().unwrap_or(0),
+                0,
+                u8::MAX,
+            ]
+        })
+        .collect()
+}
@@ -1,2 +1,3 @@
+pub mod bindless;
 pub mod buffers;
 pub mod descriptors;
@@ -0,0 +1,223 @@
+use crate::{
+    backend::assets::TextureHandle,
+    util::result::{Result, VulkanError},
+};
+
+use ash::vk;
+use std::{collections::HashMap, convert::TryFrom, ptr};
+//////////////////////// Consts ///////////////////////
+/// How many textures the array can hold at most, if the device supports that many
+const MAX_BINDLESS_TEXTURES: u32 = 4096;
+/// the descriptor limits of the device are shared with the samplers that the pipelines declare in set 1
+const SET_1_RESERVED_SAMPLERS: u32 = 16;
+//////////////////////// Structs ///////////////////////
+/// The array of combined image samplers which is bound as set 2 of every pipeline when bindless textures are enabled.
+/// Shaders declare it as `layout(set = 2, binding = 0) uniform sampler2D textures[];` and index it with the
+/// texture id that is pushed at `BINDLESS_TEXTURE_ID_OFFSET` of the push constants of each draw.
+pub struct BindlessTextures {
+    pub layout: vk::DescriptorSetLayout,
+    pub descriptor_set: vk::DescriptorSet,
+    descriptor_pool: vk::DescriptorPool,
+    capacity: u32,
+    /// the texture of every texture id. removed textures leave their descriptor behind, which is never read again
+    slots: Vec<Option<BindlessSlot>>,
+    /// the texture ids of the image views in the array
+    texture_ids: HashMap<vk::ImageView, u32>,
+    free_texture_ids: Vec<u32>,
+}
+
+struct BindlessSlot {
+    // the texture is held so that it stays loaded for as long as the array refers to it
+    texture: TextureHandle,
+    user_count: usize,
+}
+//////////////////////// Impls ///////////////////////
+impl BindlessTextures {
+    /// The logical device has to be created with the features of `get_descriptor_indexing_features`.
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        logical_device: &ash::Device,
+    ) -> Result<Self> {
+        let capacity = Self::get_capacity(instance, physical_device);
+
+        // textures are written into the set while frames which don't read them are in flight,
+        // and the slots that aren't written or were freed again are never read
+        let binding_flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING];
+        let binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo {
+            binding_count: u32::try_from(binding_flags.len())?,
+            p_binding_flags: binding_flags.as_ptr(),
+            ..Default::default()
+        };
+        let layout_binding = vk::DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: capacity,
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            ..Default::default()
+        };
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            p_next: &binding_flags_info as *const vk::DescriptorSetLayoutBindingFlagsCreateInfo
+                as *const std::ffi::c_void,
+            flags: vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+            binding_count: 1,
+            p_bindings: &layout_binding,
+            ..Default::default()
+        };
+        let layout = unsafe { logical_device.create_descriptor_set_layout(&layout_info, None)? };
+
+        let pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: capacity,
+        };
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            flags: vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+            pool_size_count: 1,
+            p_pool_sizes: &pool_size,
+            max_sets: 1,
+            ..Default::default()
+        };
+        let descriptor_pool =
+            match unsafe { logical_device.create_descriptor_pool(&pool_info, None) } {
+                Ok(descriptor_pool) => descriptor_pool,
+                Err(error) => {
+                    unsafe { logical_device.destroy_descriptor_set_layout(layout, None) };
+                    return Err(VulkanError::from(error));
+                }
+            };
+
+        let alloc_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: 1,
+            p_set_layouts: &layout,
+            ..Default::default()
+        };
+        let descriptor_set = match unsafe { logical_device.allocate_descriptor_sets(&alloc_info) } {
+            Ok(descriptor_sets) => descriptor_sets.first().copied(),
+            Err(error) => {
+                unsafe {
+                    logical_device.destroy_descriptor_pool(descriptor_pool, None);
+                    logical_device.destroy_descriptor_set_layout(layout, None);
+                }
+                return Err(VulkanError::from(error));
+            }
+        };
+        let descriptor_set = descriptor_set.ok_or(VulkanError::DescriptorSetNotAvailable(0))?;
+
+        Ok(Self {
+            layout,
+            descriptor_set,
+            descriptor_pool,
+            capacity,
+            slots: Vec::new(),
+            texture_ids: HashMap::new(),
+            free_texture_ids: Vec::new(),
+        })
+    }
+
+    /// Adds the `texture` to the array and returns its texture id. A texture that is already in the array keeps its id.
+    /// Every added texture has to be removed again using `remove`.
+    pub fn add(&mut self, texture: &TextureHandle, logical_device: &ash::Device) -> Result<u32> {
+        let texture_image = texture.get_texture();
+        if let Some(&texture_id) = self.texture_ids.get(&texture_image.view) {
+            if let Some(slot) = self.slots[texture_id as usize].as_mut() {
+                slot.user_count += 1;
+            }
+            return Ok(texture_id);
+        }
+
+        let texture_id = match self.free_texture_ids.pop() {
+            Some(texture_id) => texture_id,
+            None if u32::try_from(self.slots.len())? < self.capacity => {
+                self.slots.push(None);
+                u32::try_from(self.slots.len() - 1)?
+            }
+            None => return Err(VulkanError::BindlessTexturesFull(self.capacity)),
+        };
+
+        let image_info = vk::DescriptorImageInfo {
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            image_view: texture_image.view,
+            sampler: texture_image.sampler,
+        };
+        let descriptor_write = vk::WriteDescriptorSet {
+            dst_set: self.descriptor_set,
+            dst_binding: 0,
+            dst_array_element: texture_id,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            p_image_info: &image_info,
+            p_buffer_info: ptr::null(),
+            p_texel_buffer_view: ptr::null(),
+            ..Default::default()
+        };
+        unsafe {
+            logical_device.update_descriptor_sets(&[descriptor_write], &[]);
+        }
+
+        self.slots[texture_id as usize] = Some(BindlessSlot {
+            texture: texture.clone(),
+            user_count: 1,
+        });
+        self.texture_ids.insert(texture_image.view, texture_id);
+        Ok(texture_id)
+    }
+
+    /// Releases one use of the texture with `texture_id`. The id is reused once the texture has no users left.
+    /// The texture is only unloaded by the asset manager after this, so its descriptor must no longer be used by any in-flight frame.
+    pub fn remove(&mut self, texture_id: u32) {
+        let slot = match self.slots.get_mut(texture_id as usize) {
+            Some(slot) => slot,
+            None => return,
+        };
+        let is_unused = match slot.as_mut() {
+            Some(bindless_slot) => {
+                bindless_slot.user_count -= 1;
+                bindless_slot.user_count == 0
+            }
+            None => false,
+        };
+        if is_unused {
+            if let Some(bindless_slot) = slot.take() {
+                self.texture_ids
+                    .remove(&bindless_slot.texture.get_texture().view);
+                self.free_texture_ids.push(texture_id);
+            }
+        }
+    }
+
+    /// Drops the handles to all textures in the array, so that the asset manager can unload them.
+    ///
+    /// # Safety
+    ///
+    /// The set must not be used by any pipeline or in-flight frame.
+    pub unsafe fn destroy(&mut self, logical_device: &ash::Device) {
+        self.slots.clear();
+        self.texture_ids.clear();
+        self.free_texture_ids.clear();
+        // the descriptor set is freed together with the pool
+        logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+        logical_device.destroy_descriptor_set_layout(self.layout, None);
+    }
+
+    /// The size of the array is limited by how many samplers a shader stage and a set with update after bind can access on the device.
+    fn get_capacity(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> u32 {
+        let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder()
+            .push_next(&mut indexing_properties)
+            .build();
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties) };
+
+        [
+            indexing_properties.max_per_stage_descriptor_update_after_bind_samplers,
+            indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images,
+            indexing_properties.max_descriptor_set_update_after_bind_samplers,
+            indexing_properties.max_descriptor_set_update_after_bind_sampled_images,
+        ]
+        .iter()
+        .map(|limit| limit.saturating_sub(SET_1_RESERVED_SAMPLERS))
+        .fold(MAX_BINDLESS_TEXTURES, u32::min)
+    }
+}
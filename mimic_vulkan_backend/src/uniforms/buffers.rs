@@ -1,15 +1,34 @@
 use crate::buffers::buffer::Buffer;
+use crate::buffers::memory::allocator::{align_up, MemoryAllocator};
 use crate::buffers::memory::MemoryCopyable;
-use crate::presentation::swap_chain::SwapChainContainer;
-use crate::util::result::Result;
+use crate::util::result::{Result, VulkanError};
 
 use ash::vk;
+use mimic_common::uniforms::{UniformSpec, UniformUpdateInput, MAX_UNIFORM_BUFFER_SIZE};
 use std::convert::TryFrom;
+use std::ptr;
+//////////////////////// Consts ///////////////////////
+const INITIAL_FRAME_CAPACITY: vk::DeviceSize = 64 * 1024;
 //////////////////////// Traits ///////////////////////
 pub trait UniformBuffer {
     fn update_uniform(&mut self);
 }
+//////////////////////// Structs ///////////////////////
+/// One persistently mapped uniform buffer per frame in flight, which holds the uniform data of all render commands.
+/// Every render command gets a slice of the buffer of the current frame that is bound using a dynamic offset,
+/// so all render commands share the same buffer and descriptor set.
+pub struct UniformRingBuffer {
+    frames: Vec<FrameUniforms>,
+    /// the offset of every slice has to be a multiple of `minUniformBufferOffsetAlignment`
+    alignment: vk::DeviceSize,
+    descriptor_pool: vk::DescriptorPool,
+}
 
+struct FrameUniforms {
+    buffer: Buffer,
+    capacity: vk::DeviceSize,
+    descriptor_set: vk::DescriptorSet,
+}
 //////////////////////// Impls ///////////////////////
 impl<T: UniformBuffer> MemoryCopyable for [T] {
     unsafe fn copy_to_mapped_memory(&self, data_target_ptr: *mut std::ffi::c_void) {
@@ -18,29 +37,179 @@ impl<T: UniformBuffer> MemoryCopyable for [T] {
     }
 }
 
-//////////////////////// Fns ///////////////////////
-pub fn create_uniform_buffers(
-    uniform_buffer_size: usize,
-    instance: &ash::Instance,
-    physical_device: vk::PhysicalDevice,
-    logical_device: &ash::Device,
-    swap_chain_container: &SwapChainContainer,
-) -> Result<Vec<Buffer>> {
-    let size = vk::DeviceSize::try_from(uniform_buffer_size)?;
-
-    let mut uniform_buffers = Vec::new();
-
-    for _i in 0..swap_chain_container.swap_chain_images.len() {
-        let new_uniform_buf = Buffer::new(
-            instance,
-            physical_device,
+impl UniformRingBuffer {
+    pub fn new(
+        frame_count: usize,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        descriptor_layout: vk::DescriptorSetLayout,
+        physical_device_properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<Self> {
+        let frame_count_u32 = u32::try_from(frame_count)?;
+        let pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+            descriptor_count: frame_count_u32,
+        };
+        let pool_create_info = vk::DescriptorPoolCreateInfo {
+            pool_size_count: 1,
+            p_pool_sizes: &pool_size,
+            max_sets: frame_count_u32,
+            ..Default::default()
+        };
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&pool_create_info, None)? };
+
+        let layouts = vec![descriptor_layout; frame_count];
+        let descriptor_alloc_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: frame_count_u32,
+            p_set_layouts: layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_sets =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_alloc_info)? };
+
+        let mut frames = Vec::with_capacity(frame_count);
+        for descriptor_set in descriptor_sets {
+            let buffer = Self::create_frame_buffer(
+                INITIAL_FRAME_CAPACITY,
+                allocator,
+                logical_device,
+                descriptor_set,
+            )?;
+            frames.push(FrameUniforms {
+                buffer,
+                capacity: INITIAL_FRAME_CAPACITY,
+                descriptor_set,
+            });
+        }
+
+        Ok(Self {
+            frames,
+            alignment: physical_device_properties
+                .limits
+                .min_uniform_buffer_offset_alignment,
+            descriptor_pool,
+        })
+    }
+
+    /// Writes the uniform data of every spec in `uniform_specs` into the buffer of the frame with `frame_idx`.
+    /// Returns the dynamic offset of the slice of every spec, in the same order.
+    /// The buffer grows if the uniform data doesn't fit, so the frame must not be in use by the GPU.
+    pub fn write_frame(
+        &mut self,
+        frame_idx: usize,
+        frame_data_input: UniformUpdateInput,
+        uniform_specs: &[&dyn UniformSpec],
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) -> Result<Vec<u32>> {
+        let mut offsets = Vec::with_capacity(uniform_specs.len());
+        let mut head: vk::DeviceSize = 0;
+        for uniform_spec in uniform_specs {
+            offsets.push(head);
+            head += align_up(
+                vk::DeviceSize::try_from(uniform_spec.uniform_buffer_size())?,
+                self.alignment,
+            );
+        }
+        // every slice is bound with the full uniform range, so the last one needs that much room behind its offset
+        let required_capacity = offsets.last().copied().unwrap_or(0)
+            + vk::DeviceSize::try_from(MAX_UNIFORM_BUFFER_SIZE)?;
+
+        let frame = self
+            .frames
+            .get_mut(frame_idx)
+            .ok_or(VulkanError::UniformBufferNotAvailable(frame_idx))?;
+        if frame.capacity < required_capacity {
+            let capacity = required_capacity.max(frame.capacity * 2);
+            let buffer = Self::create_frame_buffer(
+                capacity,
+                allocator,
+                logical_device,
+                frame.descriptor_set,
+            )?;
+            unsafe {
+                std::mem::replace(&mut frame.buffer, buffer).destroy(allocator, logical_device);
+            }
+            frame.capacity = capacity;
+        }
+
+        let data_target_ptr = frame
+            .buffer
+            .allocation
+            .get_mapped_ptr()
+            .ok_or(VulkanError::MemoryNotHostVisible)? as *mut u8;
+        for (uniform_spec, offset) in uniform_specs.iter().zip(offsets.iter()) {
+            // the memory is HOST_COHERENT so the writes are visible to the GPU on next vk::QueueSubmit
+            let slice_ptr = unsafe { data_target_ptr.add(usize::try_from(*offset)?) };
+            uniform_spec.get_uniform_data(frame_data_input, slice_ptr as *mut std::ffi::c_void);
+        }
+
+        offsets
+            .into_iter()
+            .map(|offset| Ok(u32::try_from(offset)?))
+            .collect()
+    }
+
+    pub fn get_descriptor_set(&self, frame_idx: usize) -> Result<vk::DescriptorSet> {
+        self.frames
+            .get(frame_idx)
+            .map(|frame| frame.descriptor_set)
+            .ok_or(VulkanError::DescriptorSetNotAvailable(frame_idx))
+    }
+
+    /// # Safety
+    ///
+    /// This method calls unsafe, low-level vulkan api functions to destroy the buffers and the descriptor pool.
+    /// The buffers must not be used by any in-flight frame.
+    pub unsafe fn destroy(
+        &mut self,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+    ) {
+        for frame in self.frames.drain(..) {
+            frame.buffer.destroy(allocator, logical_device);
+        }
+        // the descriptor sets are cleared automatically when the pool is cleared
+        logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+    }
+
+    /// Creates a buffer with room for `capacity` bytes and points the `descriptor_set` of its frame at it.
+    fn create_frame_buffer(
+        capacity: vk::DeviceSize,
+        allocator: &mut MemoryAllocator,
+        logical_device: &ash::Device,
+        descriptor_set: vk::DescriptorSet,
+    ) -> Result<Buffer> {
+        let buffer = Buffer::new(
+            allocator,
             logical_device,
-            size,
+            capacity,
             vk::BufferUsageFlags::UNIFORM_BUFFER,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
         )?;
-        uniform_buffers.push(new_uniform_buf);
-    }
 
-    Ok(uniform_buffers)
+        let descriptor_buffer_info = vk::DescriptorBufferInfo {
+            buffer: buffer.buffer,
+            offset: 0,
+            range: vk::DeviceSize::try_from(MAX_UNIFORM_BUFFER_SIZE)?,
+        };
+        let descriptor_write_info = vk::WriteDescriptorSet {
+            dst_set: descriptor_set,
+            dst_binding: 0,
+            dst_array_element: 0,
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+            descriptor_count: 1,
+            p_buffer_info: &descriptor_buffer_info,
+            p_image_info: ptr::null(),
+            p_texel_buffer_view: ptr::null(),
+            ..Default::default()
+        };
+        unsafe {
+            logical_device.update_descriptor_sets(&[descriptor_write_info], &[]);
+        }
+
+        Ok(buffer)
+    }
 }
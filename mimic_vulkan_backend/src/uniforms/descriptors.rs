@@ -1,67 +1,80 @@
-use crate::buffers::buffer::Buffer;
-use crate::presentation::swap_chain::SwapChainContainer;
+use crate::graphics_pipeline::shader_reflection::ReflectedBinding;
 use crate::textures::images::TextureImage;
 use crate::util::result::{Result, VulkanError};
 
 use ash::vk;
-use mimic_common::uniforms::UniformSpec;
 use std::convert::TryFrom;
 use std::ptr;
 //////////////////////// Structs ///////////////////////
+/// The descriptor set which binds the texture sampler and material of a submesh of a render command.
+/// The uniform data is bound by the shared descriptor set of the `UniformRingBuffer` instead.
 #[derive(Debug)]
 pub struct DescriptorData {
     pub descriptor_pool: vk::DescriptorPool,
-    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+/// The layout of set 1 of a pipeline, which holds the combined image samplers and material uniform buffers that its shaders declare.
+#[derive(Debug)]
+pub struct TextureDescriptorLayout {
+    pub layout: vk::DescriptorSetLayout,
+    pub bindings: Vec<ReflectedBinding>,
 }
 //////////////////////// Impls ///////////////////////
 impl DescriptorData {
+    /// Creates the set which binds the `texture_image` to every sampler binding and the `material` to every uniform buffer binding of the `descriptor_layout`.
+    /// If the shaders don't declare any binding in set 1, then the set is null and doesn't have to be bound.
     pub fn new(
-        uniform_spec: &Box<dyn UniformSpec>,
         logical_device: &ash::Device,
-        swap_chain_container: &SwapChainContainer,
-        descriptor_layout: vk::DescriptorSetLayout,
-        uniform_buffers: &[Buffer],
+        descriptor_layout: &TextureDescriptorLayout,
         texture_image: &TextureImage,
+        material: vk::DescriptorBufferInfo,
     ) -> Result<Self> {
-        let descriptor_pool = Self::create_descriptor_pool(logical_device, swap_chain_container)?;
-        let descriptor_sets = Self::create_descriptor_sets(
-            uniform_spec,
+        if descriptor_layout.bindings.is_empty() {
+            return Ok(Self {
+                descriptor_pool: vk::DescriptorPool::null(),
+                descriptor_set: vk::DescriptorSet::null(),
+            });
+        }
+        let descriptor_pool =
+            Self::create_descriptor_pool(logical_device, &descriptor_layout.bindings)?;
+        let descriptor_set = Self::create_descriptor_set(
             logical_device,
-            swap_chain_container,
             descriptor_pool,
             descriptor_layout,
-            uniform_buffers,
             texture_image,
+            material,
         )?;
 
         Ok(Self {
             descriptor_pool,
-            descriptor_sets,
+            descriptor_set,
         })
     }
 
     fn create_descriptor_pool(
         logical_device: &ash::Device,
-        swap_chain_container: &SwapChainContainer,
+        bindings: &[ReflectedBinding],
     ) -> Result<vk::DescriptorPool> {
-        let swap_chain_img_cnt = u32::try_from(swap_chain_container.swap_chain_images.len())?;
-
-        let ubo_descriptor_pool_size = vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count: swap_chain_img_cnt,
-        };
-
-        let sampler_descriptor_pool_size = vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: swap_chain_img_cnt,
-        };
-
-        let descriptor_pool_sizes = [ubo_descriptor_pool_size, sampler_descriptor_pool_size];
+        // one pool size per descriptor type that the bindings use
+        let mut descriptor_pool_sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
+        for binding in bindings.iter() {
+            match descriptor_pool_sizes
+                .iter_mut()
+                .find(|pool_size| pool_size.ty == binding.descriptor_type)
+            {
+                Some(pool_size) => pool_size.descriptor_count += binding.descriptor_count,
+                None => descriptor_pool_sizes.push(vk::DescriptorPoolSize {
+                    ty: binding.descriptor_type,
+                    descriptor_count: binding.descriptor_count,
+                }),
+            }
+        }
 
         let create_info = vk::DescriptorPoolCreateInfo {
             pool_size_count: u32::try_from(descriptor_pool_sizes.len())?,
             p_pool_sizes: descriptor_pool_sizes.as_ptr(),
-            max_sets: swap_chain_img_cnt,
+            max_sets: 1,
             ..Default::default()
         };
 
@@ -70,105 +83,120 @@ impl DescriptorData {
         Ok(descriptor_pool)
     }
 
-    fn create_descriptor_sets(
-        uniform_spec: &Box<dyn UniformSpec>,
+    fn create_descriptor_set(
         logical_device: &ash::Device,
-        swap_chain_container: &SwapChainContainer,
         descriptor_pool: vk::DescriptorPool,
-        descriptor_layout: vk::DescriptorSetLayout,
-        uniform_buffers: &[Buffer],
+        descriptor_layout: &TextureDescriptorLayout,
         texture_image: &TextureImage,
-    ) -> Result<Vec<vk::DescriptorSet>> {
-        let layouts = vec![descriptor_layout; swap_chain_container.swap_chain_images.len()];
-
+        material: vk::DescriptorBufferInfo,
+    ) -> Result<vk::DescriptorSet> {
         let descriptor_alloc_info = vk::DescriptorSetAllocateInfo {
             descriptor_pool,
-            descriptor_set_count: u32::try_from(layouts.len())?,
-            p_set_layouts: layouts.as_ptr(),
+            descriptor_set_count: 1,
+            p_set_layouts: &descriptor_layout.layout,
             ..Default::default()
         };
 
         let descriptor_sets =
             unsafe { logical_device.allocate_descriptor_sets(&descriptor_alloc_info)? };
+        let descriptor_set = *descriptor_sets
+            .first()
+            .ok_or(VulkanError::DescriptorSetNotAvailable(0))?;
+
+        let descriptor_sampler_info = vk::DescriptorImageInfo {
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            image_view: texture_image.view,
+            sampler: texture_image.sampler,
+        };
 
-        if uniform_buffers.len() < swap_chain_container.swap_chain_images.len() {
-            return Err(VulkanError::UniformBufferNotAvailable(
-                uniform_buffers.len(),
-            ));
-        }
-
-        for buf_idx in 0..swap_chain_container.swap_chain_images.len() {
-            let descriptor_buffer_info = vk::DescriptorBufferInfo {
-                offset: 0,
-                range: u64::try_from(uniform_spec.uniform_buffer_size())?,
-                buffer: uniform_buffers[buf_idx].buffer,
-            };
-
-            let descriptor_sampler_info = vk::DescriptorImageInfo {
-                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                image_view: texture_image.view,
-                sampler: texture_image.sampler,
-            };
-
-            if buf_idx >= descriptor_sets.len() {
-                return Err(VulkanError::DescriptorSetNotAvailable(buf_idx));
-            }
-
-            let descriptor_write_info = [
-                vk::WriteDescriptorSet {
-                    dst_set: descriptor_sets[buf_idx],
-                    dst_binding: 0,
-                    dst_array_element: 0,
-                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                    descriptor_count: 1,
-                    p_buffer_info: &descriptor_buffer_info,
-                    p_image_info: ptr::null(),
-                    p_texel_buffer_view: ptr::null(),
-                    ..Default::default()
+        // every sampler of the shaders samples the one texture of the render command
+        let max_descriptor_count = descriptor_layout
+            .bindings
+            .iter()
+            .map(|binding| binding.descriptor_count)
+            .max()
+            .unwrap_or(0);
+        let descriptor_sampler_infos =
+            vec![descriptor_sampler_info; usize::try_from(max_descriptor_count)?];
+
+        let descriptor_write_info: Vec<_> = descriptor_layout
+            .bindings
+            .iter()
+            .map(|binding| vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: binding.binding,
+                dst_array_element: 0,
+                descriptor_type: binding.descriptor_type,
+                descriptor_count: binding.descriptor_count,
+                // the reflection only lets set 1 declare samplers and single uniform buffers
+                p_buffer_info: if binding.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER {
+                    &material
+                } else {
+                    ptr::null()
                 },
-                vk::WriteDescriptorSet {
-                    dst_set: descriptor_sets[buf_idx],
-                    dst_binding: 1,
-                    dst_array_element: 0,
-                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                    descriptor_count: 1,
-                    p_buffer_info: ptr::null(),
-                    p_image_info: &descriptor_sampler_info,
-                    p_texel_buffer_view: ptr::null(),
-                    ..Default::default()
+                p_image_info: if binding.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER {
+                    ptr::null()
+                } else {
+                    descriptor_sampler_infos.as_ptr()
                 },
-            ];
+                p_texel_buffer_view: ptr::null(),
+                ..Default::default()
+            })
+            .collect();
 
-            unsafe {
-                logical_device.update_descriptor_sets(&descriptor_write_info, &[]);
-            }
+        unsafe {
+            logical_device.update_descriptor_sets(&descriptor_write_info, &[]);
         }
 
-        Ok(descriptor_sets)
+        Ok(descriptor_set)
+    }
+}
+impl TextureDescriptorLayout {
+    /// Creates the layout of set 1 from the `bindings` which were reflected from the shaders of a pipeline.
+    pub fn new(bindings: Vec<ReflectedBinding>, logical_device: &ash::Device) -> Result<Self> {
+        let layout_bindings: Vec<_> = bindings
+            .iter()
+            .map(|binding| vk::DescriptorSetLayoutBinding {
+                binding: binding.binding,
+                descriptor_type: binding.descriptor_type,
+                descriptor_count: binding.descriptor_count,
+                stage_flags: binding.stage_flags,
+                ..Default::default()
+            })
+            .collect();
+        let layout = create_descriptor_set_layout(&layout_bindings, logical_device)?;
+
+        Ok(Self { layout, bindings })
+    }
+
+    /// # Safety
+    ///
+    /// The layout must not be used by any pipeline or descriptor set that is still alive.
+    pub unsafe fn destroy(&self, logical_device: &ash::Device) {
+        logical_device.destroy_descriptor_set_layout(self.layout, None);
     }
 }
 //////////////////////// Fns ///////////////////////
-pub fn create_descriptor_set_layout(
+/// The layout of set 0, which binds the uniform data of a render command as a slice of the uniform ring buffer.
+pub fn create_uniform_descriptor_set_layout(
     logical_device: &ash::Device,
 ) -> Result<vk::DescriptorSetLayout> {
     let ubo_layout_binding = vk::DescriptorSetLayoutBinding {
         binding: 0,
-        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
         descriptor_count: 1,
-        stage_flags: vk::ShaderStageFlags::VERTEX,
+        // the set is shared by all pipelines, so it is visible to every stage that may read the uniform data
+        stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
         ..Default::default()
     };
 
-    let sampler_layout_binding = vk::DescriptorSetLayoutBinding {
-        binding: 1,
-        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-        descriptor_count: 1,
-        stage_flags: vk::ShaderStageFlags::FRAGMENT,
-        ..Default::default()
-    };
-
-    let bindings = [ubo_layout_binding, sampler_layout_binding];
+    create_descriptor_set_layout(&[ubo_layout_binding], logical_device)
+}
 
+fn create_descriptor_set_layout(
+    bindings: &[vk::DescriptorSetLayoutBinding],
+    logical_device: &ash::Device,
+) -> Result<vk::DescriptorSetLayout> {
     let descriptor_layout_info = vk::DescriptorSetLayoutCreateInfo {
         binding_count: u32::try_from(bindings.len())?,
         p_bindings: bindings.as_ptr(),
@@ -179,4 +207,4 @@ pub fn create_descriptor_set_layout(
         unsafe { logical_device.create_descriptor_set_layout(&descriptor_layout_info, None)? };
 
     Ok(descriptor_layout)
-}
\ No newline at end of file
+}
@@ -45,6 +45,11 @@ pub fn required_extension_names() -> Vec<*const i8> {
     ]
 }
 
+// when rendering headless we don't create a surface so we only need the debug extension
+pub fn headless_extension_names() -> Vec<*const i8> {
+    vec![DebugUtils::name().as_ptr()]
+}
+
 // surfaces --------------------------------------------------------------------------
 pub struct SurfaceContainer {
     pub surface_loader: ash::extensions::khr::Surface,
@@ -1,6 +1,6 @@
 use ash::{vk};
 use mimic_common::{propagate, result::MimicCommonError};
-use std::{ffi::OsString, num::TryFromIntError, str::Utf8Error};
+use std::{ffi::OsString, num::TryFromIntError, path::PathBuf, str::Utf8Error};
 use thiserror::Error;
 use tobj::LoadError;
 
@@ -8,12 +8,29 @@ pub type Result<T> = std::result::Result<T, VulkanError>;
 
 #[derive(Error, Debug)]
 pub enum VulkanError {
+    // frame capture
+    #[error("Failed to capture frame because no frame was rendered yet")]
+    CaptureNoFrameRendered,
+    #[error("Failed to capture frame. The image format {0:?} cannot be converted to RGBA8")]
+    CaptureFormatUnsupported(vk::Format),
+    #[error("Failed to save captured frame to: {file:?}. Reason: {source:?}")]
+    CaptureSaveFailure {
+        source: image::ImageError,
+        file: PathBuf,
+    },
+    #[error("Failed to capture frame: {0}")]
+    CaptureUnsupported(String),
     #[error("Failed to find command buffer with index: {0}")]
     CommandBufferNotAvailable(usize),
+    // frames in flight
+    #[error("At least one frame has to be in flight, but {0} were requested")]
+    FramesInFlightInvalid(usize),
     // depth
     #[error("Failed to find supported format")]
     DepthFailedToFindSupportedFormat,
     // descriptors
+    #[error("All {0} textures of the bindless texture array are in use")]
+    BindlessTexturesFull(u32),
     #[error("Incorrect number of descriptors sets: {0}")]
     DescriptorSetNotAvailable(usize),
     #[error("Image layout transition not supported: {0}")]
@@ -25,6 +42,8 @@ pub enum VulkanError {
     // memory
     #[error("Failed to find suitable memory type")]
     MemoryFailedToFindType,
+    #[error("Tried to access memory on the host which is not host visible")]
+    MemoryNotHostVisible,
     // propagating common errors
     #[error(transparent)]
     MimicCommonError(MimicCommonError),
@@ -33,6 +52,20 @@ pub enum VulkanError {
     NoValidationLayers,
     #[error("Failed to create physical device. No GPU with supported functions")]
     PhysicalDeviceNoGpu,
+    // gltf
+    #[error("Failed to load primitive of gltf model: {file:?}. Reason: {reason}")]
+    GltfPrimitiveUnsupported { file: PathBuf, reason: String },
+    // meshes
+    #[error("Malformed mesh in {file:?}. Reason: {reason}")]
+    MeshMalformed { file: PathBuf, reason: String },
+    #[error("The mesh has no part named: {0}")]
+    MeshPartNotFound(String),
+    // instancing
+    #[error("An instanced render command needs at least one instance")]
+    InstancesEmpty,
+    // push constants
+    #[error("Push constants of {0} bytes don't fit into the push constant range of the pipeline")]
+    PushConstantsTooLarge(usize),
     // queues
     #[error("Failed to create queue indices")]
     QueueCreationFailed,
@@ -43,28 +76,58 @@ pub enum VulkanError {
     // Pipeline
     #[error("Failed to create graphics pipeline")]
     PipelineCreateError,
+    #[error("Unsupported pipeline descriptor: {0}")]
+    PipelineDescriptorUnsupported(String),
+    #[error("No graphics pipeline was available")]
+    PipelineNotAvailable,
+    #[error("Failed to save pipeline cache to: {file:?}. Reason: {source:?}")]
+    PipelineCacheSaveFailure {
+        source: std::io::Error,
+        file: PathBuf,
+    },
     // validation
     #[error("Not all required validation layers are supported")]
     RequiredValidationLayersUnsupported,
     // render commands
     #[error("No render command was available")]
     RenderCommandNotAvailable,
+    // render graph
+    #[error("Invalid render graph: {0}")]
+    RenderGraphInvalid(String),
     // shaders
     #[error("Failed to read shader: {shader_file:?}. Reason: {source:?}")]
     ShaderFileReadFailure {
         source: std::io::Error,
         shader_file: OsString,
     },
+    #[error("Shader {shader_file:?} doesn't match the pipeline. Reason: {reason}")]
+    ShaderInterfaceMismatch { shader_file: OsString, reason: String },
+    #[error("Failed to reflect shader: {shader_file:?}. Reason: {reason}")]
+    ShaderReflectionFailure { shader_file: OsString, reason: String },
     // swap chain errors
     #[error("Failed to choose a swap chain format")]
     SwapChainFormatsError,
     #[error("Failed to choose a swap extent")]
     SwapExtentFailedToGetCurrentMonitor,
+    // textures
+    #[error("Failed to read texture: {file:?}. Reason: {source:?}")]
+    TextureFileReadFailure {
+        source: std::io::Error,
+        file: PathBuf,
+    },
+    #[error("Unsupported texture format in {file:?}: {reason}")]
+    TextureFormatUnsupported { file: PathBuf, reason: String },
+    #[error("Malformed texture in {file:?}. Reason: {reason}")]
+    TextureMalformed { file: PathBuf, reason: String },
     // uniform buffer errors
-    #[error("No uniform buffer for swap chain image with index {0}")]
+    #[error("No uniform buffer for frame in flight with index {0}")]
     UniformBufferNotAvailable(usize),
+    #[error("Uniform data of {0} bytes doesn't fit into the uniform range of a render command")]
+    UniformBufferTooLarge(usize),
     // fallback errors
     #[error(transparent)]
+    GltfError(gltf::Error),
+    #[error(transparent)]
     ObjError(LoadError),
     #[error(transparent)]
     OtherVkResult(vk::Result),
@@ -72,12 +135,16 @@ pub enum VulkanError {
     VulkanStringConversionError(Utf8Error),
     #[error(transparent)]
     VulkanUsizeConversionError(TryFromIntError),
+    // vertex layout
+    #[error("Invalid vertex layout: {0}")]
+    VertexLayoutInvalid(String),
     #[error("Failed to create a window")]
     WindowCreateFailure,
     #[error("The platform surface stored in window is incorrect")]
     WindowIncorrectPlatformSurface,
 }
 
+propagate!(VulkanError, GltfError as gltf::Error, using_panic_feature);
 propagate!(VulkanError, ObjError as LoadError, using_panic_feature);
 propagate!(
     VulkanError,